@@ -0,0 +1,433 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::{Mutex, RwLock};
+use std::time::SystemTime;
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpMessage, HttpResponse};
+use futures_util::StreamExt;
+use hmac::{Hmac, Mac};
+use serde_json::json;
+use sha2::Sha256;
+
+use crate::handlers::AppState;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Signed requests older (or newer) than this are rejected
+const MAX_CLOCK_SKEW_SECS: i64 = 300;
+
+/// Largest body read for signature verification (matches `/bounces`)
+const MAX_SIGNED_BODY_BYTES: usize = crate::handlers::MAX_BOUNCE_BYTES;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Scope {
+    /// Queue and preview email, cancel jobs
+    Send,
+    /// Job status, statistics, template and suppression listings
+    Read,
+    /// Post bounce and complaint reports to `/bounces` (for an MTA pipe)
+    Bounces,
+    /// Everything, including template and suppression management
+    Admin,
+}
+
+impl std::str::FromStr for Scope {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "send" => Ok(Scope::Send),
+            "read" => Ok(Scope::Read),
+            "bounces" => Ok(Scope::Bounces),
+            "admin" => Ok(Scope::Admin),
+            other => Err(anyhow::anyhow!("Unknown API scope: {}", other)),
+        }
+    }
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Send => "send",
+            Scope::Read => "read",
+            Scope::Bounces => "bounces",
+            Scope::Admin => "admin",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ApiKey {
+    pub id: String,
+    secret: String,
+    scopes: HashSet<Scope>,
+}
+
+impl ApiKey {
+    pub fn new<'a>(id: &str, secret: &str, scopes: impl IntoIterator<Item = &'a str>) -> Result<Self, anyhow::Error> {
+        if id.is_empty() || secret.len() < 16 {
+            anyhow::bail!("API key '{}' needs an ID and a secret of at least 16 characters", id);
+        }
+        Ok(Self {
+            id: id.to_string(),
+            secret: secret.to_string(),
+            scopes: scopes.into_iter().map(str::parse).collect::<Result<_, _>>()?,
+        })
+    }
+
+    fn allows(&self, scope: Scope) -> bool {
+        self.scopes.contains(&Scope::Admin) || self.scopes.contains(&scope)
+    }
+}
+
+/// Parse `id:secret:scope+scope` entries separated by commas or newlines;
+/// blank lines and `#` comments are ignored
+pub fn parse_keys(input: &str) -> Result<Vec<ApiKey>, anyhow::Error> {
+    input
+        .split([',', '\n'])
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty() && !entry.starts_with('#'))
+        .map(|entry| {
+            let parts: Vec<&str> = entry.splitn(3, ':').collect();
+            let [id, secret, scopes] = parts[..] else {
+                anyhow::bail!("Invalid API key entry, expected id:secret:scopes");
+            };
+            ApiKey::new(id, secret, scopes.split('+'))
+        })
+        .collect()
+}
+
+/// API keys accepted by the mailer
+///
+/// Keys come from the environment and, optionally, a file that is re-read
+/// when it changes. Several keys can be valid at once, so a key is rotated by
+/// adding the new one, moving clients over and then removing the old one,
+/// without restarting the mailer.
+///
+/// Whether authentication is required is decided once, at startup: it is as
+/// soon as any key or a key file is configured. A reload that leaves no keys
+/// then rejects every request rather than opening the API.
+pub struct KeyRing {
+    env_keys: Vec<ApiKey>,
+    file: Option<PathBuf>,
+    required: bool,
+    keys: RwLock<Vec<ApiKey>>,
+    file_modified: Mutex<Option<SystemTime>>,
+}
+
+impl KeyRing {
+    pub fn new(env_keys: Vec<ApiKey>, file: Option<PathBuf>) -> Result<Self, anyhow::Error> {
+        let ring = Self {
+            keys: RwLock::new(env_keys.clone()),
+            required: !env_keys.is_empty() || file.is_some(),
+            env_keys,
+            file,
+            file_modified: Mutex::new(None),
+        };
+        ring.reload_if_changed()?;
+        Ok(ring)
+    }
+
+    /// Authentication is off only when no keys were configured at startup
+    pub fn is_required(&self) -> bool {
+        self.required
+    }
+
+    /// Re-read the key file if it was modified; returns whether keys changed
+    pub fn reload_if_changed(&self) -> Result<bool, anyhow::Error> {
+        let Some(file) = &self.file else {
+            return Ok(false);
+        };
+
+        let modified = std::fs::metadata(file)?.modified()?;
+        let mut last_modified = self.file_modified.lock().unwrap_or_else(|e| e.into_inner());
+        if *last_modified == Some(modified) {
+            return Ok(false);
+        }
+
+        let file_keys = parse_keys(&std::fs::read_to_string(file)?)?;
+        let mut keys = self.env_keys.clone();
+        keys.extend(file_keys);
+
+        let ids: Vec<&str> = keys.iter().map(|k| k.id.as_str()).collect();
+        log::info!("🔑 Loaded {} API keys: {}", keys.len(), ids.join(", "));
+
+        *self.keys.write().unwrap_or_else(|e| e.into_inner()) = keys;
+        *last_modified = Some(modified);
+
+        Ok(true)
+    }
+
+    /// Key for a bearer token
+    fn by_secret(&self, token: &str) -> Option<ApiKey> {
+        let keys = self.keys.read().unwrap_or_else(|e| e.into_inner());
+        keys.iter().find(|k| constant_time_eq(k.secret.as_bytes(), token.as_bytes())).cloned()
+    }
+
+    fn by_id(&self, id: &str) -> Option<ApiKey> {
+        let keys = self.keys.read().unwrap_or_else(|e| e.into_inner());
+        keys.iter().find(|k| k.id == id).cloned()
+    }
+}
+
+/// Scope needed for a route, `None` for public routes
+///
+/// Tracking links are opened by mail clients and `/health` by orchestrators,
+/// so they stay unauthenticated.
+pub fn required_scope(method: &Method, path: &str) -> Option<Scope> {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    match (method, segments.as_slice()) {
+        (_, ["health", ..]) | (_, ["track", ..]) => None,
+        (&Method::POST, ["send", ..] | ["preview"] | ["job", _, "cancel"]) => Some(Scope::Send),
+        (&Method::GET, ["stats", ..] | ["metrics"] | ["job", ..] | ["jobs"] | ["webhooks", ..] | ["templates", ..] | ["suppressions", ..]) => Some(Scope::Read),
+        // Previews render but don't change anything
+        (&Method::POST, ["templates", _, "preview"]) => Some(Scope::Read),
+        (&Method::POST, ["bounces"]) => Some(Scope::Bounces),
+        _ => Some(Scope::Admin),
+    }
+}
+
+/// Actix middleware enforcing API keys
+///
+/// Clients authenticate with `Authorization: Bearer <secret>`, or sign the
+/// request with `X-Mailer-Key: <id>`, `X-Mailer-Timestamp: <unix seconds>`
+/// and `X-Mailer-Signature: sha256=<hex>`, an HMAC-SHA256 keyed with the
+/// secret over `"{timestamp}.{METHOD}.{path and query}.{body}"`.
+pub async fn require_api_key(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let Some(scope) = required_scope(req.method(), req.path()) else {
+        return next.call(req).await.map(ServiceResponse::map_into_left_body);
+    };
+    // Without the key ring nothing can be checked; never fail open
+    let Some(state) = req.app_data::<web::Data<AppState>>().cloned() else {
+        log::error!("🔒 No application state for {} {}, refusing the request", req.method(), req.path());
+        let response = HttpResponse::InternalServerError().json(json!({ "error": "Authentication is unavailable" }));
+        return Ok(req.into_response(response).map_into_right_body());
+    };
+    if !state.auth.is_required() {
+        return next.call(req).await.map(ServiceResponse::map_into_left_body);
+    }
+
+    let key = match authenticate(&state.auth, &mut req).await {
+        Ok(key) => key,
+        Err(message) => {
+            log::warn!("🔒 Rejected {} {}: {}", req.method(), req.path(), message);
+            let response = HttpResponse::Unauthorized().json(json!({ "error": message }));
+            return Ok(req.into_response(response).map_into_right_body());
+        }
+    };
+
+    if !key.allows(scope) {
+        log::warn!("🔒 Key {} lacks scope {} for {} {}", key.id, scope.as_str(), req.method(), req.path());
+        let response = HttpResponse::Forbidden().json(json!({
+            "error": format!("API key lacks the '{}' scope", scope.as_str())
+        }));
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+
+    next.call(req).await.map(ServiceResponse::map_into_left_body)
+}
+
+async fn authenticate(ring: &KeyRing, req: &mut ServiceRequest) -> Result<ApiKey, String> {
+    if let Some(token) = req.headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+    {
+        return ring.by_secret(token.trim()).ok_or_else(|| "Invalid API key".to_string());
+    }
+
+    let header = |name: &str| req.headers().get(name).and_then(|v| v.to_str().ok()).map(str::to_string);
+    let (Some(key_id), Some(timestamp), Some(signature)) = (
+        header("X-Mailer-Key"),
+        header("X-Mailer-Timestamp"),
+        header("X-Mailer-Signature"),
+    ) else {
+        return Err("Missing API key or request signature".to_string());
+    };
+
+    let sent_at: i64 = timestamp.parse().map_err(|_| "Invalid X-Mailer-Timestamp".to_string())?;
+    if (chrono::Utc::now().timestamp() - sent_at).abs() > MAX_CLOCK_SKEW_SECS {
+        return Err("Request timestamp outside the allowed window".to_string());
+    }
+
+    let key = ring.by_id(&key_id).ok_or_else(|| "Unknown API key".to_string())?;
+    let signature = signature
+        .strip_prefix("sha256=")
+        .and_then(decode_hex)
+        .ok_or_else(|| "Malformed X-Mailer-Signature".to_string())?;
+
+    // The body is consumed to verify it and handed back to the handler
+    let body = read_body(req).await?;
+    let path = req.uri().path_and_query().map(|p| p.as_str()).unwrap_or("/").to_string();
+
+    let mut mac = HmacSha256::new_from_slice(key.secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(format!("{}.{}.{}.", timestamp, req.method(), path).as_bytes());
+    mac.update(&body);
+    req.set_payload(Payload::from(body));
+
+    mac.verify_slice(&signature).map_err(|_| "Invalid request signature".to_string())?;
+
+    Ok(key)
+}
+
+async fn read_body(req: &mut ServiceRequest) -> Result<web::Bytes, String> {
+    let mut payload = req.take_payload();
+    let mut body = web::BytesMut::new();
+
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(|e| format!("Failed to read request body: {}", e))?;
+        if body.len() + chunk.len() > MAX_SIGNED_BODY_BYTES {
+            return Err("Request body too large".to_string());
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    Ok(body.freeze())
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    const SECRET: &str = "0123456789abcdef-secret";
+
+    fn ring() -> KeyRing {
+        KeyRing::new(parse_keys(&format!("server:{}:send+read", SECRET)).unwrap(), None).unwrap()
+    }
+
+    fn signature(timestamp: &str, method: &str, path: &str, body: &str) -> String {
+        let mut mac = HmacSha256::new_from_slice(SECRET.as_bytes()).unwrap();
+        mac.update(format!("{}.{}.{}.{}", timestamp, method, path, body).as_bytes());
+        mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn test_required_scope() {
+        assert_eq!(required_scope(&Method::GET, "/health/ready"), None);
+        assert_eq!(required_scope(&Method::GET, "/track/open/abc"), None);
+        assert_eq!(required_scope(&Method::POST, "/send/otp"), Some(Scope::Send));
+        assert_eq!(required_scope(&Method::POST, "/job/123/cancel"), Some(Scope::Send));
+        assert_eq!(required_scope(&Method::GET, "/jobs"), Some(Scope::Read));
+        assert_eq!(required_scope(&Method::GET, "/job/123/"), Some(Scope::Read));
+        assert_eq!(required_scope(&Method::POST, "/templates/welcome/preview"), Some(Scope::Read));
+        assert_eq!(required_scope(&Method::POST, "/bounces"), Some(Scope::Bounces));
+        assert_eq!(required_scope(&Method::POST, "/templates/welcome"), Some(Scope::Admin));
+        assert_eq!(required_scope(&Method::DELETE, "/suppressions/a@b.test"), Some(Scope::Admin));
+        assert_eq!(required_scope(&Method::GET, "/bounces"), Some(Scope::Admin));
+    }
+
+    #[test]
+    fn test_parse_keys() {
+        let keys = parse_keys("# rotated 2026-10\nold:0123456789abcdef:admin\n\n new:fedcba9876543210:send+bounces ,").unwrap();
+        assert_eq!(keys.iter().map(|k| k.id.as_str()).collect::<Vec<_>>(), vec!["old", "new"]);
+        assert!(keys[0].allows(Scope::Bounces));
+        assert!(keys[1].allows(Scope::Bounces) && keys[1].allows(Scope::Send) && !keys[1].allows(Scope::Read));
+
+        assert!(parse_keys("id:short:send").is_err());
+        assert!(parse_keys("id:0123456789abcdef").is_err());
+        assert!(parse_keys("id:0123456789abcdef:superuser").is_err());
+        assert!(parse_keys("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_decode_hex() {
+        assert_eq!(decode_hex("00ff10Ab"), Some(vec![0x00, 0xff, 0x10, 0xab]));
+        assert_eq!(decode_hex(""), Some(vec![]));
+        assert_eq!(decode_hex("abc"), None);
+        assert_eq!(decode_hex("zz"), None);
+        assert_eq!(decode_hex("é1"), None);
+    }
+
+    #[actix_web::test]
+    async fn test_authenticate_bearer() {
+        let ring = ring();
+        let mut req = TestRequest::get()
+            .uri("/jobs")
+            .insert_header((AUTHORIZATION, format!("Bearer {}", SECRET)))
+            .to_srv_request();
+        assert_eq!(authenticate(&ring, &mut req).await.unwrap().id, "server");
+
+        let mut req = TestRequest::get().uri("/jobs").insert_header((AUTHORIZATION, "Bearer wrong")).to_srv_request();
+        assert_eq!(authenticate(&ring, &mut req).await.unwrap_err(), "Invalid API key");
+
+        let mut req = TestRequest::get().uri("/jobs").to_srv_request();
+        assert_eq!(authenticate(&ring, &mut req).await.unwrap_err(), "Missing API key or request signature");
+    }
+
+    #[actix_web::test]
+    async fn test_authenticate_signed() {
+        let ring = ring();
+        let body = r#"{"to":"a@b.test"}"#;
+        let timestamp = chrono::Utc::now().timestamp().to_string();
+        let signed = |timestamp: &str, signature: &str, body: &'static str| {
+            TestRequest::post()
+                .uri("/send/otp?x=1")
+                .insert_header(("X-Mailer-Key", "server"))
+                .insert_header(("X-Mailer-Timestamp", timestamp.to_string()))
+                .insert_header(("X-Mailer-Signature", format!("sha256={}", signature)))
+                .set_payload(body)
+                .to_srv_request()
+        };
+
+        let valid = signature(&timestamp, "POST", "/send/otp?x=1", body);
+        let mut req = signed(&timestamp, &valid, r#"{"to":"a@b.test"}"#);
+        assert_eq!(authenticate(&ring, &mut req).await.unwrap().id, "server");
+        // The body is handed back for the handler
+        let payload = read_body(&mut req).await.unwrap();
+        assert_eq!(payload, body.as_bytes());
+
+        let mut req = signed(&timestamp, &valid, r#"{"to":"evil@b.test"}"#);
+        assert_eq!(authenticate(&ring, &mut req).await.unwrap_err(), "Invalid request signature");
+
+        let stale = (chrono::Utc::now().timestamp() - MAX_CLOCK_SKEW_SECS - 10).to_string();
+        let mut req = signed(&stale, &signature(&stale, "POST", "/send/otp?x=1", body), r#"{"to":"a@b.test"}"#);
+        assert_eq!(authenticate(&ring, &mut req).await.unwrap_err(), "Request timestamp outside the allowed window");
+
+        let mut req = signed(&timestamp, "not-hex", r#"{"to":"a@b.test"}"#);
+        assert_eq!(authenticate(&ring, &mut req).await.unwrap_err(), "Malformed X-Mailer-Signature");
+    }
+
+    #[test]
+    fn test_auth_stays_required_when_keys_are_removed() {
+        let path = std::env::temp_dir().join(format!("mailer-keys-{}", std::process::id()));
+        std::fs::write(&path, format!("server:{}:send", SECRET)).unwrap();
+        let ring = KeyRing::new(Vec::new(), Some(path.clone())).unwrap();
+        assert!(ring.is_required());
+        assert!(ring.by_secret(SECRET).is_some());
+
+        std::fs::write(&path, "# all keys revoked\n").unwrap();
+        let later = SystemTime::now() + std::time::Duration::from_secs(5);
+        std::fs::File::options().write(true).open(&path).unwrap().set_modified(later).unwrap();
+        assert!(ring.reload_if_changed().unwrap());
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(ring.is_required());
+        assert!(ring.by_secret(SECRET).is_none());
+
+        assert!(!KeyRing::new(Vec::new(), None).unwrap().is_required());
+    }
+}
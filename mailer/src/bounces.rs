@@ -0,0 +1,547 @@
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use chrono::Utc;
+use mail_parser::mailbox::mbox::MessageIterator;
+use mail_parser::{Message, MessageParser, MimeHeaders, PartType};
+use redis::{AsyncCommands, Client as RedisClient};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use crate::logging;
+use crate::models::{BounceKind, BounceReport, EmailStatus, Suppression};
+use crate::suppressions::SuppressionList;
+use crate::webhooks::WebhookDispatcher;
+use crate::EmailQueue;
+
+/// Headers of the bounce itself that may carry the VERP envelope recipient
+const VERP_HEADERS: &[&str] = &["To", "Delivered-To", "X-Original-To", "Envelope-To"];
+
+/// Hash of mbox path -> byte offset read up to
+const MBOX_OFFSETS_KEY: &str = "mailer:bounce_mbox_offsets";
+
+/// A DSN or ARF report before it is matched against a job
+#[derive(Debug)]
+pub struct ParsedReport {
+    pub kind: BounceKind,
+    pub job_id: Option<String>,
+    pub recipient: Option<String>,
+    pub status_code: Option<String>,
+    pub diagnostic: Option<String>,
+}
+
+/// Parse a raw DSN (RFC 3464) or ARF (RFC 5965) message
+///
+/// Returns `None` for messages that aren't failure reports, such as
+/// "delayed" or "delivered" DSNs and ordinary mail.
+pub fn parse_report(raw: &[u8]) -> Option<ParsedReport> {
+    let message = MessageParser::default().parse(raw)?;
+    let job_id = verp_job_id(&message).or_else(|| original_message_job_id(&message));
+
+    let report_type = message
+        .content_type()
+        .filter(|ct| ct.ctype().eq_ignore_ascii_case("multipart") && ct.subtype().is_some_and(|s| s.eq_ignore_ascii_case("report")))
+        .and_then(|ct| ct.attribute("report-type"))
+        .map(str::to_ascii_lowercase);
+
+    match report_type.as_deref() {
+        Some("feedback-report") => {
+            let fields = part_fields(&message, &["feedback-report"]);
+            Some(ParsedReport {
+                kind: BounceKind::Complaint,
+                job_id,
+                recipient: field_in(&fields, "original-rcpt-to").map(strip_address_type),
+                status_code: None,
+                diagnostic: field_in(&fields, "feedback-type").map(|t| format!("feedback-type: {}", t)),
+            })
+        }
+        Some("delivery-status") => {
+            let fields = part_fields(&message, &["delivery-status", "global-delivery-status"]);
+            // Per-recipient blocks follow the per-message block; only failures count
+            let failed = fields
+                .split(|(name, _)| name.is_empty())
+                .find(|block| field_in(block, "action").is_some_and(|a| a.eq_ignore_ascii_case("failed")))?;
+
+            let status_code = field_in(failed, "status").map(str::to_string);
+            Some(ParsedReport {
+                kind: classify(status_code.as_deref()),
+                job_id,
+                recipient: field_in(failed, "final-recipient")
+                    .or_else(|| field_in(failed, "original-recipient"))
+                    .map(strip_address_type),
+                status_code,
+                diagnostic: field_in(failed, "diagnostic-code").map(strip_address_type),
+            })
+        }
+        _ => {
+            // Non-standard bounces: only trust them when they reference one of
+            // our jobs and carry an enhanced status code
+            let job_id = job_id?;
+            let text = message.body_text(0)?;
+            let status_code = find_status_code(&text)?;
+            Some(ParsedReport {
+                kind: classify(Some(&status_code)),
+                job_id: Some(job_id),
+                recipient: None,
+                status_code: Some(status_code),
+                diagnostic: message.subject().map(str::to_string),
+            })
+        }
+    }
+}
+
+/// Record a report: mark the job as bounced, suppress its recipient on hard
+/// bounces and complaints and send a `bounced` webhook
+///
+/// Only reports that resolve to one of our jobs (through the VERP address or
+/// the returned Message-ID) are acted on, and it is the job's recipient that
+/// gets suppressed, never the address the report names; anyone can send us
+/// a well-formed report. Returns `None` for messages that aren't failure
+/// reports or match no job.
+pub async fn ingest(
+    queue: &EmailQueue,
+    suppressions: &SuppressionList,
+    webhooks: Option<&WebhookDispatcher>,
+    raw: &[u8],
+) -> Result<Option<BounceReport>, anyhow::Error> {
+    let Some(parsed) = parse_report(raw) else {
+        return Ok(None);
+    };
+
+    let job = match &parsed.job_id {
+        Some(job_id) => queue.get_job(job_id).await?,
+        None => None,
+    };
+    let Some(job) = job else {
+        log::warn!(
+            "↩️ Dropping {} report that matches no job (job ID: {})",
+            parsed.kind.as_str(),
+            parsed.job_id.as_deref().unwrap_or("none"),
+        );
+        return Ok(None);
+    };
+    let reason = parsed.diagnostic.clone()
+        .or_else(|| parsed.status_code.clone())
+        .unwrap_or_else(|| "Bounced".to_string());
+
+    // Duplicate reports for an already bounced job are not re-announced
+    if parsed.kind != BounceKind::Complaint && job.status == EmailStatus::Sent {
+        log::warn!("↩️ Email job {} bounced: {}", job.id, logging::redact(&reason));
+        queue.finish(&job.id, EmailStatus::Bounced, &reason).await?;
+        if let Some(webhooks) = webhooks {
+            webhooks.notify(&job, EmailStatus::Bounced, Some(&reason)).await?;
+        }
+    }
+
+    let suppressed = match parsed.kind {
+        BounceKind::Hard | BounceKind::Complaint => {
+            suppressions.add(Suppression {
+                email: job.to.clone(),
+                reason: parsed.kind.clone(),
+                job_id: Some(job.id.clone()),
+                diagnostic: parsed.diagnostic.clone(),
+                created_at: Utc::now(),
+            }).await?;
+            true
+        }
+        BounceKind::Soft => false,
+    };
+
+    Ok(Some(BounceReport {
+        kind: parsed.kind,
+        job_id: Some(job.id),
+        recipient: Some(job.to),
+        status_code: parsed.status_code,
+        diagnostic: parsed.diagnostic,
+        suppressed,
+    }))
+}
+
+/// Reads bounces appended to a local mbox file (e.g. the mailbox the bounce
+/// address is delivered to, or one filled by fetchmail from IMAP)
+///
+/// The read position is stored in Redis once the messages read up to it have
+/// been ingested, so a restart picks up where the last run left off instead
+/// of re-reading (and re-suppressing) the whole mailbox.
+pub struct MboxPoller {
+    redis: Arc<Mutex<redis::aio::MultiplexedConnection>>,
+    path: PathBuf,
+    offset: u64,
+    last_len: u64,
+}
+
+impl MboxPoller {
+    pub async fn new(redis_url: &str, path: impl Into<PathBuf>) -> Result<Self, anyhow::Error> {
+        let path = path.into();
+        let client = RedisClient::open(redis_url)?;
+        let mut conn = client.get_multiplexed_async_connection().await?;
+        let offset: Option<u64> = conn.hget(MBOX_OFFSETS_KEY, path.to_string_lossy().as_ref()).await?;
+
+        Ok(Self {
+            redis: Arc::new(Mutex::new(conn)),
+            path,
+            offset: offset.unwrap_or(0),
+            last_len: 0,
+        })
+    }
+
+    /// Mark the mailbox read up to the end of `message` once it is ingested
+    pub fn consumed(&mut self, message: &MboxMessage) {
+        self.offset = self.offset.max(message.end);
+    }
+
+    /// Store the position of the last consumed message
+    pub async fn commit(&self) -> Result<(), anyhow::Error> {
+        let mut conn = self.redis.lock().await;
+        let _: () = conn.hset(MBOX_OFFSETS_KEY, self.path.to_string_lossy().as_ref(), self.offset).await?;
+        Ok(())
+    }
+
+    /// Messages appended since the last consumed one
+    ///
+    /// While the file is still growing, the last message is left for the next
+    /// poll in case the MTA is in the middle of writing it. Messages that
+    /// aren't marked `consumed` are returned again by the next poll.
+    pub fn poll(&mut self) -> Result<Vec<MboxMessage>, anyhow::Error> {
+        let len = match std::fs::metadata(&self.path) {
+            Ok(metadata) => metadata.len(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        // Truncated or rotated
+        if len < self.offset {
+            self.offset = 0;
+        }
+        let stable = len == self.last_len;
+        self.last_len = len;
+        if len == self.offset {
+            return Ok(Vec::new());
+        }
+
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut buffer = Vec::with_capacity((len - self.offset) as usize);
+        file.take(len - self.offset).read_to_end(&mut buffer)?;
+
+        let complete = if stable {
+            buffer.len()
+        } else {
+            last_message_start(&buffer)
+        };
+
+        Ok(split_messages(&buffer[..complete], self.offset))
+    }
+}
+
+/// A message read from an mbox, with the file offset just past it
+pub struct MboxMessage {
+    pub raw: Vec<u8>,
+    pub end: u64,
+}
+
+/// Split mbox data read from `base` into its messages; unparseable ones are
+/// dropped, the following message's `end` covers them
+fn split_messages(buffer: &[u8], base: u64) -> Vec<MboxMessage> {
+    let mut starts: Vec<usize> = buffer
+        .windows(6)
+        .enumerate()
+        .filter(|(_, w)| *w == b"\nFrom ")
+        .map(|(i, _)| i + 1)
+        .collect();
+    starts.insert(0, 0);
+
+    starts
+        .iter()
+        .enumerate()
+        .filter_map(|(i, &start)| {
+            let end = starts.get(i + 1).copied().unwrap_or(buffer.len());
+            let message = MessageIterator::new(BufReader::new(Cursor::new(&buffer[start..end]))).next()?.ok()?;
+            Some(MboxMessage {
+                raw: message.contents().to_vec(),
+                end: base + end as u64,
+            })
+        })
+        .collect()
+}
+
+/// Offset of the last `From ` separator line, or 0 if there is only one message
+fn last_message_start(buffer: &[u8]) -> usize {
+    buffer
+        .windows(6)
+        .rposition(|w| w == b"\nFrom ")
+        .map(|i| i + 1)
+        .unwrap_or(0)
+}
+
+/// Job ID from a VERP recipient such as `bounces+{job_id}@killcode.app`
+fn verp_job_id(message: &Message) -> Option<String> {
+    VERP_HEADERS
+        .iter()
+        .filter_map(|header| message.header_raw(*header))
+        .flat_map(|value| value.split([',', '<', '>', ' ', '\t', '\r', '\n']))
+        .filter_map(|address| address.split_once('@').map(|(local, _)| local))
+        .filter_map(|local| local.rsplit_once('+').map(|(_, tag)| tag))
+        .find_map(parse_job_id)
+}
+
+/// Job ID from the `Message-ID` (`<{job_id}@domain>`) of the returned message
+fn original_message_job_id(message: &Message) -> Option<String> {
+    message.parts.iter().skip(1).find_map(|part| match &part.body {
+        PartType::Message(original) => original.message_id().and_then(message_id_job),
+        _ => part
+            .text_contents()?
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .filter(|(name, _)| name.trim().eq_ignore_ascii_case("message-id"))
+            .find_map(|(_, value)| message_id_job(value.trim())),
+    })
+}
+
+fn message_id_job(message_id: &str) -> Option<String> {
+    let message_id = message_id.trim().trim_start_matches('<');
+    parse_job_id(message_id.split('@').next()?)
+}
+
+fn parse_job_id(candidate: &str) -> Option<String> {
+    Uuid::parse_str(candidate.trim()).ok().map(|id| id.to_string())
+}
+
+/// `name: value` fields of the report part with one of the given subtypes,
+/// with an empty entry between blocks
+fn part_fields(message: &Message, subtypes: &[&str]) -> Vec<(String, String)> {
+    let Some(text) = message.parts.iter().find_map(|part| {
+        let ct = part.content_type()?;
+        let is_report = ct.ctype().eq_ignore_ascii_case("message")
+            && ct.subtype().is_some_and(|s| subtypes.iter().any(|t| s.eq_ignore_ascii_case(t)));
+        if is_report { part.text_contents() } else { None }
+    }) else {
+        return Vec::new();
+    };
+
+    let mut fields: Vec<(String, String)> = Vec::new();
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            if fields.last().is_some_and(|f| !f.0.is_empty()) {
+                fields.push((String::new(), String::new()));
+            }
+        } else if line.starts_with([' ', '\t']) {
+            // Folded continuation of the previous field
+            if let Some(last) = fields.last_mut() {
+                last.1.push(' ');
+                last.1.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            fields.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+    fields
+}
+
+fn field_in<'a>(fields: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    fields.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+}
+
+/// `rfc822; <user@example.com>` → `user@example.com`
+fn strip_address_type(value: &str) -> String {
+    let value = match value.split_once(';') {
+        Some((_, rest)) => rest,
+        None => value,
+    };
+    value.trim().trim_start_matches('<').trim_end_matches('>').to_string()
+}
+
+/// 5.x.x is permanent; anything else the relay gave up on is treated as soft
+fn classify(status_code: Option<&str>) -> BounceKind {
+    match status_code {
+        Some(code) if code.starts_with('4') => BounceKind::Soft,
+        _ => BounceKind::Hard,
+    }
+}
+
+/// First enhanced status code (`5.1.1`) in free text
+fn find_status_code(text: &str) -> Option<String> {
+    text.split(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .map(|token| token.trim_end_matches('.'))
+        .find(|token| {
+            let parts: Vec<&str> = token.split('.').collect();
+            parts.len() == 3
+                && matches!(parts[0], "4" | "5")
+                && parts[1..].iter().all(|p| !p.is_empty() && p.len() <= 3)
+        })
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JOB_ID: &str = "0b6c7a52-6a4e-4c3b-9a53-2f4d5e6f7a8b";
+
+    /// Postfix-style DSN for a message sent with a VERP return path
+    fn dsn(action: &str, status: &str) -> String {
+        format!(
+            "From: MAILER-DAEMON@mx.example.net (Mail Delivery System)\r\n\
+             To: bounces+{job}@killcode.app\r\n\
+             Subject: Undelivered Mail Returned to Sender\r\n\
+             MIME-Version: 1.0\r\n\
+             Content-Type: multipart/report; report-type=delivery-status; boundary=\"b1\"\r\n\
+             \r\n\
+             --b1\r\n\
+             Content-Type: text/plain\r\n\
+             \r\n\
+             I'm sorry to have to inform you that your message could not be delivered.\r\n\
+             \r\n\
+             --b1\r\n\
+             Content-Type: message/delivery-status\r\n\
+             \r\n\
+             Reporting-MTA: dns; mx.example.net\r\n\
+             Arrival-Date: Mon, 12 Oct 2026 10:00:00 +0000\r\n\
+             \r\n\
+             Final-Recipient: rfc822; someone@example.net\r\n\
+             Original-Recipient: rfc822;someone@example.net\r\n\
+             Action: {action}\r\n\
+             Status: {status}\r\n\
+             Diagnostic-Code: smtp; 550 {status} <someone@example.net>:\r\n\
+             \x20   Recipient address rejected: User unknown\r\n\
+             \r\n\
+             --b1\r\n\
+             Content-Type: text/rfc822-headers\r\n\
+             \r\n\
+             Message-ID: <{job}@killcode.app>\r\n\
+             Subject: Welcome\r\n\
+             \r\n\
+             --b1--\r\n",
+            job = JOB_ID,
+        )
+    }
+
+    /// ARF complaint returning the original message, without a VERP address
+    const ARF: &str = "From: feedback@isp.example\r\n\
+        To: abuse@killcode.app\r\n\
+        Subject: Complaint\r\n\
+        MIME-Version: 1.0\r\n\
+        Content-Type: multipart/report; report-type=feedback-report; boundary=\"b2\"\r\n\
+        \r\n\
+        --b2\r\n\
+        Content-Type: text/plain\r\n\
+        \r\n\
+        This is an email abuse report.\r\n\
+        \r\n\
+        --b2\r\n\
+        Content-Type: message/feedback-report\r\n\
+        \r\n\
+        Feedback-Type: abuse\r\n\
+        User-Agent: ExampleFBL/1.0\r\n\
+        Version: 1\r\n\
+        Original-Rcpt-To: <complainer@isp.example>\r\n\
+        \r\n\
+        --b2\r\n\
+        Content-Type: message/rfc822\r\n\
+        \r\n\
+        From: noreply@killcode.app\r\n\
+        To: complainer@isp.example\r\n\
+        Message-ID: <0b6c7a52-6a4e-4c3b-9a53-2f4d5e6f7a8b@killcode.app>\r\n\
+        Subject: Welcome\r\n\
+        \r\n\
+        Hello\r\n\
+        \r\n\
+        --b2--\r\n";
+
+    #[test]
+    fn test_parse_hard_bounce() {
+        let report = parse_report(dsn("failed", "5.1.1").as_bytes()).unwrap();
+        assert_eq!(report.kind, BounceKind::Hard);
+        assert_eq!(report.job_id.as_deref(), Some(JOB_ID));
+        assert_eq!(report.recipient.as_deref(), Some("someone@example.net"));
+        assert_eq!(report.status_code.as_deref(), Some("5.1.1"));
+        assert_eq!(
+            report.diagnostic.as_deref(),
+            Some("550 5.1.1 <someone@example.net>: Recipient address rejected: User unknown")
+        );
+    }
+
+    #[test]
+    fn test_parse_soft_bounce_and_non_failures() {
+        let report = parse_report(dsn("failed", "4.2.2").as_bytes()).unwrap();
+        assert_eq!(report.kind, BounceKind::Soft);
+
+        assert!(parse_report(dsn("delayed", "4.4.1").as_bytes()).is_none());
+        assert!(parse_report(dsn("delivered", "2.0.0").as_bytes()).is_none());
+        assert!(parse_report(b"From: a@b.test\r\nSubject: hi\r\n\r\nJust mail\r\n").is_none());
+    }
+
+    #[test]
+    fn test_parse_job_id_from_returned_message_id() {
+        // Without the VERP address the job comes from the returned headers
+        let without_verp = dsn("failed", "5.1.1").replace(&format!("To: bounces+{}@killcode.app", JOB_ID), "To: bounces@killcode.app");
+        let report = parse_report(without_verp.as_bytes()).unwrap();
+        assert_eq!(report.job_id.as_deref(), Some(JOB_ID));
+
+        let unknown = without_verp.replace(&format!("<{}@", JOB_ID), "<not-a-job@");
+        assert_eq!(parse_report(unknown.as_bytes()).unwrap().job_id, None);
+    }
+
+    #[test]
+    fn test_parse_complaint() {
+        let report = parse_report(ARF.as_bytes()).unwrap();
+        assert_eq!(report.kind, BounceKind::Complaint);
+        assert_eq!(report.job_id.as_deref(), Some(JOB_ID));
+        assert_eq!(report.recipient.as_deref(), Some("complainer@isp.example"));
+        assert_eq!(report.diagnostic.as_deref(), Some("feedback-type: abuse"));
+    }
+
+    #[test]
+    fn test_parse_non_standard_bounce() {
+        let raw = format!(
+            "From: postmaster@legacy.example\r\nTo: bounces+{}@killcode.app\r\nSubject: Delivery failure\r\n\r\n\
+             Your message to someone@legacy.example failed: 550 5.7.1 rejected by policy.\r\n",
+            JOB_ID,
+        );
+        let report = parse_report(raw.as_bytes()).unwrap();
+        assert_eq!(report.kind, BounceKind::Hard);
+        assert_eq!(report.job_id.as_deref(), Some(JOB_ID));
+        assert_eq!(report.status_code.as_deref(), Some("5.7.1"));
+        assert_eq!(report.recipient, None);
+
+        // Without a job reference free-text bounces are ignored
+        let anonymous = raw.replace(&format!("bounces+{}@", JOB_ID), "bounces@");
+        assert!(parse_report(anonymous.as_bytes()).is_none());
+    }
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(classify(Some("5.1.1")), BounceKind::Hard);
+        assert_eq!(classify(Some("4.2.2")), BounceKind::Soft);
+        assert_eq!(classify(None), BounceKind::Hard);
+    }
+
+    #[test]
+    fn test_helpers() {
+        assert_eq!(find_status_code("host said: 452 4.2.2 mailbox full."), Some("4.2.2".to_string()));
+        assert_eq!(find_status_code("version 1.2.3 and 550 5.1.10."), Some("5.1.10".to_string()));
+        assert_eq!(find_status_code("no code here, 10.0.0.1"), None);
+        assert_eq!(strip_address_type("rfc822; <a@b.test>"), "a@b.test");
+        assert_eq!(strip_address_type("a@b.test"), "a@b.test");
+        assert_eq!(last_message_start(b"From a\nbody\nFrom b\nbody\n"), 12);
+        assert_eq!(last_message_start(b"From a\nbody\n"), 0);
+    }
+
+    #[test]
+    fn test_split_messages_tracks_offsets() {
+        let mbox = b"From a@b.test Mon Mar  2 10:00:00 2026\nSubject: one\n\nbody\nFrom c@d.test Mon Mar  2 10:01:00 2026\nSubject: two\n\nbody\n";
+        let second = mbox.windows(6).position(|w| w == b"\nFrom ").unwrap() + 1;
+
+        let messages = split_messages(mbox, 100);
+        assert_eq!(messages.len(), 2);
+        assert!(String::from_utf8_lossy(&messages[0].raw).contains("Subject: one"));
+        assert!(!String::from_utf8_lossy(&messages[0].raw).contains("Subject: two"));
+        assert_eq!(messages[0].end, 100 + second as u64);
+        assert!(String::from_utf8_lossy(&messages[1].raw).contains("Subject: two"));
+        assert_eq!(messages[1].end, 100 + mbox.len() as u64);
+
+        assert!(split_messages(b"", 0).is_empty());
+    }
+}
@@ -0,0 +1,919 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::auth::{self, ApiKey};
+use crate::logging::LogFormat;
+use crate::sanitizer::{SanitizeMode, DEFAULT_MAX_BODY_BYTES};
+use crate::server::{self, Listener, TlsConfig};
+use crate::tracking::{self, Tracker};
+use crate::webhooks;
+
+/// A value that is never printed, e.g. `SMTP_PASS`
+#[derive(Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Self(value.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn redacted(&self) -> &'static str {
+        if self.0.is_empty() { "" } else { "***" }
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.redacted())
+    }
+}
+
+impl std::fmt::Display for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.redacted())
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.redacted())
+    }
+}
+
+/// Every problem found while loading the configuration
+#[derive(Debug)]
+pub struct ConfigError(pub Vec<String>);
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Invalid configuration:")?;
+        for error in &self.0 {
+            write!(f, "\n  - {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Mailer configuration
+///
+/// Read from an optional TOML or YAML file (`--config` or `MAILER_CONFIG`),
+/// then overridden by the environment variables documented in `.env.example`.
+/// Printing it (`Display`, `Debug`) redacts secrets.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub redis: RedisConfig,
+    pub smtp: SmtpConfig,
+    pub dkim: Vec<DkimKeyConfig>,
+    pub templates: TemplatesConfig,
+    pub custom_html: CustomHtmlConfig,
+    pub tracking: TrackingConfig,
+    pub webhooks: WebhooksConfig,
+    pub bounces: BouncesConfig,
+    pub auth: AuthConfig,
+    pub health: HealthConfig,
+    pub logging: LoggingConfig,
+    pub telemetry: TelemetryConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// `host:port` and/or `unix:/path` listeners
+    pub bind: Vec<String>,
+    pub tls: TlsSettings,
+    /// How long in-flight requests and SMTP sends get to finish on SIGTERM
+    pub shutdown_timeout_secs: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: vec!["0.0.0.0:8000".to_string()],
+            tls: TlsSettings::default(),
+            shutdown_timeout_secs: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsSettings {
+    pub cert_file: Option<PathBuf>,
+    pub key_file: Option<PathBuf>,
+    /// Require client certificates signed by this CA (mutual TLS)
+    pub client_ca_file: Option<PathBuf>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RedisConfig {
+    #[serde(serialize_with = "serialize_redacted_url")]
+    pub url: String,
+}
+
+impl Default for RedisConfig {
+    fn default() -> Self {
+        Self { url: "redis://redis:6379".to_string() }
+    }
+}
+
+impl std::fmt::Debug for RedisConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisConfig").field("url", &redact_url(&self.url)).finish()
+    }
+}
+
+/// `redis://:hunter2@redis:6379` → `redis://:***@redis:6379`
+pub fn redact_url(url: &str) -> String {
+    match url::Url::parse(url) {
+        Ok(mut parsed) if parsed.password().is_some() => {
+            let _ = parsed.set_password(Some("***"));
+            parsed.to_string()
+        }
+        _ => url.to_string(),
+    }
+}
+
+fn serialize_redacted_url<S: serde::Serializer>(url: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&redact_url(url))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub user: String,
+    pub pass: Secret,
+    pub secure: bool,
+    pub accept_invalid_certs: bool,
+    /// SMTPS (port 465) instead of STARTTLS (port 587)
+    pub implicit_tls: bool,
+    pub from: Option<String>,
+    /// Envelope sender, VERP-encoded per job (`bounces+{job_id}@domain`)
+    pub bounce_address: Option<String>,
+}
+
+impl Default for SmtpConfig {
+    fn default() -> Self {
+        Self {
+            host: String::new(),
+            port: 587,
+            user: String::new(),
+            pass: Secret::default(),
+            secure: true,
+            accept_invalid_certs: false,
+            implicit_tls: false,
+            from: None,
+            bounce_address: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DkimKeyConfig {
+    pub domain: String,
+    pub selector: String,
+    #[serde(default = "default_dkim_algorithm")]
+    pub algorithm: String,
+    pub private_key_file: PathBuf,
+}
+
+fn default_dkim_algorithm() -> String {
+    "rsa".to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TemplatesConfig {
+    pub dir: PathBuf,
+    pub watch: bool,
+}
+
+impl Default for TemplatesConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("templates"),
+            watch: true,
+        }
+    }
+}
+
+/// Sanitization of caller-supplied HTML; lists replace the built-in defaults
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CustomHtmlConfig {
+    pub allowed_tags: Option<Vec<String>>,
+    pub allowed_attributes: Option<Vec<String>>,
+    pub link_domains: Option<Vec<String>>,
+    pub max_bytes: usize,
+    pub mode: SanitizeMode,
+}
+
+impl Default for CustomHtmlConfig {
+    fn default() -> Self {
+        Self {
+            allowed_tags: None,
+            allowed_attributes: None,
+            link_domains: None,
+            max_bytes: DEFAULT_MAX_BODY_BYTES,
+            mode: SanitizeMode::Clean,
+        }
+    }
+}
+
+/// Open/click tracking, enabled by setting `base_url`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrackingConfig {
+    pub base_url: Option<String>,
+    pub secret: Secret,
+    pub opens: bool,
+    pub clicks: bool,
+}
+
+impl Default for TrackingConfig {
+    fn default() -> Self {
+        Self {
+            base_url: None,
+            secret: Secret::default(),
+            opens: true,
+            clicks: true,
+        }
+    }
+}
+
+/// Status-change webhooks, enabled by setting `secret`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhooksConfig {
+    pub secret: Secret,
+    /// Receives events for jobs without their own callback URL
+    pub url: Option<String>,
+    /// Hosts callbacks may reach although they resolve to loopback, private
+    /// or link-local addresses
+    pub allowed_hosts: Vec<String>,
+    pub max_attempts: u32,
+    /// Days delivered and failed deliveries are kept
+    pub retention_days: u32,
+}
+
+impl Default for WebhooksConfig {
+    fn default() -> Self {
+        Self {
+            secret: Secret::default(),
+            url: None,
+            allowed_hosts: Vec::new(),
+            max_attempts: 8,
+            retention_days: 7,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BouncesConfig {
+    /// Local mbox the bounce address is delivered to
+    pub mbox: Option<PathBuf>,
+    pub poll_secs: u64,
+}
+
+impl Default for BouncesConfig {
+    fn default() -> Self {
+        Self {
+            mbox: None,
+            poll_secs: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    pub format: LogFormat,
+    /// `tracing` filter directives, e.g. `info,mailer=debug`
+    pub level: String,
+    /// Mask recipient addresses (`j***@example.com`) in log lines
+    pub redact_recipients: bool,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::Text,
+            level: "info".to_string(),
+            redact_recipients: false,
+        }
+    }
+}
+
+/// Trace export over OTLP/HTTP
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetryConfig {
+    /// Collector base URL, e.g. `http://otel-collector:4318`; spans are not
+    /// exported when unset
+    pub otlp_endpoint: Option<String>,
+    /// `service.name` reported with every span
+    pub service_name: String,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            otlp_endpoint: None,
+            service_name: "mailer".to_string(),
+        }
+    }
+}
+
+/// Checks behind `/health/ready`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthConfig {
+    /// The worker counts as down without a heartbeat for this long
+    pub worker_timeout_secs: u64,
+    /// Log in to the SMTP relay and send NOOP as part of readiness
+    pub check_smtp: bool,
+    /// Minimum time between SMTP checks; results are cached in between
+    pub smtp_check_interval_secs: u64,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            worker_timeout_secs: 60,
+            check_smtp: false,
+            smtp_check_interval_secs: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub keys: Vec<ApiKeyConfig>,
+    /// `id:secret:scopes` per line, re-read when it changes
+    pub keys_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApiKeyConfig {
+    pub id: String,
+    pub secret: Secret,
+    pub scopes: Vec<String>,
+}
+
+impl ApiKeyConfig {
+    pub fn to_key(&self) -> Result<ApiKey, anyhow::Error> {
+        ApiKey::new(&self.id, self.secret.expose(), self.scopes.iter().map(String::as_str))
+    }
+}
+
+impl Config {
+    /// Load from `path` (if any) and the process environment
+    pub fn load(path: Option<&Path>) -> Result<Self, ConfigError> {
+        Self::load_with(path, |name| std::env::var(name).ok())
+    }
+
+    /// Load with a custom environment lookup
+    pub fn load_with(path: Option<&Path>, env: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        let mut errors = Vec::new();
+
+        let (mut config, file_ok) = match path.map(Self::from_file) {
+            Some(Ok(config)) => (config, true),
+            Some(Err(e)) => {
+                errors.push(e);
+                (Config::default(), false)
+            }
+            None => (Config::default(), true),
+        };
+
+        // Empty variables count as unset, as docker-compose passes them through
+        let env = |name: &str| env(name).filter(|value| !value.trim().is_empty());
+        config.apply_env(&env, &mut errors);
+
+        // Validating defaults after a broken file would only add noise
+        if file_ok {
+            errors.extend(config.validate());
+        }
+
+        if errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigError(errors))
+        }
+    }
+
+    /// Parse a `.toml`, `.yaml` or `.yml` file
+    fn from_file(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+        match path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase).as_deref() {
+            Some("toml") => toml::from_str(&contents).map_err(|e| format!("{}: {}", path.display(), e)),
+            Some("yaml" | "yml") => serde_yaml_ng::from_str(&contents).map_err(|e| format!("{}: {}", path.display(), e)),
+            _ => Err(format!("{}: unknown config format, expected .toml, .yaml or .yml", path.display())),
+        }
+    }
+
+    fn apply_env(&mut self, env: &impl Fn(&str) -> Option<String>, errors: &mut Vec<String>) {
+        let mut vars = EnvOverrides { env, errors };
+
+        vars.list("MAILER_BIND", &mut self.server.bind);
+        vars.path("MAILER_TLS_CERT", &mut self.server.tls.cert_file);
+        vars.path("MAILER_TLS_KEY", &mut self.server.tls.key_file);
+        vars.path("MAILER_TLS_CLIENT_CA", &mut self.server.tls.client_ca_file);
+        vars.parse("MAILER_SHUTDOWN_TIMEOUT", &mut self.server.shutdown_timeout_secs);
+
+        vars.string("REDIS_URL", &mut self.redis.url);
+
+        vars.string("SMTP_HOST", &mut self.smtp.host);
+        vars.parse("SMTP_PORT", &mut self.smtp.port);
+        vars.string("SMTP_USER", &mut self.smtp.user);
+        vars.secret("SMTP_PASS", &mut self.smtp.pass);
+        vars.bool("SMTP_SECURE", &mut self.smtp.secure);
+        vars.bool("SMTP_ACCEPT_INVALID_CERTS", &mut self.smtp.accept_invalid_certs);
+        vars.bool("SMTP_IMPLICIT_TLS", &mut self.smtp.implicit_tls);
+        vars.optional("SMTP_FROM", &mut self.smtp.from);
+        vars.optional("SMTP_BOUNCE_ADDRESS", &mut self.smtp.bounce_address);
+
+        // A single key via DKIM_DOMAIN/DKIM_SELECTOR/DKIM_PRIVATE_KEY_FILE and/or
+        // DKIM_KEYS="domain:selector:algorithm:/path/to/key,..."; both add to the file's keys
+        if let Some(domain) = (vars.env)("DKIM_DOMAIN") {
+            let selector = (vars.env)("DKIM_SELECTOR");
+            let key_file = (vars.env)("DKIM_PRIVATE_KEY_FILE");
+            if selector.is_none() {
+                vars.errors.push("DKIM_SELECTOR must be set when DKIM_DOMAIN is set".to_string());
+            }
+            if key_file.is_none() {
+                vars.errors.push("DKIM_PRIVATE_KEY_FILE must be set when DKIM_DOMAIN is set".to_string());
+            }
+            if let (Some(selector), Some(key_file)) = (selector, key_file) {
+                self.dkim.push(DkimKeyConfig {
+                    domain,
+                    selector,
+                    algorithm: (vars.env)("DKIM_ALGORITHM").unwrap_or_else(default_dkim_algorithm),
+                    private_key_file: key_file.into(),
+                });
+            }
+        }
+        if let Some(keys) = (vars.env)("DKIM_KEYS") {
+            for entry in split_list(&keys) {
+                let parts: Vec<&str> = entry.splitn(4, ':').collect();
+                match parts[..] {
+                    [domain, selector, algorithm, key_file] => self.dkim.push(DkimKeyConfig {
+                        domain: domain.to_string(),
+                        selector: selector.to_string(),
+                        algorithm: algorithm.to_string(),
+                        private_key_file: key_file.into(),
+                    }),
+                    _ => vars.errors.push(format!("DKIM_KEYS: invalid entry '{}', expected domain:selector:algorithm:path", entry)),
+                }
+            }
+        }
+
+        vars.path_buf("TEMPLATES_DIR", &mut self.templates.dir);
+        vars.bool("TEMPLATES_WATCH", &mut self.templates.watch);
+
+        vars.optional_list("CUSTOM_HTML_ALLOWED_TAGS", &mut self.custom_html.allowed_tags);
+        vars.optional_list("CUSTOM_HTML_ALLOWED_ATTRIBUTES", &mut self.custom_html.allowed_attributes);
+        vars.optional_list("CUSTOM_HTML_LINK_DOMAINS", &mut self.custom_html.link_domains);
+        vars.parse("CUSTOM_HTML_MAX_BYTES", &mut self.custom_html.max_bytes);
+        vars.parse("CUSTOM_HTML_MODE", &mut self.custom_html.mode);
+
+        vars.optional("TRACKING_BASE_URL", &mut self.tracking.base_url);
+        vars.secret("TRACKING_SECRET", &mut self.tracking.secret);
+        vars.bool("TRACK_OPENS", &mut self.tracking.opens);
+        vars.bool("TRACK_CLICKS", &mut self.tracking.clicks);
+
+        vars.secret("WEBHOOK_SECRET", &mut self.webhooks.secret);
+        vars.optional("WEBHOOK_URL", &mut self.webhooks.url);
+        vars.list("WEBHOOK_ALLOWED_HOSTS", &mut self.webhooks.allowed_hosts);
+        vars.parse("WEBHOOK_MAX_ATTEMPTS", &mut self.webhooks.max_attempts);
+        vars.parse("WEBHOOK_RETENTION_DAYS", &mut self.webhooks.retention_days);
+
+        vars.path("BOUNCE_MBOX", &mut self.bounces.mbox);
+        vars.parse("BOUNCE_POLL_SECS", &mut self.bounces.poll_secs);
+
+        // MAILER_API_KEYS="id:secret:send+read,..." adds to the file's keys
+        if let Some(keys) = (vars.env)("MAILER_API_KEYS") {
+            for entry in split_list(&keys) {
+                let parts: Vec<&str> = entry.splitn(3, ':').collect();
+                match parts[..] {
+                    [id, secret, scopes] => self.auth.keys.push(ApiKeyConfig {
+                        id: id.to_string(),
+                        secret: Secret::new(secret),
+                        scopes: scopes.split('+').map(str::to_string).collect(),
+                    }),
+                    // Never echo the entry, it contains the secret
+                    _ => vars.errors.push("MAILER_API_KEYS: invalid entry, expected id:secret:scopes".to_string()),
+                }
+            }
+        }
+        vars.path("MAILER_API_KEYS_FILE", &mut self.auth.keys_file);
+
+        vars.parse("LOG_FORMAT", &mut self.logging.format);
+        vars.string("RUST_LOG", &mut self.logging.level);
+        vars.bool("LOG_REDACT_RECIPIENTS", &mut self.logging.redact_recipients);
+
+        vars.parse("HEALTH_WORKER_TIMEOUT", &mut self.health.worker_timeout_secs);
+        vars.bool("HEALTH_CHECK_SMTP", &mut self.health.check_smtp);
+        vars.parse("HEALTH_SMTP_INTERVAL", &mut self.health.smtp_check_interval_secs);
+
+        vars.optional("OTEL_EXPORTER_OTLP_ENDPOINT", &mut self.telemetry.otlp_endpoint);
+        vars.string("OTEL_SERVICE_NAME", &mut self.telemetry.service_name);
+    }
+
+    /// Check the whole configuration, returning every problem found
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+
+        if let Err(e) = self.listeners() {
+            errors.push(format!("server.bind (MAILER_BIND): {}", e));
+        }
+        let tls = &self.server.tls;
+        match (&tls.cert_file, &tls.key_file) {
+            (Some(cert), Some(key)) => {
+                check_file(&mut errors, "server.tls.cert_file (MAILER_TLS_CERT)", cert);
+                check_file(&mut errors, "server.tls.key_file (MAILER_TLS_KEY)", key);
+            }
+            (None, None) => {
+                if tls.client_ca_file.is_some() {
+                    errors.push("server.tls.client_ca_file (MAILER_TLS_CLIENT_CA) requires a certificate and key".to_string());
+                }
+            }
+            _ => errors.push("server.tls.cert_file and key_file (MAILER_TLS_CERT, MAILER_TLS_KEY) must be set together".to_string()),
+        }
+        if let Some(ca) = &tls.client_ca_file {
+            check_file(&mut errors, "server.tls.client_ca_file (MAILER_TLS_CLIENT_CA)", ca);
+        }
+
+        if let Err(e) = redis::Client::open(self.redis.url.as_str()) {
+            errors.push(format!("redis.url (REDIS_URL): {}", e));
+        }
+
+        if self.smtp.host.is_empty() {
+            errors.push("smtp.host (SMTP_HOST) is required".to_string());
+        }
+        if self.smtp.port == 0 {
+            errors.push("smtp.port (SMTP_PORT) must be a valid port number".to_string());
+        }
+        if self.smtp.user.is_empty() {
+            errors.push("smtp.user (SMTP_USER) is required".to_string());
+        }
+        if self.smtp.pass.is_empty() {
+            errors.push("smtp.pass (SMTP_PASS) is required".to_string());
+        }
+        match &self.smtp.from {
+            Some(from) => {
+                if let Err(e) = from.parse::<lettre::message::Mailbox>() {
+                    errors.push(format!("smtp.from (SMTP_FROM): '{}' is not a valid mailbox: {}", from, e));
+                }
+            }
+            // The sender defaults to the SMTP user, which then has to be an address
+            None => {
+                if !self.smtp.user.is_empty() && self.smtp.user.parse::<lettre::message::Mailbox>().is_err() {
+                    errors.push(format!(
+                        "smtp.user (SMTP_USER): '{}' is not an email address, so smtp.from (SMTP_FROM) must be set",
+                        self.smtp.user
+                    ));
+                }
+            }
+        }
+        if let Some(address) = &self.smtp.bounce_address
+            && let Err(e) = address.parse::<lettre::Address>()
+        {
+            errors.push(format!("smtp.bounce_address (SMTP_BOUNCE_ADDRESS): '{}' is not a valid address: {}", address, e));
+        }
+
+        for key in &self.dkim {
+            let name = format!("dkim key for {}", key.domain);
+            if key.domain.is_empty() || key.selector.is_empty() {
+                errors.push(format!("{}: domain and selector are required", name));
+            }
+            match crate::smtp::parse_dkim_algorithm(&key.algorithm) {
+                Ok(algorithm) => {
+                    if let Err(e) = crate::smtp::read_dkim_key(&key.private_key_file.to_string_lossy(), algorithm) {
+                        errors.push(format!("{}: {}", name, e));
+                    }
+                }
+                Err(e) => {
+                    errors.push(format!("{}: {}", name, e));
+                    check_file(&mut errors, &name, &key.private_key_file);
+                }
+            }
+        }
+
+        if self.custom_html.max_bytes == 0 {
+            errors.push("custom_html.max_bytes (CUSTOM_HTML_MAX_BYTES) must be greater than 0".to_string());
+        }
+
+        if let Some(base_url) = &self.tracking.base_url {
+            if self.tracking.secret.is_empty() {
+                errors.push("tracking.secret (TRACKING_SECRET) must be set when tracking.base_url is set".to_string());
+            } else if self.tracking.secret.expose().len() < tracking::MIN_SECRET_LEN {
+                errors.push(format!(
+                    "tracking.secret (TRACKING_SECRET) must be at least {} characters",
+                    tracking::MIN_SECRET_LEN
+                ));
+            } else if let Err(e) = Tracker::new(base_url, self.tracking.secret.expose()) {
+                errors.push(format!("tracking.base_url (TRACKING_BASE_URL): {}", e));
+            }
+        }
+
+        if let Some(url) = &self.webhooks.url {
+            if self.webhooks.secret.is_empty() {
+                errors.push("webhooks.url (WEBHOOK_URL) requires webhooks.secret (WEBHOOK_SECRET)".to_string());
+            }
+            if let Err(e) = webhooks::validate_callback_url(url, &self.webhooks.allowed_hosts) {
+                errors.push(format!("webhooks.url (WEBHOOK_URL): {}", e));
+            }
+        }
+        if self.webhooks.max_attempts == 0 {
+            errors.push("webhooks.max_attempts (WEBHOOK_MAX_ATTEMPTS) must be at least 1".to_string());
+        }
+        if self.webhooks.retention_days == 0 {
+            errors.push("webhooks.retention_days (WEBHOOK_RETENTION_DAYS) must be at least 1".to_string());
+        }
+
+        if let Err(e) = tracing_subscriber::EnvFilter::try_new(&self.logging.level) {
+            errors.push(format!("logging.level (RUST_LOG): {}", e));
+        }
+
+        if let Some(endpoint) = &self.telemetry.otlp_endpoint
+            && !(endpoint.starts_with("http://") || endpoint.starts_with("https://"))
+        {
+            errors.push(format!(
+                "telemetry.otlp_endpoint (OTEL_EXPORTER_OTLP_ENDPOINT): expected an http(s) URL, got {}",
+                endpoint
+            ));
+        }
+
+        if self.health.worker_timeout_secs < 2 {
+            errors.push("health.worker_timeout_secs (HEALTH_WORKER_TIMEOUT) must be at least 2".to_string());
+        }
+
+        if self.bounces.poll_secs == 0 {
+            errors.push("bounces.poll_secs (BOUNCE_POLL_SECS) must be at least 1".to_string());
+        }
+
+        for key in &self.auth.keys {
+            if let Err(e) = key.to_key() {
+                errors.push(format!("auth.keys (MAILER_API_KEYS): {}", e));
+            }
+        }
+        if let Some(file) = &self.auth.keys_file {
+            match std::fs::read_to_string(file) {
+                Ok(contents) => {
+                    if let Err(e) = auth::parse_keys(&contents) {
+                        errors.push(format!("auth.keys_file (MAILER_API_KEYS_FILE) {}: {}", file.display(), e));
+                    }
+                }
+                Err(e) => errors.push(format!("auth.keys_file (MAILER_API_KEYS_FILE) {}: {}", file.display(), e)),
+            }
+        }
+
+        errors
+    }
+
+    pub fn listeners(&self) -> Result<Vec<Listener>, anyhow::Error> {
+        server::parse_listeners(&self.server.bind.join(","))
+    }
+
+    /// TLS for TCP listeners, when a certificate and key are configured
+    pub fn tls(&self) -> Option<TlsConfig> {
+        let tls = &self.server.tls;
+        Some(TlsConfig {
+            cert_file: tls.cert_file.clone()?,
+            key_file: tls.key_file.clone()?,
+            client_ca_file: tls.client_ca_file.clone(),
+        })
+    }
+
+    pub fn api_keys(&self) -> Result<Vec<ApiKey>, anyhow::Error> {
+        self.auth.keys.iter().map(ApiKeyConfig::to_key).collect()
+    }
+}
+
+/// Prints the effective configuration as TOML, secrets redacted
+impl std::fmt::Display for Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let toml = toml::to_string_pretty(self).map_err(|_| std::fmt::Error)?;
+        f.write_str(&toml)
+    }
+}
+
+/// Applies environment variables to config fields, collecting parse errors
+struct EnvOverrides<'a, F: Fn(&str) -> Option<String>> {
+    env: &'a F,
+    errors: &'a mut Vec<String>,
+}
+
+impl<F: Fn(&str) -> Option<String>> EnvOverrides<'_, F> {
+    fn string(&mut self, name: &str, field: &mut String) {
+        if let Some(value) = (self.env)(name) {
+            *field = value;
+        }
+    }
+
+    fn optional(&mut self, name: &str, field: &mut Option<String>) {
+        if let Some(value) = (self.env)(name) {
+            *field = Some(value);
+        }
+    }
+
+    fn secret(&mut self, name: &str, field: &mut Secret) {
+        if let Some(value) = (self.env)(name) {
+            *field = Secret::new(value);
+        }
+    }
+
+    fn path(&mut self, name: &str, field: &mut Option<PathBuf>) {
+        if let Some(value) = (self.env)(name) {
+            *field = Some(value.into());
+        }
+    }
+
+    fn path_buf(&mut self, name: &str, field: &mut PathBuf) {
+        if let Some(value) = (self.env)(name) {
+            *field = value.into();
+        }
+    }
+
+    fn list(&mut self, name: &str, field: &mut Vec<String>) {
+        if let Some(value) = (self.env)(name) {
+            *field = split_list(&value).map(str::to_string).collect();
+        }
+    }
+
+    fn optional_list(&mut self, name: &str, field: &mut Option<Vec<String>>) {
+        if let Some(value) = (self.env)(name) {
+            *field = Some(split_list(&value).map(str::to_string).collect());
+        }
+    }
+
+    fn parse<T>(&mut self, name: &str, field: &mut T)
+    where
+        T: std::str::FromStr,
+        T::Err: std::fmt::Display,
+    {
+        if let Some(value) = (self.env)(name) {
+            match value.trim().parse() {
+                Ok(parsed) => *field = parsed,
+                Err(e) => self.errors.push(format!("{}: invalid value '{}': {}", name, value, e)),
+            }
+        }
+    }
+
+    /// Unlike `str::parse::<bool>`, typos are reported instead of ignored
+    fn bool(&mut self, name: &str, field: &mut bool) {
+        if let Some(value) = (self.env)(name) {
+            match value.trim().to_ascii_lowercase().as_str() {
+                "true" | "1" | "yes" | "on" => *field = true,
+                "false" | "0" | "no" | "off" => *field = false,
+                _ => self.errors.push(format!("{}: expected true or false, got '{}'", name, value)),
+            }
+        }
+    }
+}
+
+fn split_list(value: &str) -> impl Iterator<Item = &str> {
+    value.split(',').map(str::trim).filter(|entry| !entry.is_empty())
+}
+
+fn check_file(errors: &mut Vec<String>, name: &str, path: &Path) {
+    if !path.is_file() {
+        errors.push(format!("{}: {} does not exist or is not a file", name, path.display()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn load(file: Option<&str>, vars: &[(&str, &str)]) -> Result<Config, ConfigError> {
+        let env: HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        let path = file.map(|contents| {
+            let path = std::env::temp_dir().join(format!("mailer-config-{}-{}.toml", std::process::id(), contents.len()));
+            std::fs::write(&path, contents).unwrap();
+            path
+        });
+        let result = Config::load_with(path.as_deref(), |name| env.get(name).cloned());
+        if let Some(path) = path {
+            std::fs::remove_file(path).unwrap();
+        }
+        result
+    }
+
+    const SMTP: &[(&str, &str)] = &[("SMTP_HOST", "smtp.example.com"), ("SMTP_USER", "mailer@example.com"), ("SMTP_PASS", "smtp-password")];
+
+    #[test]
+    fn test_collects_every_error() {
+        let errors = load(None, &[("SMTP_PORT", "smtp"), ("SMTP_SECURE", "ture"), ("WEBHOOK_MAX_ATTEMPTS", "0")])
+            .unwrap_err()
+            .0;
+        assert!(errors.iter().any(|e| e.starts_with("SMTP_PORT: ")), "{:?}", errors);
+        assert!(errors.contains(&"SMTP_SECURE: expected true or false, got 'ture'".to_string()), "{:?}", errors);
+        assert!(errors.contains(&"smtp.host (SMTP_HOST) is required".to_string()), "{:?}", errors);
+        assert!(errors.contains(&"smtp.pass (SMTP_PASS) is required".to_string()), "{:?}", errors);
+        assert!(errors.iter().any(|e| e.starts_with("webhooks.max_attempts")), "{:?}", errors);
+    }
+
+    #[test]
+    fn test_env_overrides_file() {
+        let file = "[smtp]\nhost = \"file.example.com\"\nport = 2525\nuser = \"file@example.com\"\npass = \"file-pass\"\nsecure = true\n";
+        let config = load(Some(file), &[("SMTP_HOST", "env.example.com"), ("SMTP_SECURE", "off"), ("SMTP_PASS", "")]).unwrap();
+        assert_eq!(config.smtp.host, "env.example.com");
+        assert!(!config.smtp.secure);
+        // Values only in the file are kept, and empty variables count as unset
+        assert_eq!(config.smtp.port, 2525);
+        assert_eq!(config.smtp.pass.expose(), "file-pass");
+
+        let errors = load(Some("[smtp]\nhots = \"typo\"\n"), SMTP).unwrap_err().0;
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(errors[0].contains("unknown field `hots`"), "{:?}", errors);
+    }
+
+    #[test]
+    fn test_smtp_user_must_be_an_address_without_from() {
+        let vars = [("SMTP_HOST", "smtp.example.com"), ("SMTP_USER", "apikey"), ("SMTP_PASS", "smtp-password")];
+        let errors = load(None, &vars).unwrap_err().0;
+        assert_eq!(
+            errors,
+            vec!["smtp.user (SMTP_USER): 'apikey' is not an email address, so smtp.from (SMTP_FROM) must be set"]
+        );
+
+        let mut vars = vars.to_vec();
+        vars.push(("SMTP_FROM", "KillCode <noreply@killcode.app>"));
+        assert!(load(None, &vars).is_ok());
+        assert!(load(None, SMTP).is_ok());
+    }
+
+    #[test]
+    fn test_dkim_key_must_be_readable() {
+        let path = std::env::temp_dir().join(format!("mailer-config-dkim-{}.pem", std::process::id()));
+        std::fs::write(&path, "not a key").unwrap();
+        let mut vars = SMTP.to_vec();
+        vars.extend([("DKIM_DOMAIN", "example.com"), ("DKIM_SELECTOR", "mail")]);
+
+        let mut with_file = vars.clone();
+        with_file.push(("DKIM_PRIVATE_KEY_FILE", path.to_str().unwrap()));
+        let errors = load(None, &with_file).unwrap_err().0;
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(errors[0].starts_with("dkim key for example.com: Invalid DKIM key"), "{:?}", errors);
+
+        vars.push(("DKIM_PRIVATE_KEY_FILE", "/nonexistent/dkim.pem"));
+        let errors = load(None, &vars).unwrap_err().0;
+        assert!(errors[0].starts_with("dkim key for example.com: Failed to read DKIM key /nonexistent/dkim.pem"), "{:?}", errors);
+    }
+
+    #[test]
+    fn test_tracking_secret_length() {
+        let mut vars = SMTP.to_vec();
+        vars.extend([("TRACKING_BASE_URL", "https://mail.example.com"), ("TRACKING_SECRET", "short")]);
+        let errors = load(None, &vars).unwrap_err().0;
+        assert_eq!(errors, vec!["tracking.secret (TRACKING_SECRET) must be at least 16 characters"]);
+
+        vars.pop();
+        vars.push(("TRACKING_SECRET", "a-long-tracking-secret"));
+        assert!(load(None, &vars).is_ok());
+    }
+
+    #[test]
+    fn test_display_redacts_secrets() {
+        let mut vars = SMTP.to_vec();
+        vars.extend([
+            ("REDIS_URL", "redis://:redis-password@redis:6379"),
+            ("WEBHOOK_SECRET", "webhook-secret"),
+            ("MAILER_API_KEYS", "server:api-key-secret-0123456789:send"),
+        ]);
+        let config = load(None, &vars).unwrap();
+
+        for printed in [config.to_string(), format!("{:?}", config)] {
+            for secret in ["smtp-password", "redis-password", "webhook-secret", "api-key-secret-0123456789"] {
+                assert!(!printed.contains(secret), "{} leaked in {}", secret, printed);
+            }
+        }
+        assert!(config.to_string().contains("redis://:***@redis:6379"));
+        assert_eq!(config.smtp.pass.expose(), "smtp-password");
+    }
+}
@@ -1,43 +1,152 @@
-use actix_web::{web, HttpResponse};
+use std::collections::{BTreeMap, HashMap};
+use std::time::Instant;
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web::http::header;
 use serde_json::json;
 
-use crate::{EmailQueue, SmtpClient, TemplateEngine};
-use crate::models::{SendOtpRequest, SendOtp2FARequest, SendEmailRequest, EmailResponse, EmailTemplate};
+use crate::{EmailQueue, HtmlSanitizer, SmtpClient, SuppressionList, TemplateEngine, TemplateStore, Tracker, WebhookDispatcher};
+use crate::auth::KeyRing;
+use crate::bounces;
+use crate::queue;
+use crate::logging::{self, RequestId};
+use crate::health::{self, HealthChecks};
+use crate::metrics::METRICS;
+use crate::shutdown::Shutdown;
+use crate::models::{
+    SendOtpRequest, SendOtp2FARequest, SendEmailRequest, EmailResponse, EmailTemplate, JobOptions, JobQuery,
+    QueueStats, StatsQuery, TemplateStats,
+    TemplateRef, RenderedEmail, ComponentHealth, ComponentStatus, ReadinessResponse, PreviewResponse, TrackingEvent, TrackingEventKind, EmailStatus, DeliveryStatus, TemplateSummary, CreateTemplateRequest, UpdateTemplateRequest, RollbackTemplateRequest, PreviewTemplateRequest,
+};
+use crate::helpers;
+use crate::template_store::TemplateStoreError;
+use crate::tracking::PIXEL_GIF;
+
+/// Limit on metadata entries, and on tags, per job
+const MAX_LABELS: usize = 20;
+/// Longest metadata key or tag
+const MAX_LABEL_LEN: usize = 64;
+const MAX_METADATA_VALUE_LEN: usize = 256;
 
 pub struct AppState {
     pub queue: EmailQueue,
     pub smtp: SmtpClient,
     pub templates: TemplateEngine,
+    pub template_store: TemplateStore,
+    pub sanitizer: HtmlSanitizer,
+    /// Open/click tracking, disabled when `None`
+    pub tracker: Option<Tracker>,
+    /// Status-change webhooks, disabled when `None`
+    pub webhooks: Option<WebhookDispatcher>,
+    pub suppressions: SuppressionList,
+    pub auth: KeyRing,
+    pub shutdown: Shutdown,
+    pub health: HealthChecks,
 }
 
-/// Health check endpoint
-pub async fn health() -> HttpResponse {
+/// Largest raw bounce message accepted by `/bounces`
+pub const MAX_BOUNCE_BYTES: usize = 10 * 1024 * 1024;
+
+/// Liveness: the process is up and serving HTTP
+pub async fn health_live() -> HttpResponse {
     HttpResponse::Ok().json(json!({
-        "status": "healthy",
+        "status": "alive",
         "service": "mailer"
     }))
 }
 
+/// Readiness: Redis answers, the email worker is running and, when enabled,
+/// the SMTP relay accepts our credentials; 503 if anything is down
+pub async fn health_ready(state: web::Data<AppState>) -> HttpResponse {
+    let started = Instant::now();
+    let redis = match state.queue.ping().await {
+        Ok(()) => ComponentHealth {
+            latency_ms: Some(health::millis(started.elapsed())),
+            ..ComponentHealth::status(ComponentStatus::Up)
+        },
+        Err(e) => ComponentHealth {
+            latency_ms: Some(health::millis(started.elapsed())),
+            error: Some(e.to_string()),
+            ..ComponentHealth::status(ComponentStatus::Down)
+        },
+    };
+
+    let components = BTreeMap::from([
+        ("redis".to_string(), redis),
+        ("worker".to_string(), state.health.worker()),
+        ("smtp".to_string(), state.health.smtp(&state.smtp).await),
+    ]);
+
+    let shutting_down = state.shutdown.is_triggered();
+    let ready = !shutting_down && components.values().all(ComponentHealth::is_ready);
+    let response = ReadinessResponse {
+        status: if ready { "ready" } else { "not_ready" }.to_string(),
+        service: "mailer".to_string(),
+        shutting_down,
+        components,
+    };
+
+    if ready {
+        HttpResponse::Ok().json(response)
+    } else {
+        HttpResponse::ServiceUnavailable().json(response)
+    }
+}
+
 /// Send OTP email for signup (queued)
 pub async fn send_otp(
     state: web::Data<AppState>,
     req: web::Json<SendOtpRequest>,
+    request_id: RequestId,
 ) -> HttpResponse {
+    if let Some(response) = check_accepting(&state) {
+        return response;
+    }
+
     let data = json!({
         "otp": req.otp,
         "email": req.email
     });
 
+    if let Some(response) = check_required_fields(&EmailTemplate::Otp, &data) {
+        return response;
+    }
+
+    let locale = match parse_locale(req.locale.as_deref()) {
+        Ok(locale) => locale,
+        Err(response) => return response,
+    };
+    let callback_url = match parse_callback_url(&state, req.callback_url.as_deref()) {
+        Ok(callback_url) => callback_url,
+        Err(response) => return response,
+    };
+    let tags = match validate_labels(&req.metadata, &req.tags) {
+        Ok(tags) => tags,
+        Err(response) => return response,
+    };
+    if let Some(response) = check_suppressed(&state, &req.email).await {
+        return response;
+    }
+
     match state.queue.enqueue(
         req.email.clone(),
-        "Your KillCode Verification Code".to_string(),
+        None,
         EmailTemplate::Otp,
         data,
+        JobOptions {
+            locale,
+            callback_url,
+            request_id: Some(request_id.0),
+            metadata: req.metadata.clone(),
+            tags,
+            ..Default::default()
+        },
     ).await {
         Ok(job_id) => HttpResponse::Ok().json(EmailResponse {
             success: true,
             job_id: Some(job_id),
             message: "OTP email queued successfully".to_string(),
+            stripped: Vec::new(),
         }),
         Err(e) => {
             log::error!("Failed to queue OTP email: {}", e);
@@ -45,6 +154,7 @@ pub async fn send_otp(
                 success: false,
                 job_id: None,
                 message: format!("Failed to queue email: {}", e),
+                stripped: Vec::new(),
             })
         }
     }
@@ -54,22 +164,56 @@ pub async fn send_otp(
 pub async fn send_otp_2fa(
     state: web::Data<AppState>,
     req: web::Json<SendOtp2FARequest>,
+    request_id: RequestId,
 ) -> HttpResponse {
+    if let Some(response) = check_accepting(&state) {
+        return response;
+    }
+
     let data = json!({
         "otp": req.otp,
         "email": req.email
     });
 
+    if let Some(response) = check_required_fields(&EmailTemplate::Otp2FA, &data) {
+        return response;
+    }
+
+    let locale = match parse_locale(req.locale.as_deref()) {
+        Ok(locale) => locale,
+        Err(response) => return response,
+    };
+    let callback_url = match parse_callback_url(&state, req.callback_url.as_deref()) {
+        Ok(callback_url) => callback_url,
+        Err(response) => return response,
+    };
+    let tags = match validate_labels(&req.metadata, &req.tags) {
+        Ok(tags) => tags,
+        Err(response) => return response,
+    };
+    if let Some(response) = check_suppressed(&state, &req.email).await {
+        return response;
+    }
+
     match state.queue.enqueue(
         req.email.clone(),
-        "KillCode Login Verification".to_string(),
+        None,
         EmailTemplate::Otp2FA,
         data,
+        JobOptions {
+            locale,
+            callback_url,
+            request_id: Some(request_id.0),
+            metadata: req.metadata.clone(),
+            tags,
+            ..Default::default()
+        },
     ).await {
         Ok(job_id) => HttpResponse::Ok().json(EmailResponse {
             success: true,
             job_id: Some(job_id),
             message: "2FA OTP email queued successfully".to_string(),
+            stripped: Vec::new(),
         }),
         Err(e) => {
             log::error!("Failed to queue 2FA OTP email: {}", e);
@@ -77,6 +221,7 @@ pub async fn send_otp_2fa(
                 success: false,
                 job_id: None,
                 message: format!("Failed to queue email: {}", e),
+                stripped: Vec::new(),
             })
         }
     }
@@ -86,17 +231,41 @@ pub async fn send_otp_2fa(
 pub async fn send_email(
     state: web::Data<AppState>,
     req: web::Json<SendEmailRequest>,
+    request_id: RequestId,
 ) -> HttpResponse {
+    if let Some(response) = check_accepting(&state) {
+        return response;
+    }
+
+    let prepared = match prepare_request(&state, &req).await {
+        Ok(prepared) => prepared,
+        Err(response) => return response,
+    };
+    if let Some(response) = check_suppressed(&state, &req.to).await {
+        return response;
+    }
+
     match state.queue.enqueue(
         req.to.clone(),
         req.subject.clone(),
         req.template.clone(),
-        req.data.clone(),
+        prepared.data,
+        JobOptions {
+            stored_template: prepared.stored_template,
+            locale: prepared.locale,
+            track: req.track.unwrap_or(true),
+            callback_url: prepared.callback_url,
+            expires_at: req.expires_at,
+            request_id: Some(request_id.0),
+            metadata: req.metadata.clone(),
+            tags: prepared.tags,
+        },
     ).await {
         Ok(job_id) => HttpResponse::Ok().json(EmailResponse {
             success: true,
             job_id: Some(job_id),
             message: "Email queued successfully".to_string(),
+            stripped: prepared.stripped,
         }),
         Err(e) => {
             log::error!("Failed to queue email: {}", e);
@@ -104,14 +273,123 @@ pub async fn send_email(
                 success: false,
                 job_id: None,
                 message: format!("Failed to queue email: {}", e),
+                stripped: Vec::new(),
             })
         }
     }
 }
 
-/// Get queue statistics
-pub async fn queue_stats(state: web::Data<AppState>) -> HttpResponse {
-    match state.queue.stats().await {
+/// Render an email exactly as `/send` would, without enqueueing it
+pub async fn preview_email(
+    state: web::Data<AppState>,
+    req: web::Json<SendEmailRequest>,
+) -> HttpResponse {
+    match prepare_request(&state, &req).await {
+        Ok(prepared) => HttpResponse::Ok().json(PreviewResponse {
+            rendered: prepared.rendered,
+            stripped: prepared.stripped,
+        }),
+        Err(response) => response,
+    }
+}
+
+/// A validated send request, rendered once to catch errors before enqueueing
+struct PreparedEmail {
+    rendered: RenderedEmail,
+    /// Request data, with custom HTML replaced by its sanitized version
+    data: serde_json::Value,
+    stored_template: Option<TemplateRef>,
+    locale: Option<String>,
+    callback_url: Option<String>,
+    /// What the sanitizer removed from custom HTML
+    stripped: Vec<String>,
+    /// Request tags, de-duplicated
+    tags: Vec<String>,
+}
+
+/// Validate, sanitize and render a send request
+///
+/// Stored templates are resolved here, at enqueue time, so later edits don't
+/// change queued mail; the returned reference pins the version for the worker.
+async fn prepare_request(
+    state: &AppState,
+    req: &SendEmailRequest,
+) -> Result<PreparedEmail, HttpResponse> {
+    if let Some(response) = check_required_fields(&req.template, &req.data) {
+        return Err(response);
+    }
+    let locale = parse_locale(req.locale.as_deref())?;
+    let callback_url = parse_callback_url(state, req.callback_url.as_deref())?;
+    if req.expires_at.is_some_and(|expires_at| expires_at <= chrono::Utc::now()) {
+        return Err(bad_request("'expires_at' must be in the future"));
+    }
+    let tags = validate_labels(&req.metadata, &req.tags)?;
+    let mut data = req.data.clone();
+    let mut stripped = Vec::new();
+
+    // Caller-supplied HTML is cleaned before it is rendered or stored on the job
+    if let (EmailTemplate::Custom, None, Some(html)) = (&req.template, &req.template_name, req.data.get("html").and_then(|v| v.as_str())) {
+        let sanitized = state.sanitizer
+            .sanitize(html)
+            .map_err(|e| bad_request(&e.to_string()))?;
+        if !sanitized.stripped.is_empty() {
+            log::warn!("Sanitized custom HTML for {}: {}", logging::recipient(&req.to), sanitized.stripped.join("; "));
+        }
+        data["html"] = json!(sanitized.html);
+        stripped = sanitized.stripped;
+    }
+
+    let (rendered, stored_template) = match (&req.template, &req.template_name) {
+        (EmailTemplate::Custom, Some(name)) => {
+            let template = state.template_store
+                .get(name, req.template_version)
+                .await
+                .map_err(template_store_error)?;
+            let rendered = state.templates
+                .render_stored(&template, &data, req.subject.as_deref(), locale.as_deref())
+                .map_err(|e| bad_request(&format!("Invalid data for template '{}': {}", name, e)))?;
+            (rendered, Some(TemplateRef { name: template.name, version: template.version }))
+        }
+        (EmailTemplate::Custom, None) if req.data.get("html").and_then(|v| v.as_str()).is_none() => {
+            return Err(bad_request("Custom template requires 'template_name' or an 'html' field in data"));
+        }
+        (EmailTemplate::Custom, None) if req.subject.is_none() => {
+            return Err(bad_request("Custom template with raw 'html' requires a 'subject'"));
+        }
+        (_, Some(_)) => return Err(bad_request("'template_name' is only supported with the custom template")),
+        (template, None) => {
+            let rendered = state.templates
+                .render(template, &data, req.subject.as_deref(), locale.as_deref())
+                .map_err(|e| bad_request(&format!("Failed to render template: {}", e)))?;
+            (rendered, None)
+        }
+    };
+
+    Ok(PreparedEmail {
+        rendered,
+        data,
+        stored_template,
+        locale,
+        callback_url,
+        stripped,
+        tags,
+    })
+}
+
+/// Get queue statistics, optionally only for jobs with a tag or metadata
+pub async fn queue_stats(
+    state: web::Data<AppState>,
+    query: web::Query<StatsQuery>,
+    params: web::Query<HashMap<String, String>>,
+) -> HttpResponse {
+    let filter = StatsQuery { metadata: metadata_filter(&params), ..query.into_inner() };
+    let stats = if filter.is_empty() {
+        state.queue.stats().await
+    } else {
+        state.queue.fold_matching_jobs(&filter, QueueStats::default(), QueueStats::add).await
+    };
+
+    match stats {
         Ok(stats) => HttpResponse::Ok().json(stats),
         Err(e) => {
             log::error!("Failed to get queue stats: {}", e);
@@ -122,6 +400,102 @@ pub async fn queue_stats(state: web::Data<AppState>) -> HttpResponse {
     }
 }
 
+/// Prometheus metrics in text exposition format
+pub async fn metrics(state: web::Data<AppState>) -> HttpResponse {
+    // Queue depth is read from Redis on scrape rather than tracked per change
+    match state.queue.depth().await {
+        Ok(lanes) => {
+            for (lane, depth) in lanes {
+                METRICS.queue_depth.with_label_values(&[lane]).set(depth as i64);
+            }
+        }
+        Err(e) => log::error!("Failed to read queue depth: {}", e),
+    }
+
+    match METRICS.encode() {
+        Ok(body) => HttpResponse::Ok()
+            .content_type("text/plain; version=0.0.4; charset=utf-8")
+            .body(body),
+        Err(e) => {
+            log::error!("Failed to encode metrics: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": format!("Failed to encode metrics: {}", e)
+            }))
+        }
+    }
+}
+
+/// Get sent/open/click counters per template, optionally only for jobs
+/// with a tag or metadata
+pub async fn template_stats(
+    state: web::Data<AppState>,
+    query: web::Query<StatsQuery>,
+    params: web::Query<HashMap<String, String>>,
+) -> HttpResponse {
+    let filter = StatsQuery { metadata: metadata_filter(&params), ..query.into_inner() };
+    let stats = if filter.is_empty() {
+        state.queue.template_stats().await
+    } else {
+        state.queue
+            .fold_matching_jobs(&filter, BTreeMap::new(), TemplateStats::add)
+            .await
+            .map(TemplateStats::collect)
+    };
+
+    match stats {
+        Ok(stats) => HttpResponse::Ok().json(stats),
+        Err(e) => {
+            log::error!("Failed to get template stats: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": format!("Failed to get template stats: {}", e)
+            }))
+        }
+    }
+}
+
+/// Open-tracking pixel; always answers with the image so mail clients never
+/// show a broken one
+pub async fn track_open(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    req: HttpRequest,
+) -> HttpResponse {
+    if let Some(tracker) = &state.tracker
+        && let Some(job_id) = tracker.verify_open(&path.into_inner())
+    {
+        record_tracking_event(&state, &job_id, TrackingEventKind::Open, None, &req).await;
+    }
+
+    HttpResponse::Ok()
+        .content_type("image/gif")
+        .insert_header((header::CACHE_CONTROL, "no-store, no-cache, must-revalidate, private"))
+        .body(PIXEL_GIF)
+}
+
+/// Click-tracking redirect to the link's original URL
+pub async fn track_click(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let verified = state.tracker
+        .as_ref()
+        .and_then(|tracker| tracker.verify_click(&path.into_inner()));
+
+    let Some((job_id, url)) = verified else {
+        return HttpResponse::NotFound().json(json!({
+            "error": "Invalid tracking link"
+        }));
+    };
+
+    record_tracking_event(&state, &job_id, TrackingEventKind::Click, Some(url.clone()), &req).await;
+
+    HttpResponse::Found()
+        .insert_header((header::LOCATION, url))
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .finish()
+}
+
 /// Get job status by ID
 pub async fn job_status(
     state: web::Data<AppState>,
@@ -142,3 +516,626 @@ pub async fn job_status(
         }
     }
 }
+
+/// Search jobs by recipient, template, status, request ID, tag, metadata
+/// (`metadata.user_id=42`) and creation time
+pub async fn list_jobs(
+    state: web::Data<AppState>,
+    query: web::Query<JobQuery>,
+    params: web::Query<HashMap<String, String>>,
+) -> HttpResponse {
+    let query = JobQuery { metadata: metadata_filter(&params), ..query.into_inner() };
+    if let Some(cursor) = &query.cursor
+        && let Err(e) = queue::parse_cursor(cursor)
+    {
+        return HttpResponse::BadRequest().json(json!({
+            "error": e.to_string()
+        }));
+    }
+
+    let limit = query.limit.unwrap_or(50).clamp(1, 500);
+    match state.queue.list_jobs(&query, limit).await {
+        Ok(page) => HttpResponse::Ok().json(page),
+        Err(e) => {
+            log::error!("Failed to list jobs: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": format!("Failed to list jobs: {}", e)
+            }))
+        }
+    }
+}
+
+/// Cancel a job that is still waiting in the queue
+pub async fn cancel_job(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let job_id = path.into_inner();
+
+    let job = match state.queue.get_job(&job_id).await {
+        Ok(Some(job)) => job,
+        Ok(None) => return HttpResponse::NotFound().json(json!({
+            "error": "Job not found"
+        })),
+        Err(e) => {
+            log::error!("Failed to get job status: {}", e);
+            return HttpResponse::InternalServerError().json(json!({
+                "error": format!("Failed to get job status: {}", e)
+            }));
+        }
+    };
+
+    match state.queue.cancel(&job.id).await {
+        Ok(Some(job)) => {
+            if let Some(webhooks) = &state.webhooks
+                && let Err(e) = webhooks.notify(&job, EmailStatus::Cancelled, None).await
+            {
+                log::error!("Failed to queue webhook for {}: {}", job.id, e);
+            }
+            HttpResponse::Ok().json(job)
+        }
+        Ok(None) => HttpResponse::Conflict().json(json!({
+            "error": "Only pending jobs can be cancelled"
+        })),
+        Err(e) => {
+            log::error!("Failed to cancel job: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": format!("Failed to cancel job: {}", e)
+            }))
+        }
+    }
+}
+
+/// Webhook deliveries for a job
+pub async fn job_webhooks(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let Some(webhooks) = &state.webhooks else {
+        return HttpResponse::Ok().json(Vec::<()>::new());
+    };
+
+    match webhooks.for_job(&path.into_inner()).await {
+        Ok(deliveries) => HttpResponse::Ok().json(deliveries),
+        Err(e) => {
+            log::error!("Failed to get webhook deliveries: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": format!("Failed to get webhook deliveries: {}", e)
+            }))
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct DeliveryQuery {
+    pub status: Option<DeliveryStatus>,
+    pub limit: Option<usize>,
+}
+
+/// Delivery log: most recent webhook deliveries, newest first
+pub async fn webhook_deliveries(
+    state: web::Data<AppState>,
+    query: web::Query<DeliveryQuery>,
+) -> HttpResponse {
+    let Some(webhooks) = &state.webhooks else {
+        return HttpResponse::Ok().json(Vec::<()>::new());
+    };
+
+    let limit = query.limit.unwrap_or(100).min(1000);
+    match webhooks.recent(limit, query.status.clone()).await {
+        Ok(deliveries) => HttpResponse::Ok().json(deliveries),
+        Err(e) => {
+            log::error!("Failed to get webhook deliveries: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": format!("Failed to get webhook deliveries: {}", e)
+            }))
+        }
+    }
+}
+
+/// Ingest a DSN or ARF report posted as raw MIME
+pub async fn ingest_bounce(
+    state: web::Data<AppState>,
+    body: web::Bytes,
+) -> HttpResponse {
+    match bounces::ingest(&state.queue, &state.suppressions, state.webhooks.as_ref(), &body).await {
+        Ok(Some(report)) => HttpResponse::Ok().json(report),
+        Ok(None) => HttpResponse::Accepted().json(json!({
+            "message": "Not a failure report for one of our jobs, ignored"
+        })),
+        Err(e) => {
+            log::error!("Failed to ingest bounce: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": format!("Failed to ingest bounce: {}", e)
+            }))
+        }
+    }
+}
+
+/// List suppressed recipients
+pub async fn list_suppressions(state: web::Data<AppState>) -> HttpResponse {
+    match state.suppressions.list().await {
+        Ok(suppressions) => HttpResponse::Ok().json(suppressions),
+        Err(e) => {
+            log::error!("Failed to list suppressions: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": format!("Failed to list suppressions: {}", e)
+            }))
+        }
+    }
+}
+
+/// Get the suppression for a recipient
+pub async fn get_suppression(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    match state.suppressions.get(&path.into_inner()).await {
+        Ok(Some(suppression)) => HttpResponse::Ok().json(suppression),
+        Ok(None) => HttpResponse::NotFound().json(json!({
+            "error": "Address is not suppressed"
+        })),
+        Err(e) => {
+            log::error!("Failed to get suppression: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": format!("Failed to get suppression: {}", e)
+            }))
+        }
+    }
+}
+
+/// Remove a recipient from the suppression list, e.g. after they fixed their mailbox
+pub async fn delete_suppression(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    match state.suppressions.remove(&path.into_inner()).await {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => HttpResponse::NotFound().json(json!({
+            "error": "Address is not suppressed"
+        })),
+        Err(e) => {
+            log::error!("Failed to remove suppression: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": format!("Failed to remove suppression: {}", e)
+            }))
+        }
+    }
+}
+
+/// Create a stored template (version 1)
+pub async fn create_template(
+    state: web::Data<AppState>,
+    req: web::Json<CreateTemplateRequest>,
+) -> HttpResponse {
+    let req = req.into_inner();
+
+    if let Err(message) = validate_template_name(&req.name) {
+        return bad_request(&message);
+    }
+    if let Err(message) = validate_template_sources(&req.subject, &req.html, req.text.as_deref()) {
+        return bad_request(&message);
+    }
+
+    match state.template_store.create(&req.name, req.subject, req.html, req.text).await {
+        Ok(template) => HttpResponse::Created().json(template),
+        Err(e) => template_store_error(e),
+    }
+}
+
+/// Update a stored template, creating a new version
+pub async fn update_template(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    req: web::Json<UpdateTemplateRequest>,
+) -> HttpResponse {
+    let name = path.into_inner();
+    let req = req.into_inner();
+
+    if let Err(message) = validate_template_sources(&req.subject, &req.html, req.text.as_deref()) {
+        return bad_request(&message);
+    }
+
+    match state.template_store.update(&name, req.subject, req.html, req.text).await {
+        Ok(template) => HttpResponse::Ok().json(template),
+        Err(e) => template_store_error(e),
+    }
+}
+
+/// List built-in and stored templates with their variables and sample data
+pub async fn list_templates(state: web::Data<AppState>) -> HttpResponse {
+    let mut templates: Vec<TemplateSummary> = EmailTemplate::BUILTIN
+        .iter()
+        .map(|template| TemplateSummary {
+            name: template.name().to_string(),
+            source: "builtin",
+            template: template.clone(),
+            variables: template.required_fields().iter().map(|f| f.to_string()).collect(),
+            sample_data: template.sample_data(),
+            latest_version: None,
+            updated_at: None,
+        })
+        .collect();
+
+    let stored = match state.template_store.list().await {
+        Ok(stored) => stored,
+        Err(e) => return template_store_error(e),
+    };
+
+    for info in stored {
+        let template = match state.template_store.get(&info.name, Some(info.latest_version)).await {
+            Ok(template) => template,
+            Err(e) => return template_store_error(e),
+        };
+
+        let mut variables = TemplateEngine::variables(&template.subject);
+        for source in std::iter::once(&template.html).chain(template.text.as_ref()) {
+            for variable in TemplateEngine::variables(source) {
+                if !variables.contains(&variable) {
+                    variables.push(variable);
+                }
+            }
+        }
+        let sample_data = variables
+            .iter()
+            .map(|v| (v.clone(), json!(format!("<{}>", v))))
+            .collect::<serde_json::Map<_, _>>();
+
+        templates.push(TemplateSummary {
+            name: info.name,
+            source: "stored",
+            template: EmailTemplate::Custom,
+            variables,
+            sample_data: serde_json::Value::Object(sample_data),
+            latest_version: Some(info.latest_version),
+            updated_at: Some(info.updated_at),
+        });
+    }
+
+    HttpResponse::Ok().json(templates)
+}
+
+/// Get the latest version of a stored template (or `?version=N`)
+pub async fn get_template(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    query: web::Query<VersionQuery>,
+) -> HttpResponse {
+    let name = path.into_inner();
+
+    match state.template_store.get(&name, query.version).await {
+        Ok(template) => HttpResponse::Ok().json(template),
+        Err(e) => template_store_error(e),
+    }
+}
+
+/// List all versions of a stored template
+pub async fn template_versions(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let name = path.into_inner();
+
+    match state.template_store.versions(&name).await {
+        Ok(versions) => HttpResponse::Ok().json(versions),
+        Err(e) => template_store_error(e),
+    }
+}
+
+/// Roll a stored template back to an earlier version
+pub async fn rollback_template(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    req: web::Json<RollbackTemplateRequest>,
+) -> HttpResponse {
+    let name = path.into_inner();
+
+    match state.template_store.rollback(&name, req.version).await {
+        Ok(template) => HttpResponse::Ok().json(template),
+        Err(e) => template_store_error(e),
+    }
+}
+
+/// Render a stored template with sample data without sending it
+pub async fn preview_template(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    req: web::Json<PreviewTemplateRequest>,
+) -> HttpResponse {
+    let name = path.into_inner();
+
+    let template = match state.template_store.get(&name, req.version).await {
+        Ok(template) => template,
+        Err(e) => return template_store_error(e),
+    };
+
+    let locale = match parse_locale(req.locale.as_deref()) {
+        Ok(locale) => locale,
+        Err(response) => return response,
+    };
+
+    match state.templates.render_stored(&template, &req.data, None, locale.as_deref()) {
+        Ok(rendered) => HttpResponse::Ok().json(rendered),
+        Err(e) => bad_request(&format!("Failed to render template: {}", e)),
+    }
+}
+
+/// Delete a stored template and all of its versions
+pub async fn delete_template(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let name = path.into_inner();
+
+    match state.template_store.delete(&name).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => template_store_error(e),
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct VersionQuery {
+    pub version: Option<u32>,
+}
+
+fn validate_template_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-');
+
+    if valid {
+        Ok(())
+    } else {
+        Err("Template name must be 1-64 characters of a-z, 0-9, '_' or '-'".to_string())
+    }
+}
+
+fn validate_template_sources(subject: &str, html: &str, text: Option<&str>) -> Result<(), String> {
+    TemplateEngine::validate(subject).map_err(|e| format!("Invalid subject template: {}", e))?;
+    TemplateEngine::validate(html).map_err(|e| format!("Invalid HTML template: {}", e))?;
+    if let Some(text) = text {
+        TemplateEngine::validate(text).map_err(|e| format!("Invalid text template: {}", e))?;
+    }
+    Ok(())
+}
+
+/// Reject a request whose `data` lacks variables the template requires
+fn check_required_fields(template: &EmailTemplate, data: &serde_json::Value) -> Option<HttpResponse> {
+    let missing = template.missing_fields(data);
+    if missing.is_empty() {
+        return None;
+    }
+
+    Some(HttpResponse::BadRequest().json(json!({
+        "success": false,
+        "job_id": null,
+        "message": format!("Missing required fields: {}", missing.join(", ")),
+        "missing_fields": missing,
+    })))
+}
+
+/// Normalize an optional locale tag, rejecting malformed ones
+fn parse_locale(locale: Option<&str>) -> Result<Option<String>, HttpResponse> {
+    match locale {
+        None => Ok(None),
+        Some(locale) => helpers::normalize_locale(locale)
+            .map(Some)
+            .ok_or_else(|| bad_request(&format!("Invalid locale '{}'", locale))),
+    }
+}
+
+/// Tracking failures are logged but never break the pixel or redirect
+async fn record_tracking_event(
+    state: &AppState,
+    job_id: &str,
+    kind: TrackingEventKind,
+    url: Option<String>,
+    req: &HttpRequest,
+) {
+    let user_agent = req.headers()
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .map(|ua| ua.chars().take(256).collect());
+
+    let event = TrackingEvent {
+        kind,
+        url,
+        user_agent,
+        at: chrono::Utc::now(),
+    };
+
+    match state.queue.record_event(job_id, event).await {
+        Ok(true) => {}
+        Ok(false) => log::warn!("Tracking event for unknown job {}", job_id),
+        Err(e) => log::error!("Failed to record tracking event for {}: {}", job_id, e),
+    }
+}
+
+/// New jobs are refused once shutdown has started, so clients retry against
+/// another instance instead of queueing mail nobody will send
+fn check_accepting(state: &AppState) -> Option<HttpResponse> {
+    if !state.shutdown.is_triggered() {
+        return None;
+    }
+    Some(HttpResponse::ServiceUnavailable()
+        .insert_header((header::RETRY_AFTER, "5"))
+        .json(json!({ "error": "Mailer is shutting down" })))
+}
+
+/// Refuse to queue mail for recipients that hard-bounced or complained
+async fn check_suppressed(state: &AppState, email: &str) -> Option<HttpResponse> {
+    match state.suppressions.get(email).await {
+        Ok(Some(suppression)) => Some(HttpResponse::UnprocessableEntity().json(EmailResponse {
+            success: false,
+            job_id: None,
+            message: format!("Recipient {} is suppressed ({})", suppression.email, suppression.reason.as_str()),
+            stripped: Vec::new(),
+        })),
+        Ok(None) => None,
+        Err(e) => {
+            // The suppression list is a safeguard; don't block sending on Redis hiccups
+            log::error!("Failed to check suppression list: {}", e);
+            None
+        }
+    }
+}
+
+/// Per-request callback URLs need webhooks to be configured (for signing)
+fn parse_callback_url(state: &AppState, url: Option<&str>) -> Result<Option<String>, HttpResponse> {
+    let Some(url) = url.map(str::trim).filter(|u| !u.is_empty()) else {
+        return Ok(None);
+    };
+    let Some(webhooks) = &state.webhooks else {
+        return Err(bad_request("Webhooks are not configured on this mailer"));
+    };
+    webhooks.validate_url(url).map_err(|e| bad_request(&e.to_string()))?;
+    Ok(Some(url.to_string()))
+}
+
+/// Check request metadata and tags, returning the tags de-duplicated
+///
+/// Both are indexed for `/jobs` and stats, so their number and size are
+/// bounded.
+fn validate_labels(metadata: &BTreeMap<String, String>, tags: &[String]) -> Result<Vec<String>, HttpResponse> {
+    if metadata.len() > MAX_LABELS {
+        return Err(bad_request(&format!("At most {} metadata entries are allowed", MAX_LABELS)));
+    }
+    for (key, value) in metadata {
+        if key.is_empty()
+            || key.len() > MAX_LABEL_LEN
+            || !key.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+        {
+            return Err(bad_request(&format!(
+                "Invalid metadata key '{}': use up to {} letters, digits, '_', '-' or '.'",
+                key, MAX_LABEL_LEN
+            )));
+        }
+        if value.is_empty() || value.len() > MAX_METADATA_VALUE_LEN {
+            return Err(bad_request(&format!(
+                "Metadata '{}' must be between 1 and {} bytes",
+                key, MAX_METADATA_VALUE_LEN
+            )));
+        }
+    }
+
+    let mut unique: Vec<String> = Vec::new();
+    for tag in tags {
+        if tag.is_empty() || tag.len() > MAX_LABEL_LEN || tag.chars().any(|c| c.is_whitespace() || c.is_control()) {
+            return Err(bad_request(&format!(
+                "Invalid tag '{}': use up to {} characters without spaces",
+                tag, MAX_LABEL_LEN
+            )));
+        }
+        if !unique.contains(tag) {
+            unique.push(tag.clone());
+        }
+    }
+    if unique.len() > MAX_LABELS {
+        return Err(bad_request(&format!("At most {} tags are allowed", MAX_LABELS)));
+    }
+
+    Ok(unique)
+}
+
+/// `metadata.{key}={value}` query parameters
+fn metadata_filter(params: &HashMap<String, String>) -> BTreeMap<String, String> {
+    params
+        .iter()
+        .filter_map(|(name, value)| Some((name.strip_prefix("metadata.")?.to_string(), value.clone())))
+        .collect()
+}
+
+fn bad_request(message: &str) -> HttpResponse {
+    HttpResponse::BadRequest().json(json!({
+        "error": message
+    }))
+}
+
+fn template_store_error(e: TemplateStoreError) -> HttpResponse {
+    match e {
+        TemplateStoreError::NotFound(_) | TemplateStoreError::VersionNotFound { .. } => {
+            HttpResponse::NotFound().json(json!({ "error": e.to_string() }))
+        }
+        TemplateStoreError::AlreadyExists(_) => {
+            HttpResponse::Conflict().json(json!({ "error": e.to_string() }))
+        }
+        _ => {
+            log::error!("Template store error: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": format!("Template store error: {}", e)
+            }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(metadata: &[(&str, &str)], tags: &[&str]) -> Result<Vec<String>, HttpResponse> {
+        let metadata = metadata.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        let tags: Vec<String> = tags.iter().map(|t| t.to_string()).collect();
+        validate_labels(&metadata, &tags)
+    }
+
+    #[test]
+    fn test_validate_labels() {
+        assert_eq!(
+            labels(&[("user_id", "42"), ("license.id", "kc-1")], &["signup", "beta", "signup"]).unwrap(),
+            ["signup", "beta"]
+        );
+        assert!(labels(&[], &[]).unwrap().is_empty());
+
+        for metadata in [
+            &[("", "x")][..],
+            &[("user id", "x")],
+            &[("user/id", "x")],
+            &[("user_id", "")],
+        ] {
+            let response = labels(metadata, &[]).unwrap_err();
+            assert_eq!(response.status(), actix_web::http::StatusCode::BAD_REQUEST, "{:?}", metadata);
+        }
+        assert!(labels(&[(&"k".repeat(MAX_LABEL_LEN + 1), "x")], &[]).is_err());
+        assert!(labels(&[("k", &"v".repeat(MAX_METADATA_VALUE_LEN + 1))], &[]).is_err());
+        assert!(labels(&[("k", &"v".repeat(MAX_METADATA_VALUE_LEN))], &[]).is_ok());
+
+        for tag in ["", "two words", "tab\t", &"t".repeat(MAX_LABEL_LEN + 1)] {
+            assert!(labels(&[], &[tag]).is_err(), "{:?}", tag);
+        }
+
+        let keys: Vec<String> = (0..=MAX_LABELS).map(|i| format!("k{}", i)).collect();
+        let metadata: Vec<(&str, &str)> = keys.iter().map(|k| (k.as_str(), "v")).collect();
+        assert!(labels(&metadata, &[]).is_err());
+        assert!(labels(&metadata[..MAX_LABELS], &[]).is_ok());
+
+        let tags: Vec<&str> = keys.iter().map(String::as_str).collect();
+        assert!(labels(&[], &tags).is_err());
+        // Duplicates count once
+        let repeated = vec!["same"; MAX_LABELS + 5];
+        assert_eq!(labels(&[], &repeated).unwrap(), ["same"]);
+    }
+
+    #[test]
+    fn test_metadata_filter() {
+        let params: HashMap<String, String> = [
+            ("metadata.user_id", "42"),
+            ("metadata.license.id", "kc-1"),
+            ("tag", "signup"),
+            ("metadata", "x"),
+            ("meta.user_id", "1"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+        let filter = metadata_filter(&params);
+        assert_eq!(
+            filter.into_iter().collect::<Vec<_>>(),
+            [
+                ("license.id".to_string(), "kc-1".to_string()),
+                ("user_id".to_string(), "42".to_string()),
+            ]
+        );
+        assert!(metadata_filter(&HashMap::new()).is_empty());
+    }
+}
@@ -0,0 +1,110 @@
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use tokio::sync::Mutex;
+
+use crate::models::{ComponentHealth, ComponentStatus};
+use crate::SmtpClient;
+
+/// Checks behind `/health/ready`
+pub struct HealthChecks {
+    /// Beaten by the email worker on every loop iteration
+    pub heartbeat: Heartbeat,
+    /// Heartbeats older than this mark the worker as down; must exceed the
+    /// longest SMTP transaction
+    worker_timeout: Duration,
+    smtp_probe: Option<SmtpProbe>,
+}
+
+impl HealthChecks {
+    pub fn new(worker_timeout: Duration) -> Self {
+        Self {
+            heartbeat: Heartbeat::default(),
+            worker_timeout,
+            smtp_probe: None,
+        }
+    }
+
+    /// Also check the SMTP relay (connect, EHLO, AUTH, NOOP), at most once
+    /// per `interval` so orchestrator probes don't hammer it
+    pub fn with_smtp_probe(mut self, interval: Duration) -> Self {
+        self.smtp_probe = Some(SmtpProbe {
+            interval,
+            last: Mutex::new(None),
+        });
+        self
+    }
+
+    pub fn worker(&self) -> ComponentHealth {
+        match self.heartbeat.age() {
+            Some(age) => {
+                let stale = age > self.worker_timeout;
+                ComponentHealth {
+                    last_heartbeat_secs: Some(age.as_secs_f64()),
+                    error: stale.then(|| format!("No heartbeat for {}s", age.as_secs())),
+                    ..ComponentHealth::status(if stale { ComponentStatus::Down } else { ComponentStatus::Up })
+                }
+            }
+            None => ComponentHealth {
+                error: Some("Worker has not started".to_string()),
+                ..ComponentHealth::status(ComponentStatus::Down)
+            },
+        }
+    }
+
+    pub async fn smtp(&self, smtp: &SmtpClient) -> ComponentHealth {
+        let Some(probe) = &self.smtp_probe else {
+            return ComponentHealth::status(ComponentStatus::Disabled);
+        };
+
+        // Holding the lock makes concurrent probes wait for one check
+        let mut last = probe.last.lock().await;
+        if let Some((at, health)) = last.as_ref()
+            && at.elapsed() < probe.interval
+        {
+            return health.clone();
+        }
+
+        let started = Instant::now();
+        let result = smtp.check_connection().await;
+        let health = ComponentHealth {
+            latency_ms: Some(millis(started.elapsed())),
+            checked_at: Some(Utc::now()),
+            error: result.as_ref().err().map(|e| e.to_string()),
+            ..ComponentHealth::status(if result.is_ok() { ComponentStatus::Up } else { ComponentStatus::Down })
+        };
+        *last = Some((Instant::now(), health.clone()));
+        health
+    }
+}
+
+struct SmtpProbe {
+    interval: Duration,
+    last: Mutex<Option<(Instant, ComponentHealth)>>,
+}
+
+/// Last time a worker loop ran
+#[derive(Default)]
+pub struct Heartbeat {
+    /// Unix milliseconds, 0 before the first beat
+    last_beat_ms: AtomicI64,
+}
+
+impl Heartbeat {
+    pub fn beat(&self) {
+        self.last_beat_ms.store(Utc::now().timestamp_millis(), Ordering::Relaxed);
+    }
+
+    pub fn age(&self) -> Option<Duration> {
+        match self.last_beat_ms.load(Ordering::Relaxed) {
+            0 => None,
+            last => Some(Duration::from_millis((Utc::now().timestamp_millis() - last).max(0) as u64)),
+        }
+    }
+}
+
+/// Milliseconds with microsecond precision, for latency fields
+pub fn millis(duration: Duration) -> f64 {
+    duration.as_micros() as f64 / 1000.0
+}
@@ -0,0 +1,549 @@
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Locale, NaiveDate, Utc};
+use chrono_tz::Tz;
+use handlebars::{Context, Handlebars, Helper, HelperResult, Output, RenderContext, RenderErrorReason};
+
+/// Locale used when a request doesn't specify one
+pub const DEFAULT_LOCALE: &str = "en";
+
+/// Key under which `TemplateEngine` adds its own values (`year`, `locale`)
+/// to the template data
+pub const MAILER_KEY: &str = "_mailer";
+
+/// Register the custom helpers on a registry
+pub fn register_helpers(hbs: &mut Handlebars<'static>) {
+    hbs.register_helper("format_date", Box::new(format_date));
+    hbs.register_helper("relative_time", Box::new(relative_time));
+    hbs.register_helper("currency", Box::new(currency));
+    hbs.register_helper("pluralize", Box::new(pluralize));
+    hbs.register_helper("url", Box::new(url));
+    hbs.register_helper("truncate", Box::new(truncate));
+}
+
+/// Normalize a locale tag to `ll` or `ll-RR` (e.g. `pt_br` -> `pt-BR`)
+///
+/// Returns `None` for anything that doesn't look like a language tag.
+pub fn normalize_locale(locale: &str) -> Option<String> {
+    let mut parts = locale.trim().split(['-', '_']);
+    let language = parts.next()?.to_ascii_lowercase();
+    if !(2..=3).contains(&language.len()) || !language.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    match parts.next() {
+        None => Some(language),
+        Some(region) if (2..=3).contains(&region.len()) && region.chars().all(|c| c.is_ascii_alphanumeric()) => {
+            Some(format!("{}-{}", language, region.to_ascii_uppercase()))
+        }
+        Some(_) => None,
+    }
+}
+
+/// Locales to try in order, e.g. `pt-BR` -> `pt-BR`, `pt`, `en`
+pub fn locale_chain(locale: &str) -> Vec<String> {
+    let mut chain = Vec::new();
+
+    if let Some(locale) = normalize_locale(locale) {
+        if let Some((language, _)) = locale.split_once('-') {
+            let language = language.to_string();
+            chain.push(locale);
+            chain.push(language);
+        } else {
+            chain.push(locale);
+        }
+    }
+
+    if !chain.iter().any(|l| l == DEFAULT_LOCALE) {
+        chain.push(DEFAULT_LOCALE.to_string());
+    }
+
+    chain
+}
+
+/// Map a locale tag to a chrono locale for month/day names
+fn chrono_locale(locale: &str) -> Locale {
+    let Some(locale) = normalize_locale(locale) else {
+        return Locale::en_US;
+    };
+
+    let (language, region) = match locale.split_once('-') {
+        Some((language, region)) => (language.to_string(), Some(region.to_string())),
+        None => (locale.clone(), None),
+    };
+
+    let candidates = [
+        region.map(|r| format!("{}_{}", language, r)),
+        Some(format!("{}_{}", language, language.to_ascii_uppercase())),
+        (language == "en").then(|| "en_US".to_string()),
+    ];
+
+    candidates
+        .into_iter()
+        .flatten()
+        .find_map(|c| c.parse::<Locale>().ok())
+        .unwrap_or(Locale::en_US)
+}
+
+/// Parse an RFC 3339 timestamp, a `YYYY-MM-DD` date or a unix timestamp
+pub(crate) fn parse_datetime(value: &serde_json::Value) -> Option<DateTime<Utc>> {
+    match value {
+        serde_json::Value::String(s) => DateTime::parse_from_rfc3339(s)
+            .map(|dt| dt.with_timezone(&Utc))
+            .ok()
+            .or_else(|| {
+                NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                    .ok()
+                    .and_then(|d| d.and_hms_opt(0, 0, 0))
+                    .map(|dt| dt.and_utc())
+            }),
+        serde_json::Value::Number(n) => n.as_i64().and_then(|ts| DateTime::from_timestamp(ts, 0)),
+        _ => None,
+    }
+}
+
+/// Write helper output through the registry's escape function
+///
+/// Helper output isn't escaped by Handlebars itself, so values from request
+/// data would otherwise land in the HTML verbatim.
+fn write_escaped(r: &Handlebars, out: &mut dyn Output, value: &str) -> Result<(), std::io::Error> {
+    out.write(&r.get_escape_fn()(value))
+}
+
+/// Locale of the email being rendered (injected by `TemplateEngine`)
+fn context_locale(ctx: &Context) -> &str {
+    ctx.data()
+        .get(MAILER_KEY)
+        .and_then(|m| m.get("locale"))
+        .and_then(|l| l.as_str())
+        .unwrap_or(DEFAULT_LOCALE)
+}
+
+/// Value of a helper argument
+///
+/// Handlebars passes variables missing from the data to helpers as `null`
+/// even in strict mode, so they are refused here like in plain expressions.
+fn param_value<'a>(h: &'a Helper, r: &Handlebars, index: usize, name: &'static str) -> Result<&'a serde_json::Value, RenderErrorReason> {
+    let param = h.param(index).ok_or(RenderErrorReason::ParamNotFoundForIndex(name, index))?;
+    if r.strict_mode() && param.is_value_missing() {
+        return Err(RenderErrorReason::MissingVariable(param.relative_path().cloned()));
+    }
+    Ok(param.value())
+}
+
+/// Parse a number given as a JSON number or numeric string
+fn param_number(h: &Helper, r: &Handlebars, index: usize, name: &'static str) -> Result<f64, RenderErrorReason> {
+    let value = param_value(h, r, index, name)?;
+
+    match value {
+        serde_json::Value::Number(n) => n.as_f64(),
+        serde_json::Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+    .ok_or_else(|| RenderErrorReason::InvalidParamType("number"))
+}
+
+fn param_str<'a>(h: &'a Helper, r: &Handlebars, index: usize, name: &'static str) -> Result<&'a str, RenderErrorReason> {
+    param_value(h, r, index, name)?
+        .as_str()
+        .ok_or(RenderErrorReason::InvalidParamType("string"))
+}
+
+/// `{{format_date value [style] [tz="Europe/Berlin"]}}` formats a date in the email's locale
+///
+/// `style` is `long` (default, e.g. "December 31, 2026"), `short` (the
+/// locale's numeric date), `time`, `datetime`, or a strftime format string.
+/// The time zone comes from `tz`, then a `timezone` field in the data, then UTC.
+/// Values that can't be parsed as a date are written unchanged.
+fn format_date(
+    h: &Helper,
+    r: &Handlebars,
+    ctx: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let value = param_value(h, r, 0, "format_date")?;
+
+    let Some(datetime) = parse_datetime(value) else {
+        write_escaped(r, out, value.as_str().unwrap_or_default())?;
+        return Ok(());
+    };
+
+    let locale = context_locale(ctx);
+    let style = h.param(1).and_then(|p| p.value().as_str()).unwrap_or("long");
+    let timezone = h
+        .hash_get("tz")
+        .and_then(|v| v.value().as_str())
+        .or_else(|| ctx.data().get("timezone").and_then(|v| v.as_str()));
+    let timezone: Tz = match timezone {
+        Some(name) => name
+            .parse()
+            .map_err(|_| RenderErrorReason::Other(format!("Unknown time zone: {}", name)))?,
+        None => Tz::UTC,
+    };
+
+    write_escaped(r, out, &format_datetime(datetime, style, timezone, locale)?)?;
+    Ok(())
+}
+
+/// Format a UTC timestamp in `timezone` using a style or strftime format
+pub fn format_datetime(datetime: DateTime<Utc>, style: &str, timezone: Tz, locale: &str) -> Result<String, RenderErrorReason> {
+    let format = match style {
+        "short" => "%x",
+        "time" => "%H:%M %Z",
+        "datetime" if locale.starts_with("en") => "%B %-d, %Y %H:%M %Z",
+        "datetime" => "%-d %B %Y %H:%M %Z",
+        "long" if locale.starts_with("en") => "%B %-d, %Y",
+        "long" => "%-d %B %Y",
+        custom => custom,
+    };
+
+    let chrono_locale = chrono_locale(locale);
+    let items = StrftimeItems::new_with_locale(format, chrono_locale);
+    if items.clone().any(|item| matches!(item, Item::Error)) {
+        return Err(RenderErrorReason::Other(format!("Invalid date format: {}", format)));
+    }
+
+    Ok(datetime
+        .with_timezone(&timezone)
+        .format_localized_with_items(items, chrono_locale)
+        .to_string())
+}
+
+/// `{{relative_time value}}` renders e.g. "in 3 days" or "2 hours ago"
+///
+/// English only: the output ignores the email's locale.
+fn relative_time(
+    h: &Helper,
+    r: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let value = param_value(h, r, 0, "relative_time")?;
+
+    match parse_datetime(value) {
+        Some(datetime) => write_escaped(r, out, &describe_relative(datetime, Utc::now()))?,
+        None => write_escaped(r, out, value.as_str().unwrap_or_default())?,
+    }
+    Ok(())
+}
+
+/// Describe `target` relative to `now`, rounding to the nearest unit
+pub fn describe_relative(target: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    const UNITS: &[(i64, &str)] = &[
+        (365 * 24 * 3600, "year"),
+        (30 * 24 * 3600, "month"),
+        (24 * 3600, "day"),
+        (3600, "hour"),
+        (60, "minute"),
+    ];
+
+    let seconds = (target - now).num_seconds();
+    let magnitude = seconds.abs();
+
+    let Some((unit_seconds, unit)) = UNITS.iter().find(|(unit_seconds, _)| magnitude >= *unit_seconds) else {
+        return "just now".to_string();
+    };
+
+    let count = (magnitude + unit_seconds / 2) / unit_seconds;
+    let amount = pluralize_word(count as f64, unit, None);
+
+    if seconds > 0 {
+        format!("in {}", amount)
+    } else {
+        format!("{} ago", amount)
+    }
+}
+
+/// `{{currency amount "EUR" [minor=true]}}` formats an amount in the email's locale
+///
+/// With `minor=true` the amount is in minor units (cents).
+fn currency(
+    h: &Helper,
+    r: &Handlebars,
+    ctx: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let amount = param_number(h, r, 0, "currency")?;
+    let code = param_str(h, r, 1, "currency")?;
+    let minor = h.hash_get("minor").and_then(|v| v.value().as_bool()).unwrap_or(false);
+
+    let amount = if minor {
+        amount / 10f64.powi(currency_decimals(code) as i32)
+    } else {
+        amount
+    };
+
+    write_escaped(r, out, &format_currency(amount, code, context_locale(ctx)))?;
+    Ok(())
+}
+
+fn currency_decimals(code: &str) -> usize {
+    match code.to_ascii_uppercase().as_str() {
+        "JPY" | "KRW" | "VND" | "CLP" | "ISK" => 0,
+        _ => 2,
+    }
+}
+
+/// Format a money amount with the locale's separators and the currency symbol
+pub fn format_currency(amount: f64, code: &str, locale: &str) -> String {
+    let code = code.to_ascii_uppercase();
+    let language = locale.split(['-', '_']).next().unwrap_or(DEFAULT_LOCALE);
+
+    let (group, decimal) = match language {
+        "de" | "pt" | "es" | "it" | "nl" | "id" | "tr" | "da" => (".", ","),
+        "fr" | "ru" | "pl" | "cs" | "sv" | "fi" | "nb" | "uk" => ("\u{a0}", ","),
+        _ => (",", "."),
+    };
+
+    let formatted = format!("{:.*}", currency_decimals(&code), amount.abs());
+    let (integer, fraction) = formatted.split_once('.').unwrap_or((&formatted, ""));
+
+    let mut grouped = String::new();
+    for (i, digit) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            grouped.push_str(group);
+        }
+        grouped.push(digit);
+    }
+    if !fraction.is_empty() {
+        grouped.push_str(decimal);
+        grouped.push_str(fraction);
+    }
+
+    let sign = if amount < 0.0 && formatted.chars().any(|c| c.is_ascii_digit() && c != '0') { "-" } else { "" };
+    let symbol = match code.as_str() {
+        "USD" => Some("$"),
+        "EUR" => Some("€"),
+        "GBP" => Some("£"),
+        "INR" => Some("₹"),
+        "JPY" => Some("¥"),
+        _ => None,
+    };
+
+    match symbol {
+        Some(symbol) if language == "en" => format!("{}{}{}", sign, symbol, grouped),
+        Some(symbol) => format!("{}{}\u{a0}{}", sign, grouped, symbol),
+        None => format!("{}{}\u{a0}{}", sign, grouped, code),
+    }
+}
+
+/// `{{pluralize count "license" ["licenses"]}}` renders e.g. "3 licenses"
+///
+/// Uses English plural rules (one vs. other) whatever the email's locale;
+/// localized templates pass their own plural form.
+fn pluralize(
+    h: &Helper,
+    r: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let count = param_number(h, r, 0, "pluralize")?;
+    let singular = param_str(h, r, 1, "pluralize")?;
+    let plural = h.param(2).and_then(|p| p.value().as_str());
+
+    write_escaped(r, out, &pluralize_word(count, singular, plural))?;
+    Ok(())
+}
+
+/// `count` followed by the singular or plural form (default: singular + "s")
+pub fn pluralize_word(count: f64, singular: &str, plural: Option<&str>) -> String {
+    let word = if count == 1.0 {
+        singular.to_string()
+    } else {
+        plural.map(str::to_string).unwrap_or_else(|| format!("{}s", singular))
+    };
+    format!("{} {}", count, word)
+}
+
+/// `{{url "https://killcode.app/dashboard" utm_campaign="license_created"}}`
+///
+/// Appends every hash argument as a query parameter (after any existing
+/// ones), percent-encoding values; useful for tracking params on links.
+fn url(
+    h: &Helper,
+    r: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let base = param_str(h, r, 0, "url")?;
+
+    let params = h
+        .hash()
+        .iter()
+        .map(|(key, value)| {
+            let value = match value.value() {
+                serde_json::Value::String(s) => s.clone(),
+                serde_json::Value::Null => String::new(),
+                other => other.to_string(),
+            };
+            (key.to_string(), value)
+        })
+        .collect::<Vec<_>>();
+
+    write_escaped(r, out, &build_url(base, &params)?)?;
+    Ok(())
+}
+
+/// Append query parameters to an absolute URL
+pub fn build_url(base: &str, params: &[(String, String)]) -> Result<String, RenderErrorReason> {
+    let mut url = url::Url::parse(base)
+        .map_err(|e| RenderErrorReason::Other(format!("Invalid URL '{}': {}", base, e)))?;
+
+    if !params.is_empty() {
+        let mut query = url.query_pairs_mut();
+        for (key, value) in params {
+            query.append_pair(key, value);
+        }
+    }
+
+    Ok(url.to_string())
+}
+
+/// `{{truncate text 100 ["…"]}}` shortens text without splitting characters
+fn truncate(
+    h: &Helper,
+    r: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let text = param_value(h, r, 0, "truncate")?;
+    let text = match text {
+        serde_json::Value::String(s) => s.clone(),
+        serde_json::Value::Null => String::new(),
+        other => other.to_string(),
+    };
+    let length = param_number(h, r, 1, "truncate")?.max(0.0) as usize;
+    let suffix = h.param(2).and_then(|p| p.value().as_str()).unwrap_or("…");
+
+    write_escaped(r, out, &truncate_text(&text, length, suffix))?;
+    Ok(())
+}
+
+/// Shorten `text` to at most `max_chars` characters including `suffix`
+///
+/// Cuts on a character boundary, preferring the last word break when one
+/// falls in the second half of the kept text.
+pub fn truncate_text(text: &str, max_chars: usize, suffix: &str) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+
+    let keep = max_chars.saturating_sub(suffix.chars().count());
+    let cut = text.char_indices().nth(keep).map(|(i, _)| i).unwrap_or(text.len());
+    let mut kept = &text[..cut];
+
+    if let Some(space) = kept.rfind(char::is_whitespace)
+        && kept[..space].chars().count() >= keep / 2
+    {
+        kept = &kept[..space];
+    }
+
+    format!("{}{}", kept.trim_end(), suffix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    fn registry() -> Handlebars<'static> {
+        let mut hbs = Handlebars::new();
+        hbs.set_strict_mode(true);
+        register_helpers(&mut hbs);
+        hbs
+    }
+
+    fn render(template: &str, data: serde_json::Value) -> String {
+        registry().render_template(template, &data).unwrap()
+    }
+
+    #[test]
+    fn test_locale_chain_falls_back_to_language_then_default() {
+        assert_eq!(locale_chain("pt_br"), vec!["pt-BR", "pt", "en"]);
+        assert_eq!(locale_chain("de"), vec!["de", "en"]);
+        assert_eq!(locale_chain("en-GB"), vec!["en-GB", "en"]);
+        assert_eq!(locale_chain("not a locale"), vec!["en"]);
+    }
+
+    #[test]
+    fn test_format_date_uses_locale_style_and_time_zone() {
+        let data = json!({ "d": "2026-12-31T23:30:00Z", "_mailer": { "locale": "en" } });
+        assert_eq!(render("{{format_date d}}", data.clone()), "December 31, 2026");
+        assert_eq!(render(r#"{{format_date d "long" tz="Asia/Tokyo"}}"#, data.clone()), "January 1, 2027");
+        assert_eq!(render(r#"{{format_date d "%Y-%m-%d %H:%M"}}"#, data), "2026-12-31 23:30");
+
+        let data = json!({ "d": "2026-03-05", "_mailer": { "locale": "pt-BR" } });
+        assert_eq!(render("{{format_date d}}", data), "5 março 2026");
+
+        let data = json!({ "d": 1767225600, "timezone": "America/New_York" });
+        assert_eq!(render(r#"{{format_date d "time"}}"#, data), "19:00 EST");
+    }
+
+    #[test]
+    fn test_format_date_passes_through_unparseable_values() {
+        assert_eq!(render("{{format_date d}}", json!({ "d": "never" })), "never");
+        assert!(registry().render_template(r#"{{format_date d "%Q"}}"#, &json!({ "d": "2026-01-01" })).is_err());
+        assert!(registry().render_template(r#"{{format_date d tz="Mars/Base"}}"#, &json!({ "d": "2026-01-01" })).is_err());
+    }
+
+    #[test]
+    fn test_relative_time_rounds_to_nearest_unit() {
+        let now = Utc.with_ymd_and_hms(2026, 1, 1, 12, 0, 0).unwrap();
+        assert_eq!(describe_relative(now + chrono::Duration::hours(71), now), "in 3 days");
+        assert_eq!(describe_relative(now - chrono::Duration::minutes(1), now), "1 minute ago");
+        assert_eq!(describe_relative(now - chrono::Duration::hours(5), now), "5 hours ago");
+        assert_eq!(describe_relative(now + chrono::Duration::seconds(20), now), "just now");
+        assert_eq!(describe_relative(now + chrono::Duration::days(400), now), "in 1 year");
+
+        let soon = (Utc::now() + chrono::Duration::days(3)).to_rfc3339();
+        assert_eq!(render("{{relative_time d}}", json!({ "d": soon })), "in 3 days");
+    }
+
+    #[test]
+    fn test_currency_formats_per_locale() {
+        assert_eq!(format_currency(1234.5, "usd", "en"), "$1,234.50");
+        assert_eq!(format_currency(1234.5, "EUR", "de-DE"), "1.234,50\u{a0}€");
+        assert_eq!(format_currency(-5.0, "CHF", "en"), "-5.00\u{a0}CHF");
+        assert_eq!(format_currency(1500.0, "JPY", "en"), "¥1,500");
+        assert_eq!(render(r#"{{currency amount "USD" minor=true}}"#, json!({ "amount": 999 })), "$9.99");
+        assert_eq!(render(r#"{{currency amount "EUR"}}"#, json!({ "amount": "12", "_mailer": { "locale": "fr" } })), "12,00\u{a0}€");
+    }
+
+    #[test]
+    fn test_pluralize_picks_form_by_count() {
+        assert_eq!(render(r#"{{pluralize n "license"}}"#, json!({ "n": 1 })), "1 license");
+        assert_eq!(render(r#"{{pluralize n "license"}}"#, json!({ "n": 3 })), "3 licenses");
+        assert_eq!(render(r#"{{pluralize n "binary" "binaries"}}"#, json!({ "n": 0 })), "0 binaries");
+    }
+
+    #[test]
+    fn test_url_appends_tracking_params() {
+        assert_eq!(
+            render(
+                r#"{{url "https://killcode.app/dashboard?tab=licenses" utm_source="email" id=license_id}}"#,
+                json!({ "license_id": "a b&c" })
+            ),
+            "https://killcode.app/dashboard?tab&#x3D;licenses&amp;id&#x3D;a+b%26c&amp;utm_source&#x3D;email"
+        );
+        assert_eq!(
+            build_url("https://killcode.app", &[("utm_campaign".to_string(), "welcome".to_string())]).unwrap(),
+            "https://killcode.app/?utm_campaign=welcome"
+        );
+        assert!(build_url("not a url", &[]).is_err());
+    }
+
+    #[test]
+    fn test_truncate_respects_characters_and_words() {
+        assert_eq!(truncate_text("short", 10, "…"), "short");
+        assert_eq!(truncate_text("The quick brown fox jumps", 15, "…"), "The quick…");
+        assert_eq!(truncate_text("ééééééééé", 5, "..."), "éé...");
+        assert_eq!(render(r#"{{truncate t 8}}"#, json!({ "t": "Protected binary" })), "Protect…");
+        assert_eq!(render(r#"{{truncate t 20}}"#, json!({ "t": "<b>bold</b>" })), "&lt;b&gt;bold&lt;/b&gt;");
+    }
+}
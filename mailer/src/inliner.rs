@@ -0,0 +1,550 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+
+use lol_html::html_content::{ContentType, Element, TextChunk};
+use lol_html::{rewrite_str, ElementContentHandlers, RewriteStrSettings, Selector};
+
+/// Attribute used to stash an element's own `style` while rules are applied
+const ORIGINAL_STYLE_ATTR: &str = "data-css-inline-original";
+
+/// A style rule that can be applied as inline styles
+struct InlineRule {
+    selector: Selector,
+    specificity: (u32, u32, u32),
+    order: usize,
+    declarations: Vec<(String, String)>,
+}
+
+/// Parsed `<style>` contents of a rendered template
+struct Stylesheet {
+    /// Rules applied to matching elements, sorted by specificity then source order
+    rules: Vec<InlineRule>,
+    /// Media queries, other at-rules and rules with selectors that can't be
+    /// inlined (e.g. `a:hover`), kept in a `<style>` block in the head
+    preserved: String,
+}
+
+/// Moves CSS from `<style>` blocks into `style` attributes
+///
+/// Many email clients strip `<style>` blocks, so rules are applied to each
+/// matching element (the element's own `style` still wins over everything but
+/// `!important`), while media queries and pseudo-class rules are kept in the
+/// head. Parsed stylesheets are cached per template and invalidated when the
+/// template's CSS changes.
+#[derive(Default)]
+pub struct CssInliner {
+    cache: Mutex<HashMap<String, (u64, Arc<Stylesheet>)>>,
+}
+
+impl CssInliner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inline the CSS of `html`, caching the parsed stylesheet under `cache_key`
+    ///
+    /// One-off HTML (such as custom emails) passes `None` and is parsed
+    /// without touching the cache.
+    pub fn inline(&self, cache_key: Option<&str>, html: &str) -> Result<String, anyhow::Error> {
+        if !has_style_block(html) {
+            return Ok(html.to_string());
+        }
+        let css = extract_styles(html)?;
+        if css.trim().is_empty() {
+            return Ok(html.to_string());
+        }
+
+        let stylesheet = match cache_key {
+            Some(cache_key) => self.stylesheet(cache_key, &css),
+            None => Arc::new(parse_stylesheet(&css)),
+        };
+        apply(html, &stylesheet)
+    }
+
+    fn stylesheet(&self, cache_key: &str, css: &str) -> Arc<Stylesheet> {
+        let mut hasher = DefaultHasher::new();
+        css.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((cached_hash, stylesheet)) = cache.get(cache_key)
+            && *cached_hash == hash
+        {
+            return stylesheet.clone();
+        }
+
+        let stylesheet = Arc::new(parse_stylesheet(css));
+        cache.insert(cache_key.to_string(), (hash, stylesheet.clone()));
+        stylesheet
+    }
+}
+
+/// Whether `html` may contain a `<style>` block, checked before running the rewriter
+fn has_style_block(html: &str) -> bool {
+    html.as_bytes().windows(6).any(|w| w.eq_ignore_ascii_case(b"<style"))
+}
+
+/// Concatenated contents of all `<style>` blocks
+fn extract_styles(html: &str) -> Result<String, anyhow::Error> {
+    let mut css = String::new();
+
+    rewrite_str(html, RewriteStrSettings {
+        element_content_handlers: vec![(
+            Cow::Owned("style".parse()?),
+            ElementContentHandlers::default().text(|chunk: &mut TextChunk| {
+                css.push_str(chunk.as_str());
+                Ok(())
+            }),
+        )],
+        ..RewriteStrSettings::new()
+    })?;
+
+    Ok(css)
+}
+
+/// Apply the stylesheet to `html` and replace the `<style>` blocks with the preserved CSS
+fn apply(html: &str, stylesheet: &Stylesheet) -> Result<String, anyhow::Error> {
+    let mut handlers = Vec::with_capacity(stylesheet.rules.len() + 3);
+
+    // Handlers for an element run in registration order: stash the element's
+    // own style, apply rules from lowest to highest specificity, then put the
+    // element's own declarations back on top (`merge_declarations` keeps
+    // `!important` values from the stylesheet).
+    handlers.push((
+        Cow::Owned("[style]".parse::<Selector>()?),
+        ElementContentHandlers::default().element(|el: &mut Element| {
+            if let Some(style) = el.get_attribute("style") {
+                el.set_attribute(ORIGINAL_STYLE_ATTR, &style)?;
+                el.remove_attribute("style");
+            }
+            Ok(())
+        }),
+    ));
+
+    for rule in &stylesheet.rules {
+        handlers.push((
+            Cow::Borrowed(&rule.selector),
+            ElementContentHandlers::default().element(move |el: &mut Element| {
+                let mut style = parse_declarations(&el.get_attribute("style").unwrap_or_default());
+                merge_declarations(&mut style, &rule.declarations);
+                el.set_attribute("style", &serialize_declarations(&style))?;
+                Ok(())
+            }),
+        ));
+    }
+
+    // Selectors match the element as parsed, so this targets the same
+    // elements as the stash handler above
+    handlers.push((
+        Cow::Owned("[style]".parse::<Selector>()?),
+        ElementContentHandlers::default().element(|el: &mut Element| {
+            let original = parse_declarations(&el.get_attribute(ORIGINAL_STYLE_ATTR).unwrap_or_default());
+            let mut style = parse_declarations(&el.get_attribute("style").unwrap_or_default());
+            merge_declarations(&mut style, &original);
+            el.set_attribute("style", &serialize_declarations(&style))?;
+            el.remove_attribute(ORIGINAL_STYLE_ATTR);
+            Ok(())
+        }),
+    ));
+
+    let mut preserved_written = false;
+    handlers.push((
+        Cow::Owned("style".parse::<Selector>()?),
+        ElementContentHandlers::default().element(|el: &mut Element| {
+            if preserved_written || stylesheet.preserved.is_empty() {
+                el.remove();
+            } else {
+                el.set_inner_content(&stylesheet.preserved, ContentType::Html);
+                preserved_written = true;
+            }
+            Ok(())
+        }),
+    ));
+
+    Ok(rewrite_str(html, RewriteStrSettings {
+        element_content_handlers: handlers,
+        ..RewriteStrSettings::new()
+    })?)
+}
+
+fn parse_stylesheet(css: &str) -> Stylesheet {
+    let css = strip_comments(css);
+    let mut rules = Vec::new();
+    let mut preserved = String::new();
+    let mut rest = css.as_str();
+    let mut order = 0;
+
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            break;
+        }
+
+        // Statement at-rules such as `@import url(...);`
+        if rest.starts_with('@') {
+            let semicolon = rest.find(';');
+            let brace = rest.find('{');
+            if let Some(end) = semicolon.filter(|s| brace.is_none_or(|b| *s < b)) {
+                preserved.push_str(rest[..=end].trim());
+                preserved.push('\n');
+                rest = &rest[end + 1..];
+                continue;
+            }
+        }
+
+        let Some(open) = rest.find('{') else {
+            break;
+        };
+        let close = matching_brace(rest, open).unwrap_or(rest.len());
+        let prelude = rest[..open].trim();
+        let body = &rest[open + 1..close.min(rest.len())];
+        let block = &rest[..(close + 1).min(rest.len())];
+        rest = &rest[(close + 1).min(rest.len())..];
+
+        // Media queries and other block at-rules are kept verbatim
+        if prelude.starts_with('@') {
+            preserved.push_str(block.trim());
+            preserved.push('\n');
+            continue;
+        }
+
+        let declarations = parse_declarations(body);
+        if declarations.is_empty() {
+            continue;
+        }
+
+        for selector in split_top_level(prelude, ',') {
+            let selector = selector.trim();
+            if selector.is_empty() {
+                continue;
+            }
+            // Pseudo-elements and dynamic pseudo-classes can't be expressed inline
+            let inlinable = !selector.contains("::")
+                && !selector.contains(":hover")
+                && !selector.contains(":active")
+                && !selector.contains(":focus")
+                && !selector.contains(":visited");
+
+            match selector.parse::<Selector>() {
+                Ok(parsed) if inlinable => {
+                    rules.push(InlineRule {
+                        selector: parsed,
+                        specificity: specificity(selector),
+                        order,
+                        declarations: declarations.clone(),
+                    });
+                    order += 1;
+                }
+                _ => {
+                    preserved.push_str(&format!("{} {{ {} }}\n", selector, serialize_declarations(&declarations)));
+                }
+            }
+        }
+    }
+
+    rules.sort_by_key(|rule| (rule.specificity, rule.order));
+
+    Stylesheet {
+        rules,
+        preserved: preserved.trim_end().to_string(),
+    }
+}
+
+fn strip_comments(css: &str) -> String {
+    let mut out = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(start) = rest.find("/*") {
+        out.push_str(&rest[..start]);
+        rest = match rest[start + 2..].find("*/") {
+            Some(end) => &rest[start + 2 + end + 2..],
+            None => "",
+        };
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Index of the `}` closing the `{` at `open`
+fn matching_brace(s: &str, open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in s[open..].char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Split on `separator` outside of parentheses, brackets and quotes
+fn split_top_level(s: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0i32;
+    let mut quote: Option<char> = None;
+    let mut start = 0;
+
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(' | '[') => depth += 1,
+            (None, ')' | ']') => depth -= 1,
+            (None, c) if c == separator && depth == 0 => {
+                parts.push(&s[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+/// Parse `prop: value; ...` into lowercase property names and values
+fn parse_declarations(style: &str) -> Vec<(String, String)> {
+    split_top_level(style, ';')
+        .into_iter()
+        .filter_map(|declaration| {
+            let (property, value) = declaration.split_once(':')?;
+            let property = property.trim().to_ascii_lowercase();
+            let value = value.trim();
+            (!property.is_empty() && !value.is_empty()).then(|| (property, value.to_string()))
+        })
+        .collect()
+}
+
+/// Set each declaration in `style`, replacing an existing value in place
+///
+/// Declarations are merged from lowest to highest precedence, so a value
+/// only loses to a later one when it isn't `!important` or both are.
+fn merge_declarations(style: &mut Vec<(String, String)>, declarations: &[(String, String)]) {
+    for (property, value) in declarations {
+        match style.iter_mut().find(|(p, _)| p == property) {
+            Some(existing) if is_important(&existing.1) && !is_important(value) => {}
+            Some(existing) => existing.1 = value.clone(),
+            None => style.push((property.clone(), value.clone())),
+        }
+    }
+}
+
+fn is_important(value: &str) -> bool {
+    value
+        .rsplit_once('!')
+        .is_some_and(|(_, flag)| flag.trim().eq_ignore_ascii_case("important"))
+}
+
+fn serialize_declarations(style: &[(String, String)]) -> String {
+    style
+        .iter()
+        .map(|(property, value)| format!("{}: {};", property, value))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// CSS specificity as (ids, classes/attributes/pseudo-classes, types)
+///
+/// `:not()`, `:is()` and `:has()` count as their most specific argument and
+/// `:where()` as nothing. Attribute values and other pseudo-class arguments
+/// (`:nth-child(2n+1)`) don't count.
+fn specificity(selector: &str) -> (u32, u32, u32) {
+    let (mut ids, mut classes, mut types) = (0, 0, 0);
+    let bytes = selector.as_bytes();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'#' => {
+                ids += 1;
+                i = ident_end(selector, i + 1);
+            }
+            b'.' => {
+                classes += 1;
+                i = ident_end(selector, i + 1);
+            }
+            b'[' => {
+                classes += 1;
+                i = closing(selector, i) + 1;
+            }
+            // Pseudo-element
+            b':' if bytes.get(i + 1) == Some(&b':') => {
+                types += 1;
+                i = ident_end(selector, i + 2);
+                if bytes.get(i) == Some(&b'(') {
+                    i = closing(selector, i) + 1;
+                }
+            }
+            b':' => {
+                let start = i + 1;
+                i = ident_end(selector, start);
+                let name = selector[start..i].to_ascii_lowercase();
+                if bytes.get(i) != Some(&b'(') {
+                    classes += 1;
+                    continue;
+                }
+
+                let close = closing(selector, i);
+                let arguments = &selector[i + 1..close];
+                i = close + 1;
+                match name.as_str() {
+                    "not" | "is" | "matches" | "has" => {
+                        let (a, b, c) = split_top_level(arguments, ',').into_iter().map(specificity).max().unwrap_or_default();
+                        ids += a;
+                        classes += b;
+                        types += c;
+                    }
+                    "where" => {}
+                    _ => classes += 1,
+                }
+            }
+            c if is_ident_byte(c) => {
+                types += 1;
+                i = ident_end(selector, i);
+            }
+            _ => i += 1,
+        }
+    }
+
+    (ids, classes, types)
+}
+
+fn is_ident_byte(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'-' || c == b'_' || c == b'\\' || !c.is_ascii()
+}
+
+/// End of the identifier starting at `start`, skipping escaped characters
+fn ident_end(s: &str, start: usize) -> usize {
+    let bytes = s.as_bytes();
+    let mut i = start;
+    while i < bytes.len() && is_ident_byte(bytes[i]) {
+        // Continuation bytes of an escaped multi-byte char are consumed as ident bytes
+        i += if bytes[i] == b'\\' { 2 } else { 1 };
+    }
+    i.min(bytes.len())
+}
+
+/// Index of the `)` or `]` closing the bracket at `open`, or the end of `s`
+fn closing(s: &str, open: usize) -> usize {
+    let bytes = s.as_bytes();
+    let mut depth = 0;
+    let mut quote = None;
+    let mut i = open;
+
+    while i < bytes.len() {
+        match (quote, bytes[i]) {
+            (_, b'\\') => i += 1,
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, c @ (b'"' | b'\'')) => quote = Some(c),
+            (None, b'(' | b'[') => depth += 1,
+            (None, b')' | b']') => {
+                depth -= 1;
+                if depth == 0 {
+                    return i;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    bytes.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inline(html: &str) -> String {
+        CssInliner::new().inline(None, html).unwrap()
+    }
+
+    #[test]
+    fn test_specificity() {
+        assert_eq!(specificity("p"), (0, 0, 1));
+        assert_eq!(specificity("table td.cell > a"), (0, 1, 3));
+        assert_eq!(specificity("#main .btn:first-child"), (1, 2, 0));
+        assert_eq!(specificity("a[href^=\"https://a.b\"]"), (0, 1, 1));
+        assert_eq!(specificity("li:nth-child(2n+1)"), (0, 1, 1));
+        assert_eq!(specificity("p:not(.intro, #lead)"), (1, 0, 1));
+        assert_eq!(specificity("p:where(#lead .x)"), (0, 0, 1));
+        assert_eq!(specificity("*"), (0, 0, 0));
+        assert_eq!(specificity("p::first-line"), (0, 0, 2));
+    }
+
+    #[test]
+    fn test_selector_matching() {
+        let html = inline(concat!(
+            "<style>td.cell { color: red } #hero { padding: 4px } a[href^=\"https\"] { color: blue } li:first-child { margin: 0 }</style>",
+            "<table><tr><td class=\"cell\">x</td><td>y</td></tr></table>",
+            "<div id=\"hero\"></div><a href=\"https://a\">s</a><a href=\"http://a\">p</a><ul><li>1</li><li>2</li></ul>",
+        ));
+        assert!(html.contains(r#"<td class="cell" style="color: red;">x</td><td>y</td>"#), "{}", html);
+        assert!(html.contains(r#"<div id="hero" style="padding: 4px;">"#), "{}", html);
+        assert!(html.contains(r#"<a href="https://a" style="color: blue;">s</a><a href="http://a">p</a>"#), "{}", html);
+        assert!(html.contains(r#"<li style="margin: 0;">1</li><li>2</li>"#), "{}", html);
+        assert!(!html.contains("<style>"), "{}", html);
+    }
+
+    #[test]
+    fn test_cascade_order() {
+        // Specificity beats source order; equal specificity goes to the later rule;
+        // the element's own style beats both
+        let html = inline(concat!(
+            "<style>p.a { color: red } p { color: blue; margin: 1px } .b { margin: 2px } .c { margin: 3px }</style>",
+            "<p class=\"a\">1</p><p class=\"b c\">2</p><p class=\"a\" style=\"color: green\">3</p>",
+        ));
+        assert!(html.contains(r#"<p class="a" style="color: red; margin: 1px;">1</p>"#), "{}", html);
+        assert!(html.contains(r#"<p class="b c" style="color: blue; margin: 3px;">2</p>"#), "{}", html);
+        assert!(html.contains(r#"<p class="a" style="color: green; margin: 1px;">3</p>"#), "{}", html);
+    }
+
+    #[test]
+    fn test_important() {
+        let html = inline(concat!(
+            "<style>p { color: red !important; margin: 0 ! IMPORTANT } p.x { color: blue } p.y { color: black !important }</style>",
+            "<p class=\"x\" style=\"color: green; margin: 5px\">1</p><p class=\"y\">2</p><p style=\"color: green !important\">3</p>",
+        ));
+        // `!important` beats higher specificity and the element's own style...
+        assert!(html.contains(r#"<p class="x" style="color: red !important; margin: 0 ! IMPORTANT;">1</p>"#), "{}", html);
+        // ...unless that is `!important` too
+        assert!(html.contains(r#"<p class="y" style="color: black !important; margin: 0 ! IMPORTANT;">2</p>"#), "{}", html);
+        assert!(html.contains(r#"<p style="color: green !important; margin: 0 ! IMPORTANT;">3</p>"#), "{}", html);
+    }
+
+    #[test]
+    fn test_media_queries_preserved_in_head() {
+        let html = inline(concat!(
+            "<html><head><style>/* base */ p { color: red } a:hover { color: blue } @media (max-width: 600px) { p { color: green } }</style>",
+            "<style>.x { margin: 0 }</style></head><body><p>1</p></body></html>",
+        ));
+        assert!(html.contains("<head><style>a:hover { color: blue; }\n@media (max-width: 600px) { p { color: green } }</style></head>"), "{}", html);
+        assert!(html.contains(r#"<p style="color: red;">1</p>"#), "{}", html);
+        assert!(!html.contains("base"), "{}", html);
+    }
+
+    #[test]
+    fn test_html_without_styles_is_unchanged() {
+        let html = "<p class=\"x\">no <b>styles</b></p>";
+        assert_eq!(inline(html), html);
+        assert!(!has_style_block(html));
+        assert!(has_style_block("<STYLE>p {}</STYLE>"));
+    }
+
+    #[test]
+    fn test_cache_follows_css_changes() {
+        let inliner = CssInliner::new();
+        let first = inliner.inline(Some("welcome"), "<style>p { color: red }</style><p>1</p>").unwrap();
+        let second = inliner.inline(Some("welcome"), "<style>p { color: blue }</style><p>1</p>").unwrap();
+        assert_eq!(first, r#"<p style="color: red;">1</p>"#);
+        assert_eq!(second, r#"<p style="color: blue;">1</p>"#);
+    }
+}
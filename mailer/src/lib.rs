@@ -1,9 +1,30 @@
 pub mod queue;
 pub mod smtp;
 pub mod templates;
+pub mod helpers;
+pub mod inliner;
+pub mod sanitizer;
+pub mod tracking;
+pub mod webhooks;
+pub mod suppressions;
+pub mod bounces;
+pub mod template_store;
+pub mod auth;
+pub mod config;
+pub mod logging;
+pub mod telemetry;
+pub mod health;
+pub mod metrics;
+pub mod shutdown;
+pub mod server;
 pub mod handlers;
 pub mod models;
 
 pub use queue::EmailQueue;
 pub use smtp::SmtpClient;
 pub use templates::TemplateEngine;
+pub use template_store::TemplateStore;
+pub use sanitizer::HtmlSanitizer;
+pub use tracking::Tracker;
+pub use webhooks::WebhookDispatcher;
+pub use suppressions::SuppressionList;
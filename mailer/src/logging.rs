@@ -0,0 +1,246 @@
+use std::borrow::Cow;
+use std::future::{ready, Ready};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{Error, FromRequest, HttpMessage, HttpRequest};
+use serde::{Deserialize, Serialize};
+use opentelemetry_sdk::trace::Tracer;
+use tracing::Instrument;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, EnvFilter, Layer};
+use uuid::Uuid;
+
+use crate::telemetry;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest caller-supplied request ID that is kept; longer ones are replaced
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+static REDACT_RECIPIENTS: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines, for development
+    Text,
+    /// One JSON object per line with span fields (`job_id`, `request_id`, ...)
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => Err(anyhow::anyhow!("Unknown log format: {}", other)),
+        }
+    }
+}
+
+/// Install the global subscriber; `log::` records are forwarded to it, so
+/// they pick up the fields of the span they are emitted in
+///
+/// Spans are also exported through `tracer` when tracing is enabled.
+pub fn init(
+    format: LogFormat,
+    filter: &str,
+    redact_recipients: bool,
+    tracer: Option<Tracer>,
+) -> Result<(), anyhow::Error> {
+    REDACT_RECIPIENTS.store(redact_recipients, Ordering::Relaxed);
+
+    let filter = EnvFilter::try_new(filter)?;
+    let output = match format {
+        LogFormat::Text => fmt::layer().boxed(),
+        LogFormat::Json => fmt::layer()
+            .json()
+            .flatten_event(true)
+            .with_current_span(false)
+            .with_span_list(true)
+            .boxed(),
+    };
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(output)
+        .with(tracer.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer)))
+        .try_init()?;
+
+    Ok(())
+}
+
+/// A recipient address for log lines, masked (`j***@example.com`) when
+/// recipient redaction is enabled
+pub fn recipient(email: &str) -> Cow<'_, str> {
+    if !REDACT_RECIPIENTS.load(Ordering::Relaxed) {
+        return Cow::Borrowed(email);
+    }
+    mask(email)
+}
+
+/// Free text such as SMTP replies, with any addresses in it masked when
+/// recipient redaction is enabled
+pub fn redact(text: &str) -> Cow<'_, str> {
+    if !REDACT_RECIPIENTS.load(Ordering::Relaxed) {
+        return Cow::Borrowed(text);
+    }
+    mask_addresses(text)
+}
+
+/// `text` with every address-like token masked
+fn mask_addresses(text: &str) -> Cow<'_, str> {
+    if !text.contains('@') {
+        return Cow::Borrowed(text);
+    }
+
+    let mut out = String::with_capacity(text.len());
+    let mut token_start = 0;
+    for (i, c) in text.char_indices().chain(std::iter::once((text.len(), ' '))) {
+        if is_address_char(c) {
+            continue;
+        }
+        out.push_str(&mask(&text[token_start..i]));
+        if i < text.len() {
+            out.push(c);
+        }
+        token_start = i + c.len_utf8();
+    }
+    Cow::Owned(out)
+}
+
+fn is_address_char(c: char) -> bool {
+    !(c.is_whitespace() || matches!(c, '<' | '>' | '(' | ')' | '"' | '\'' | ',' | ';' | ':' | '[' | ']'))
+}
+
+fn mask(token: &str) -> Cow<'_, str> {
+    match token.rsplit_once('@') {
+        Some((local, domain)) if !local.is_empty() && !domain.is_empty() => {
+            let first = local.chars().next().map(String::from).unwrap_or_default();
+            Cow::Owned(format!("{}***@{}", first, domain))
+        }
+        _ => Cow::Borrowed(token),
+    }
+}
+
+/// ID correlating a request with the job it queued, from the caller's
+/// `X-Request-Id` header or generated
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+impl FromRequest for RequestId {
+    type Error = Error;
+    type Future = Ready<Result<Self, Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let id = req.extensions().get::<RequestId>().cloned();
+        // Outside the middleware (e.g. in tests) every request gets its own
+        ready(Ok(id.unwrap_or_else(|| RequestId(Uuid::new_v4().to_string()))))
+    }
+}
+
+/// Actix middleware assigning request IDs and logging each request
+///
+/// Handlers run inside a `request` span carrying the ID, which is echoed in
+/// the `X-Request-Id` response header. A W3C `traceparent` header makes the
+/// span a child of the caller's trace.
+pub async fn request_span(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let request_id = req
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|id| is_valid_request_id(id))
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    req.extensions_mut().insert(RequestId(request_id.clone()));
+
+    let span = tracing::info_span!(
+        "request",
+        request_id = %request_id,
+        method = %req.method(),
+        path = %req.path(),
+    );
+    telemetry::set_parent_from_headers(&span, req.headers());
+    let started = Instant::now();
+
+    let result = next.call(req).instrument(span.clone()).await;
+
+    let _entered = span.enter();
+    match result {
+        Ok(mut response) => {
+            let status = response.status().as_u16();
+            if let Ok(value) = HeaderValue::from_str(&request_id) {
+                response.headers_mut().insert(REQUEST_ID_HEADER, value);
+            }
+            tracing::info!(status, duration_ms = started.elapsed().as_secs_f64() * 1000.0, "request completed");
+            Ok(response)
+        }
+        Err(e) => {
+            let status = e.as_response_error().status_code().as_u16();
+            tracing::warn!(status, duration_ms = started.elapsed().as_secs_f64() * 1000.0, error = %e, "request failed");
+            Err(e)
+        }
+    }
+}
+
+/// Only short, printable IDs are propagated into jobs and logs
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':' | '/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mask() {
+        assert_eq!(mask("jane.doe@example.com"), "j***@example.com");
+        assert_eq!(mask("élise@example.fr"), "é***@example.fr");
+        assert_eq!(mask("a@b@example.com"), "a***@example.com");
+        assert_eq!(mask("@example.com"), "@example.com");
+        assert_eq!(mask("user@"), "user@");
+        assert_eq!(mask("no-address"), "no-address");
+    }
+
+    #[test]
+    fn test_mask_addresses() {
+        assert_eq!(
+            mask_addresses("550 5.1.1 <jane@example.com>: Recipient address rejected (to=bob@example.org, from=x@y.z)"),
+            "550 5.1.1 <j***@example.com>: Recipient address rejected (t***@example.org, f***@y.z)"
+        );
+        assert_eq!(mask_addresses("rfc822;jane@example.com"), "rfc822;j***@example.com");
+        assert!(matches!(mask_addresses("421 4.7.0 try again later"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_redact_is_off_by_default() {
+        assert_eq!(redact("550 <jane@example.com>"), "550 <jane@example.com>");
+        assert_eq!(recipient("jane@example.com"), "jane@example.com");
+    }
+
+    #[test]
+    fn test_is_valid_request_id() {
+        assert!(is_valid_request_id("0b6c7a52-6a4e-4c3b-9a53-2f4d5e6f7a8b"));
+        assert!(is_valid_request_id("svc/checkout:req_42.1"));
+        assert!(is_valid_request_id(&"a".repeat(MAX_REQUEST_ID_LEN)));
+
+        assert!(!is_valid_request_id(""));
+        assert!(!is_valid_request_id(&"a".repeat(MAX_REQUEST_ID_LEN + 1)));
+        assert!(!is_valid_request_id("id with spaces"));
+        assert!(!is_valid_request_id("id\nInjected: header"));
+        assert!(!is_valid_request_id("café"));
+    }
+}
@@ -1,45 +1,100 @@
 use actix_web::{web, App, HttpServer, middleware};
 use std::env;
+use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::time::{interval, Duration};
+use tracing::Instrument;
 
-use mailer::{EmailQueue, SmtpClient, TemplateEngine};
+use mailer::{EmailQueue, HtmlSanitizer, SmtpClient, SuppressionList, TemplateEngine, TemplateStore, Tracker, WebhookDispatcher};
+use mailer::auth::{self, KeyRing};
+use mailer::logging;
+use mailer::metrics;
+use mailer::telemetry::{self, Telemetry};
+use mailer::config::{self, Config};
+use mailer::server::{self, Listener};
+use mailer::shutdown::{self, Shutdown};
+use mailer::bounces::{self, MboxPoller};
 use mailer::handlers::{self, AppState};
+use mailer::health::HealthChecks;
+use mailer::models::{DeliveryAttempt, EmailJob, EmailStatus, RenderedEmail};
+use mailer::smtp;
+
+/// Render a job either from a stored template or a built-in one
+async fn render_job(state: &AppState, job: &EmailJob) -> Result<RenderedEmail, anyhow::Error> {
+    if let Some(stored) = &job.stored_template {
+        let template = state.template_store.get(&stored.name, Some(stored.version)).await?;
+        return state.templates.render_stored(&template, &job.data, job.subject.as_deref(), job.locale.as_deref());
+    }
+
+    state.templates.render(&job.template, &job.data, job.subject.as_deref(), job.locale.as_deref())
+}
+
+/// Queue a status-change webhook; failures are logged, never fatal to the job
+async fn notify(state: &AppState, job: &EmailJob, status: EmailStatus, reason: Option<&str>) {
+    if let Some(webhooks) = &state.webhooks
+        && let Err(e) = webhooks.notify(job, status, reason).await
+    {
+        log::error!("Failed to queue webhook for {}: {}", job.id, e);
+    }
+}
+
+/// Identifies this process on delivery attempts: `{hostname}-{pid}`
+fn worker_id() -> String {
+    let host = env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/proc/sys/kernel/hostname").ok())
+        .map(|host| host.trim().to_string())
+        .filter(|host| !host.is_empty())
+        .unwrap_or_else(|| "mailer".to_string());
+    format!("{}-{}", host, std::process::id())
+}
 
 /// Worker task that processes queued emails
-async fn email_worker(state: Arc<AppState>) {
-    log::info!("📧 Email worker started");
+///
+/// On shutdown it stops taking jobs; a send still running when the grace
+/// period ends is abandoned and the job returned to the queue.
+async fn email_worker(state: Arc<AppState>, worker_id: String) {
+    log::info!("📧 Email worker {} started", worker_id);
     
     let mut ticker = interval(Duration::from_secs(1));
     
     loop {
-        ticker.tick().await;
+        if !state.shutdown.tick(&mut ticker).await {
+            break;
+        }
+        state.health.heartbeat.beat();
         
         // Try to process a job
         match state.queue.dequeue().await {
             Ok(Some(job)) => {
-                log::info!("📤 Processing email job: {} to {}", job.id, job.to);
-                
-                // Render template
-                let html = match state.templates.render(&job.template, &job.data) {
-                    Ok(h) => h,
-                    Err(e) => {
-                        log::error!("Failed to render template: {}", e);
-                        let _ = state.queue.fail(&job.id, &e.to_string()).await;
-                        continue;
-                    }
-                };
-                
-                // Send email
-                match state.smtp.send(&job.to, &job.subject, &html).await {
-                    Ok(()) => {
-                        let _ = state.queue.complete(&job.id).await;
-                    }
-                    Err(e) => {
-                        log::error!("Failed to send email: {}", e);
-                        let _ = state.queue.fail(&job.id, &e.to_string()).await;
+                let job_id = job.id.clone();
+                // Log lines emitted while handling the job, including those
+                // from the queue and SMTP client, carry these fields
+                let span = tracing::info_span!(
+                    "job",
+                    job_id = %job.id,
+                    template = job.template_name(),
+                    attempt = job.retries + 1,
+                    request_id = job.request_id.as_deref(),
+                );
+                telemetry::set_parent(&span, &job.trace_context);
+                let interrupted = async {
+                    tokio::select! {
+                        _ = process_job(&state, job, &worker_id) => false,
+                        _ = state.shutdown.deadline() => {
+                            log::warn!("⏱️ Shutdown deadline reached while sending {}", job_id);
+                            if let Err(e) = state.queue.requeue(&job_id).await {
+                                log::error!("Failed to return job {} to the queue: {}", job_id, e);
+                            }
+                            true
+                        }
                     }
                 }
+                .instrument(span)
+                .await;
+                if interrupted {
+                    break;
+                }
             }
             Ok(None) => {
                 // No jobs in queue, continue waiting
@@ -49,84 +104,523 @@ async fn email_worker(state: Arc<AppState>) {
             }
         }
     }
+    
+    log::info!("📧 Email worker stopped");
+}
+
+async fn process_job(state: &AppState, job: EmailJob, worker_id: &str) {
+    log::info!("📤 Processing email job: {} to {}", job.id, logging::recipient(&job.to));
+    
+    if let Some(expires_at) = job.expires_at
+        && expires_at <= chrono::Utc::now()
+    {
+        let reason = format!("Expired at {} before it could be sent", expires_at.to_rfc3339());
+        log::warn!("⌛ Email job {} expired", job.id);
+        match state.queue.finish(&job.id, EmailStatus::Expired, &reason).await {
+            Ok(Some(_)) => notify(state, &job, EmailStatus::Expired, Some(&reason)).await,
+            Ok(None) => log::warn!("Email job {} was removed before it could be expired", job.id),
+            Err(e) => log::error!("Failed to mark job {} as expired: {}", job.id, e),
+        }
+        return;
+    }
+    
+    let started_at = chrono::Utc::now();
+    let started = std::time::Instant::now();
+    let attempt = |transport: Option<&str>| DeliveryAttempt {
+        started_at,
+        worker_id: worker_id.to_string(),
+        transport: transport.map(str::to_string),
+        duration_ms: started.elapsed().as_millis() as u64,
+        success: false,
+        smtp_code: None,
+        smtp_message: None,
+        queue_id: None,
+        error: None,
+    };
+    
+    // Render template
+    let span = tracing::info_span!("render", otel.status_code = tracing::field::Empty, error = tracing::field::Empty);
+    let rendered = async {
+        let result = render_job(state, &job).await;
+        if let Err(e) = &result {
+            telemetry::record_error(e);
+        }
+        result
+    }
+    .instrument(span)
+    .await;
+    let mut rendered = match rendered {
+        Ok(r) => r,
+        Err(e) => {
+            log::error!("Failed to render template: {}", e);
+            let attempt = DeliveryAttempt { error: Some(e.to_string()), ..attempt(None) };
+            record_failure(state, &job, &e.to_string(), attempt).await;
+            return;
+        }
+    };
+    
+    if job.track && let Some(tracker) = &state.tracker {
+        match tracker.instrument(&job.id, &rendered.html) {
+            Ok(html) => rendered.html = html,
+            Err(e) => log::warn!("Failed to add tracking to {}: {}", job.id, e),
+        }
+    }
+    
+    // Send email
+    match state.smtp.send(&job.id, &job.to, &rendered.subject, &rendered.html, rendered.text.as_deref()).await {
+        Ok(reply) => {
+            let attempt = DeliveryAttempt {
+                success: true,
+                smtp_code: Some(reply.code),
+                smtp_message: Some(reply.message),
+                queue_id: reply.queue_id,
+                ..attempt(Some(state.smtp.transport()))
+            };
+            match state.queue.complete(&job.id, attempt).await {
+                Ok(true) => notify(state, &job, EmailStatus::Sent, None).await,
+                Ok(false) => log::warn!("Email job {} was removed before it could be marked as sent", job.id),
+                Err(e) => log::error!("Failed to mark job {} as sent: {}", job.id, e),
+            }
+        }
+        Err(e) => {
+            log::error!("Failed to send email: {}", logging::redact(&e.to_string()));
+            let reply = smtp::rejection(&e);
+            let attempt = DeliveryAttempt {
+                smtp_code: reply.as_ref().map(|r| r.code),
+                smtp_message: reply.map(|r| r.message),
+                error: Some(e.to_string()),
+                ..attempt(Some(state.smtp.transport()))
+            };
+            record_failure(state, &job, &e.to_string(), attempt).await;
+        }
+    }
+}
+
+/// Record a failed attempt; the `failed` webhook is only sent once the job
+/// is stored as permanently failed
+async fn record_failure(state: &AppState, job: &EmailJob, error: &str, attempt: DeliveryAttempt) {
+    match state.queue.fail(&job.id, error, attempt).await {
+        Ok(Some(true)) => {}
+        Ok(Some(false)) => notify(state, job, EmailStatus::Failed, Some(error)).await,
+        Ok(None) => log::warn!("Email job {} was removed before its failure could be recorded", job.id),
+        Err(e) => log::error!("Failed to record failure of job {}: {}", job.id, e),
+    }
+}
+
+/// Worker task that delivers (and retries) status-change webhooks
+async fn webhook_worker(state: Arc<AppState>) {
+    let Some(webhooks) = &state.webhooks else {
+        return;
+    };
+    log::info!("🪝 Webhook worker started");
+    
+    let mut ticker = interval(Duration::from_secs(1));
+    
+    loop {
+        if !state.shutdown.tick(&mut ticker).await {
+            break;
+        }
+        
+        if let Err(e) = webhooks.process_due().await {
+            log::error!("Failed to process webhooks: {}", e);
+        }
+    }
+}
+
+/// Worker task that ingests bounces delivered to a local mbox
+async fn bounce_worker(state: Arc<AppState>, mut poller: MboxPoller, poll_secs: u64) {
+    log::info!("↩️ Bounce worker started");
+    
+    let mut ticker = interval(Duration::from_secs(poll_secs));
+    
+    loop {
+        if !state.shutdown.tick(&mut ticker).await {
+            break;
+        }
+        
+        let messages = match poller.poll() {
+            Ok(messages) => messages,
+            Err(e) => {
+                log::error!("Failed to read bounce mailbox: {}", e);
+                continue;
+            }
+        };
+        
+        if messages.is_empty() {
+            continue;
+        }
+        // Stop at the first failure so it is read again on the next poll
+        for message in &messages {
+            if let Err(e) = bounces::ingest(&state.queue, &state.suppressions, state.webhooks.as_ref(), &message.raw).await {
+                log::error!("Failed to ingest bounce, retrying on the next poll: {}", e);
+                break;
+            }
+            poller.consumed(message);
+        }
+        if let Err(e) = poller.commit().await {
+            log::error!("Failed to save bounce mailbox position: {}", e);
+        }
+    }
+}
+
+/// The value of a startup step, or exit with the error like a config error
+fn or_exit<T>(result: Result<T, anyhow::Error>, context: &str) -> T {
+    result.unwrap_or_else(|e| {
+        eprintln!("❌ {}: {}", context, e);
+        std::process::exit(1);
+    })
+}
+
+const USAGE: &str = "Usage: mailer [--config <file.toml|file.yaml>] [--check-config]";
+
+/// Command-line options; everything else is configured through the config file
+/// and environment
+struct Args {
+    config: Option<PathBuf>,
+    check_config: bool,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args {
+        config: env::var_os("MAILER_CONFIG").filter(|p| !p.is_empty()).map(PathBuf::from),
+        check_config: false,
+    };
+
+    let mut argv = env::args().skip(1);
+    while let Some(arg) = argv.next() {
+        match arg.as_str() {
+            "--config" => args.config = Some(argv.next().ok_or("--config needs a path")?.into()),
+            "--check-config" => args.check_config = true,
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            other => match other.strip_prefix("--config=") {
+                Some(path) => args.config = Some(path.into()),
+                None => return Err(format!("Unknown argument '{}'\n{}", other, USAGE)),
+            },
+        }
+    }
+
+    Ok(args)
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    // Initialize logging
-    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+    let args = parse_args().unwrap_or_else(|message| {
+        eprintln!("{}", message);
+        std::process::exit(2);
+    });
     
-    log::info!("🚀 Starting KillCode Mailer Service");
+    // Configuration file (TOML/YAML) with environment overrides, validated
+    // up front so every problem is reported at once
+    let config = match Config::load(args.config.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("❌ {}", e);
+            std::process::exit(1);
+        }
+    };
+    
+    if args.check_config {
+        println!("✅ Configuration is valid\n\n{}", config);
+        return Ok(());
+    }
+    
+    // Initialize logging, and trace export when a collector is configured
+    let telemetry = match &config.telemetry.otlp_endpoint {
+        Some(endpoint) => Some(
+            Telemetry::otlp(endpoint, &config.telemetry.service_name).map_err(std::io::Error::other)?,
+        ),
+        None => None,
+    };
+    logging::init(
+        config.logging.format,
+        &config.logging.level,
+        config.logging.redact_recipients,
+        telemetry.as_ref().map(Telemetry::tracer),
+    )
+    .map_err(std::io::Error::other)?;
     
-    // Load configuration from environment
-    // Internal service - always binds to 0.0.0.0:8000
-    let bind_addr = "0.0.0.0:8000";
-    let redis_url = env::var("REDIS_URL").unwrap_or_else(|_| "redis://redis:6379".to_string());
-    
-    let smtp_host = env::var("SMTP_HOST").expect("SMTP_HOST must be set");
-    let smtp_port: u16 = env::var("SMTP_PORT")
-        .unwrap_or_else(|_| "587".to_string())
-        .parse()
-        .expect("SMTP_PORT must be a valid port number");
-    let smtp_user = env::var("SMTP_USER").expect("SMTP_USER must be set");
-    let smtp_pass = env::var("SMTP_PASS").expect("SMTP_PASS must be set");
-    let smtp_secure = env::var("SMTP_SECURE")
-        .unwrap_or_else(|_| "true".to_string())
-        .parse::<bool>()
-        .unwrap_or(true);
-    let smtp_accept_invalid_certs = env::var("SMTP_ACCEPT_INVALID_CERTS")
-        .unwrap_or_else(|_| "false".to_string())
-        .parse::<bool>()
-        .unwrap_or(false);
-    // Use implicit TLS (SMTPS/port 465) instead of STARTTLS (port 587)
-    // Set to true if your SMTP server uses port 465 or expects TLS from the start
-    let smtp_implicit_tls = env::var("SMTP_IMPLICIT_TLS")
-        .unwrap_or_else(|_| "false".to_string())
-        .parse::<bool>()
-        .unwrap_or(false);
+    log::info!("🚀 Starting KillCode Mailer Service");
+    if let Some(endpoint) = &config.telemetry.otlp_endpoint {
+        log::info!("🔭 Exporting traces to {}", endpoint);
+    }
+    if let Some(path) = &args.config {
+        log::info!("⚙️ Loaded configuration from {}", path.display());
+    }
     
+    let listeners = config.listeners().map_err(std::io::Error::other)?;
+    let tls = config.tls();
+    let redis_url = config.redis.url.as_str();
+    let smtp_config = &config.smtp;
+
     log::info!("📫 SMTP: {}:{} (secure: {}, implicit_tls: {}, accept_invalid_certs: {})", 
-        smtp_host, smtp_port, smtp_secure, smtp_implicit_tls, smtp_accept_invalid_certs);
-    log::info!("📦 Redis: {}", redis_url);
+        smtp_config.host, smtp_config.port, smtp_config.secure, smtp_config.implicit_tls, smtp_config.accept_invalid_certs);
+    log::info!("📦 Redis: {}", config::redact_url(redis_url));
     
     // Initialize components
-    let queue = EmailQueue::new(&redis_url)
-        .await
-        .expect("Failed to connect to Redis");
+    let queue = or_exit(EmailQueue::new(redis_url).await, "Failed to connect to Redis");
+    match queue.rebuild_indexes().await {
+        Ok(0) => {}
+        Ok(count) => log::info!("🗂️ Indexed {} existing jobs for /jobs", count),
+        Err(e) => log::warn!("Failed to index existing jobs: {}", e),
+    }
+    
+    let mut smtp = or_exit(
+        SmtpClient::new(
+            &smtp_config.host,
+            smtp_config.port,
+            &smtp_config.user,
+            smtp_config.pass.expose(),
+            smtp_config.secure,
+            smtp_config.accept_invalid_certs,
+            smtp_config.implicit_tls,
+        ),
+        "Failed to create SMTP client",
+    );
+    
+    if let Some(from) = &smtp_config.from {
+        smtp = smtp.with_from(from).expect("SMTP from address was validated");
+    }
+    
+    if let Some(bounce_address) = &smtp_config.bounce_address {
+        smtp = smtp.with_bounce_address(bounce_address).expect("SMTP bounce address was validated");
+    }
+    
+    // DKIM signing, one key per sender domain
+    for key in &config.dkim {
+        let algorithm = mailer::smtp::parse_dkim_algorithm(&key.algorithm).expect("DKIM algorithm was validated");
+        or_exit(
+            smtp.add_dkim_key(&key.domain, &key.selector, &key.private_key_file.to_string_lossy(), algorithm),
+            "Failed to load DKIM key",
+        );
+    }
     
-    let smtp = SmtpClient::new(&smtp_host, smtp_port, &smtp_user, &smtp_pass, smtp_secure, smtp_accept_invalid_certs, smtp_implicit_tls)
-        .expect("Failed to create SMTP client");
+    // Templates are loaded from the templates directory and re-registered on
+    // change; the embedded copies are used as a fallback
+    let templates_dir = &config.templates.dir;
+    let mut templates = if templates_dir.is_dir() {
+        TemplateEngine::from_dir(templates_dir)
+    } else {
+        log::warn!("Template directory {} not found, using embedded templates", templates_dir.display());
+        TemplateEngine::new()
+    };
     
-    let templates = TemplateEngine::new();
+    if config.templates.watch && let Err(e) = templates.watch() {
+        log::warn!("Template hot-reload disabled: {}", e);
+    }
+    
+    let template_store = or_exit(TemplateStore::new(redis_url).await, "Failed to connect to Redis");
+    
+    // Caller-supplied HTML (custom template) is sanitized before enqueueing
+    let custom_html = &config.custom_html;
+    let mut sanitizer = HtmlSanitizer::new()
+        .with_max_body_bytes(custom_html.max_bytes)
+        .with_mode(custom_html.mode);
+    if let Some(tags) = &custom_html.allowed_tags {
+        sanitizer = sanitizer.with_allowed_tags(tags);
+    }
+    if let Some(attributes) = &custom_html.allowed_attributes {
+        sanitizer = sanitizer.with_allowed_attributes(attributes);
+    }
+    if let Some(domains) = &custom_html.link_domains {
+        sanitizer = sanitizer.with_allowed_domains(domains);
+    }
+    
+    // Open/click tracking is enabled by setting the mailer's public URL
+    let tracker = config.tracking.base_url.as_ref().map(|base_url| {
+        let tracking = &config.tracking;
+        log::info!("👁️ Tracking via {} (opens: {}, clicks: {})", base_url, tracking.opens, tracking.clicks);
+        Tracker::new(base_url, tracking.secret.expose())
+            .expect("Tracking configuration was validated")
+            .with_opens(tracking.opens)
+            .with_clicks(tracking.clicks)
+    });
+    
+    // Status-change webhooks are enabled by the webhook secret, which signs
+    // every delivery
+    let webhooks = if config.webhooks.secret.is_empty() {
+        None
+    } else {
+        let default_url = config.webhooks.url.clone();
+        log::info!("🪝 Webhooks enabled (default URL: {})", default_url.as_deref().unwrap_or("none"));
+        let dispatcher = WebhookDispatcher::new(redis_url, config.webhooks.secret.expose(), default_url, config.webhooks.allowed_hosts.clone()).await;
+        Some(
+            or_exit(dispatcher, "Failed to connect to Redis")
+                .with_max_attempts(config.webhooks.max_attempts)
+                .with_retention(Duration::from_secs(u64::from(config.webhooks.retention_days) * 24 * 3600)),
+        )
+    };
+    
+    let suppressions = or_exit(SuppressionList::new(redis_url).await, "Failed to connect to Redis");
+    
+    // API keys from the config and/or a key file that is re-read when it
+    // changes, so keys can be rotated without a restart
+    let keys_file = config.auth.keys_file.clone();
+    let watch_keys = keys_file.is_some();
+    let auth = or_exit(
+        KeyRing::new(config.api_keys().expect("API keys were validated"), keys_file),
+        "Failed to load API key file",
+    );
+    if !auth.is_required() {
+        log::warn!("🔓 No API keys configured, the HTTP API is unauthenticated");
+    }
+    
+    let mut health = HealthChecks::new(Duration::from_secs(config.health.worker_timeout_secs));
+    if config.health.check_smtp {
+        health = health.with_smtp_probe(Duration::from_secs(config.health.smtp_check_interval_secs));
+    }
     
     let state = Arc::new(AppState {
         queue,
         smtp,
         templates,
+        template_store,
+        sanitizer,
+        tracker,
+        webhooks,
+        suppressions,
+        auth,
+        shutdown: Shutdown::new(Duration::from_secs(config.server.shutdown_timeout_secs)),
+        health,
     });
     
+    // Workers are awaited on shutdown so in-flight sends can finish
+    let mut workers = Vec::new();
+    
     // Start email worker in background
     let worker_state = state.clone();
-    tokio::spawn(async move {
-        email_worker(worker_state).await;
-    });
+    let worker_id = worker_id();
+    workers.push(tokio::spawn(async move {
+        email_worker(worker_state, worker_id).await;
+    }));
     
-    log::info!("🌐 Listening on {}", bind_addr);
+    let webhook_state = state.clone();
+    workers.push(tokio::spawn(async move {
+        webhook_worker(webhook_state).await;
+    }));
     
+    if watch_keys {
+        let keys_state = state.clone();
+        tokio::spawn(async move {
+            let mut ticker = interval(Duration::from_secs(10));
+            loop {
+                ticker.tick().await;
+                if let Err(e) = keys_state.auth.reload_if_changed() {
+                    log::error!("Failed to reload API keys, keeping the current ones: {}", e);
+                }
+            }
+        });
+    }
+    
+    // Bounces can also be POSTed to /bounces as raw MIME
+    if let Some(mbox) = config.bounces.mbox.clone() {
+        let poll_secs = config.bounces.poll_secs;
+        log::info!("↩️ Polling {} for bounces every {}s", mbox.display(), poll_secs);
+        let poller = or_exit(MboxPoller::new(redis_url, mbox).await, "Failed to connect to Redis");
+        let bounce_state = state.clone();
+        workers.push(tokio::spawn(async move {
+            bounce_worker(bounce_state, poller, poll_secs).await;
+        }));
+    }
+    
+    let shutdown = state.shutdown.clone();
     let app_state = web::Data::from(state);
     
-    HttpServer::new(move || {
+    let mut http_server = HttpServer::new(move || {
         App::new()
             .app_data(app_state.clone())
-            .wrap(middleware::Logger::default())
-            .route("/health", web::get().to(handlers::health))
+            .wrap(middleware::from_fn(auth::require_api_key))
+            .wrap(middleware::from_fn(logging::request_span))
+            .wrap(middleware::from_fn(metrics::track_requests))
+            // `/health` is kept for existing probes
+            .route("/health", web::get().to(handlers::health_live))
+            .route("/health/live", web::get().to(handlers::health_live))
+            .route("/health/ready", web::get().to(handlers::health_ready))
             .route("/send/otp", web::post().to(handlers::send_otp))
             .route("/send/otp-2fa", web::post().to(handlers::send_otp_2fa))
             .route("/send", web::post().to(handlers::send_email))
+            .route("/preview", web::post().to(handlers::preview_email))
             .route("/stats", web::get().to(handlers::queue_stats))
+            .route("/stats/templates", web::get().to(handlers::template_stats))
+            .route("/metrics", web::get().to(handlers::metrics))
+            .route("/jobs", web::get().to(handlers::list_jobs))
             .route("/job/{job_id}", web::get().to(handlers::job_status))
-    })
-    .bind(bind_addr)?
-    .run()
-    .await
+            .route("/job/{job_id}/cancel", web::post().to(handlers::cancel_job))
+            .route("/job/{job_id}/webhooks", web::get().to(handlers::job_webhooks))
+            .route("/webhooks/deliveries", web::get().to(handlers::webhook_deliveries))
+            .service(
+                web::resource("/bounces")
+                    .app_data(web::PayloadConfig::new(handlers::MAX_BOUNCE_BYTES))
+                    .route(web::post().to(handlers::ingest_bounce))
+            )
+            .route("/suppressions", web::get().to(handlers::list_suppressions))
+            .route("/suppressions/{email}", web::get().to(handlers::get_suppression))
+            .route("/suppressions/{email}", web::delete().to(handlers::delete_suppression))
+            .route("/track/open/{token}", web::get().to(handlers::track_open))
+            .route("/track/click/{token}", web::get().to(handlers::track_click))
+            .route("/templates", web::get().to(handlers::list_templates))
+            .route("/templates", web::post().to(handlers::create_template))
+            .route("/templates/{name}", web::get().to(handlers::get_template))
+            .route("/templates/{name}", web::put().to(handlers::update_template))
+            .route("/templates/{name}", web::delete().to(handlers::delete_template))
+            .route("/templates/{name}/versions", web::get().to(handlers::template_versions))
+            .route("/templates/{name}/rollback", web::post().to(handlers::rollback_template))
+            .route("/templates/{name}/preview", web::post().to(handlers::preview_template))
+    });
+    
+    for listener in &listeners {
+        match (listener, &tls) {
+            (Listener::Tcp(addr), Some(tls)) => {
+                let acceptor = tls.acceptor().map_err(std::io::Error::other)?;
+                http_server = http_server.bind_openssl(addr, acceptor)?;
+                log::info!("🌐 Listening on https://{} (mTLS: {})", addr, tls.client_ca_file.is_some());
+            }
+            (Listener::Tcp(addr), None) => {
+                http_server = http_server.bind(addr)?;
+                log::info!("🌐 Listening on http://{}", addr);
+            }
+            #[cfg(unix)]
+            (Listener::Unix(path), _) => {
+                server::remove_stale_socket(path)?;
+                http_server = http_server.bind_uds(path)?;
+                log::info!("🌐 Listening on {}", listener);
+            }
+            #[cfg(not(unix))]
+            (Listener::Unix(_), _) => {
+                return Err(std::io::Error::other("Unix domain sockets are not supported on this platform"));
+            }
+        }
+    }
+    
+    // Signals are handled below so the workers can be drained after the
+    // server has stopped accepting connections
+    let server = http_server
+        .disable_signals()
+        .shutdown_timeout(config.server.shutdown_timeout_secs)
+        .run();
+    let server_handle = server.handle();
+    let mut server = std::pin::pin!(server);
+    
+    tokio::select! {
+        result = &mut server => return result,
+        _ = shutdown::signal() => {}
+    }
+    
+    log::info!("🛑 Shutting down, waiting up to {}s for in-flight work", shutdown.grace().as_secs());
+    shutdown.trigger();
+    
+    let (_, result) = tokio::join!(server_handle.stop(true), server);
+    
+    // Workers return by the deadline on their own; the margin covers
+    // returning an interrupted job to the queue
+    let drain = futures_util::future::join_all(workers.iter_mut());
+    if tokio::time::timeout(shutdown.grace() + Duration::from_secs(5), drain).await.is_err() {
+        log::warn!("Workers did not stop in time, aborting them");
+        workers.iter().for_each(|worker| worker.abort());
+    }
+    
+    log::info!("👋 Mailer stopped");
+    if let Some(telemetry) = &telemetry {
+        telemetry.shutdown();
+    }
+    result
 }
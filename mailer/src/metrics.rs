@@ -0,0 +1,142 @@
+use std::sync::LazyLock;
+use std::time::Instant;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::Error;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, Histogram, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+
+/// Buckets for Redis round trips and HTTP handlers (seconds)
+const FAST_BUCKETS: &[f64] = &[0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5];
+/// Buckets for SMTP transactions and time spent queued (seconds)
+const SLOW_BUCKETS: &[f64] = &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 300.0, 900.0];
+
+/// Process-wide metrics, exposed in Prometheus text format on `/metrics`
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+pub struct Metrics {
+    registry: Registry,
+    /// Jobs by template and the status they reached (`queued`, `sent`, ...)
+    pub emails: IntCounterVec,
+    /// Time to persist and queue a job
+    pub enqueue_duration: Histogram,
+    /// Time from enqueueing to a successful send
+    pub queue_wait: HistogramVec,
+    /// Duration of the SMTP transaction
+    pub smtp_duration: Histogram,
+    /// Failed SMTP sends by reply code, or error class without one
+    pub smtp_errors: IntCounterVec,
+    pub retries: IntCounterVec,
+    pub render_failures: IntCounterVec,
+    /// Jobs per queue lane, refreshed on scrape
+    pub queue_depth: IntGaugeVec,
+    pub http_requests: IntCounterVec,
+    pub http_duration: HistogramVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+
+        let metrics = Self {
+            emails: IntCounterVec::new(
+                Opts::new("mailer_emails_total", "Email jobs by template and status"),
+                &["template", "status"],
+            ).expect("valid metric"),
+            enqueue_duration: Histogram::with_opts(
+                HistogramOpts::new("mailer_enqueue_duration_seconds", "Time to queue a job").buckets(FAST_BUCKETS.to_vec()),
+            ).expect("valid metric"),
+            queue_wait: HistogramVec::new(
+                HistogramOpts::new("mailer_queue_wait_seconds", "Time from enqueue to successful send").buckets(SLOW_BUCKETS.to_vec()),
+                &["template"],
+            ).expect("valid metric"),
+            smtp_duration: Histogram::with_opts(
+                HistogramOpts::new("mailer_smtp_send_duration_seconds", "Duration of SMTP transactions").buckets(SLOW_BUCKETS.to_vec()),
+            ).expect("valid metric"),
+            smtp_errors: IntCounterVec::new(
+                Opts::new("mailer_smtp_errors_total", "Failed SMTP sends by reply code or error class"),
+                &["code"],
+            ).expect("valid metric"),
+            retries: IntCounterVec::new(
+                Opts::new("mailer_retries_total", "Sends that failed and were queued for retry"),
+                &["template"],
+            ).expect("valid metric"),
+            render_failures: IntCounterVec::new(
+                Opts::new("mailer_render_failures_total", "Template render failures"),
+                &["template"],
+            ).expect("valid metric"),
+            queue_depth: IntGaugeVec::new(
+                Opts::new("mailer_queue_depth", "Jobs per queue lane"),
+                &["lane"],
+            ).expect("valid metric"),
+            http_requests: IntCounterVec::new(
+                Opts::new("mailer_http_requests_total", "HTTP requests by route and status"),
+                &["method", "route", "status"],
+            ).expect("valid metric"),
+            http_duration: HistogramVec::new(
+                HistogramOpts::new("mailer_http_request_duration_seconds", "HTTP request duration").buckets(FAST_BUCKETS.to_vec()),
+                &["method", "route"],
+            ).expect("valid metric"),
+            registry,
+        };
+
+        let collectors: [Box<dyn prometheus::core::Collector>; 10] = [
+            Box::new(metrics.emails.clone()),
+            Box::new(metrics.enqueue_duration.clone()),
+            Box::new(metrics.queue_wait.clone()),
+            Box::new(metrics.smtp_duration.clone()),
+            Box::new(metrics.smtp_errors.clone()),
+            Box::new(metrics.retries.clone()),
+            Box::new(metrics.render_failures.clone()),
+            Box::new(metrics.queue_depth.clone()),
+            Box::new(metrics.http_requests.clone()),
+            Box::new(metrics.http_duration.clone()),
+        ];
+        for collector in collectors {
+            metrics.registry.register(collector).expect("metric registered once");
+        }
+
+        metrics
+    }
+
+    /// Count a job reaching `status`
+    pub fn email(&self, template: &str, status: &str) {
+        self.emails.with_label_values(&[template, status]).inc();
+    }
+
+    /// Everything registered, in Prometheus text format
+    pub fn encode(&self) -> Result<String, anyhow::Error> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+}
+
+/// Actix middleware recording request counts and durations
+///
+/// Requests are labelled by route pattern (`/job/{job_id}`), not the raw
+/// path, to keep the number of series bounded.
+pub async fn track_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let started = Instant::now();
+    let method = req.method().to_string();
+    let route = req.match_pattern().unwrap_or_else(|| "unmatched".to_string());
+
+    let response = next.call(req).await;
+
+    let status = match &response {
+        Ok(response) => response.status(),
+        Err(e) => e.as_response_error().status_code(),
+    };
+    METRICS.http_requests.with_label_values(&[&method, &route, status.as_str()]).inc();
+    METRICS.http_duration
+        .with_label_values(&[&method, &route])
+        .observe(started.elapsed().as_secs_f64());
+
+    response
+}
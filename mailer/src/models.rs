@@ -1,3 +1,5 @@
+use std::collections::{BTreeMap, HashMap};
+
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
 
@@ -6,15 +8,109 @@ use chrono::{DateTime, Utc};
 pub struct EmailJob {
     pub id: String,
     pub to: String,
-    pub subject: String,
+    /// Subject override; the template's own subject is used when `None`
+    #[serde(default)]
+    pub subject: Option<String>,
     pub template: EmailTemplate,
     pub data: serde_json::Value,
+    /// Requested locale, e.g. `pt-BR`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
     pub status: EmailStatus,
     pub created_at: DateTime<Utc>,
     pub sent_at: Option<DateTime<Utc>>,
     pub retries: u32,
     pub max_retries: u32,
     pub error: Option<String>,
+    /// Stored template used for `EmailTemplate::Custom` jobs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stored_template: Option<TemplateRef>,
+    /// Whether opens and clicks are tracked for this job
+    #[serde(default)]
+    pub track: bool,
+    #[serde(default)]
+    pub opens: u32,
+    #[serde(default)]
+    pub clicks: u32,
+    /// Most recent open/click events, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<TrackingEvent>,
+    /// Status-change webhook URL, overriding the global one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub callback_url: Option<String>,
+    /// Jobs still queued after this time are dropped as expired
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// `X-Request-Id` of the request that queued the job
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// W3C trace context (`traceparent`, `tracestate`) of the enqueueing
+    /// request, so the send is exported as part of the caller's trace
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub trace_context: HashMap<String, String>,
+    /// Every delivery attempt, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attempts: Vec<DeliveryAttempt>,
+    /// Caller's identifiers for the email, e.g. `user_id`, `license_id`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+/// One try at delivering a job, successful or not
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeliveryAttempt {
+    pub started_at: DateTime<Utc>,
+    /// Worker that made the attempt (`{hostname}-{pid}`)
+    pub worker_id: String,
+    /// Relay the message was handed to, e.g. `smtp+starttls://smtp.example.com:587`;
+    /// `None` when the attempt failed before sending, e.g. while rendering
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transport: Option<String>,
+    pub duration_ms: u64,
+    pub success: bool,
+    /// Reply code of the relay's final response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub smtp_code: Option<u16>,
+    /// Text of the relay's final response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub smtp_message: Option<String>,
+    /// ID the relay queued the message under, parsed from its reply
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub queue_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl EmailJob {
+    /// Name used to aggregate statistics: the stored template's name for
+    /// stored templates, otherwise the built-in template's name
+    pub fn template_name(&self) -> &str {
+        match &self.stored_template {
+            Some(stored) => &stored.name,
+            None => self.template.name(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TrackingEventKind {
+    Open,
+    Click,
+}
+
+/// An open or click recorded by the tracking endpoints
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrackingEvent {
+    pub kind: TrackingEventKind,
+    /// Target of a click
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+    pub at: DateTime<Utc>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -24,6 +120,23 @@ pub enum EmailStatus {
     Processing,
     Sent,
     Failed,
+    Bounced,
+    Expired,
+    Cancelled,
+}
+
+impl EmailStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmailStatus::Pending => "pending",
+            EmailStatus::Processing => "processing",
+            EmailStatus::Sent => "sent",
+            EmailStatus::Failed => "failed",
+            EmailStatus::Bounced => "bounced",
+            EmailStatus::Expired => "expired",
+            EmailStatus::Cancelled => "cancelled",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -37,11 +150,91 @@ pub enum EmailTemplate {
     Custom,
 }
 
+impl EmailTemplate {
+    /// Built-in templates shipped with the mailer
+    pub const BUILTIN: [EmailTemplate; 5] = [
+        EmailTemplate::Otp,
+        EmailTemplate::Otp2FA,
+        EmailTemplate::Welcome,
+        EmailTemplate::PasswordReset,
+        EmailTemplate::LicenseCreated,
+    ];
+
+    /// Name the template is registered under in `TemplateEngine`
+    pub fn name(&self) -> &'static str {
+        match self {
+            EmailTemplate::Otp => "otp",
+            EmailTemplate::Otp2FA => "otp_2fa",
+            EmailTemplate::Welcome => "welcome",
+            EmailTemplate::PasswordReset => "password_reset",
+            EmailTemplate::LicenseCreated => "license_created",
+            EmailTemplate::Custom => "custom",
+        }
+    }
+
+    /// Example `data` for previews and the template gallery
+    pub fn sample_data(&self) -> serde_json::Value {
+        match self {
+            EmailTemplate::Otp | EmailTemplate::Otp2FA => serde_json::json!({
+                "otp": "123456",
+                "email": "user@example.com"
+            }),
+            EmailTemplate::Welcome => serde_json::json!({
+                "email": "user@example.com"
+            }),
+            EmailTemplate::PasswordReset => serde_json::json!({
+                "email": "user@example.com",
+                "reset_link": "https://killcode.app/reset-password?token=sample"
+            }),
+            EmailTemplate::LicenseCreated => serde_json::json!({
+                "license_key": "KC-1234-5678-9ABC-DEF0",
+                "binary_name": "my-app",
+                "expires_at": "2026-12-31T23:59:59Z"
+            }),
+            EmailTemplate::Custom => serde_json::json!({
+                "html": "<p>Hello from KillCode</p>"
+            }),
+        }
+    }
+
+    /// Variables that must be present in `data` for this template to render
+    pub fn required_fields(&self) -> &'static [&'static str] {
+        match self {
+            EmailTemplate::Otp | EmailTemplate::Otp2FA => &["otp"],
+            EmailTemplate::Welcome => &["email"],
+            EmailTemplate::PasswordReset => &["email", "reset_link"],
+            EmailTemplate::LicenseCreated => &["license_key", "binary_name", "expires_at"],
+            EmailTemplate::Custom => &[],
+        }
+    }
+
+    /// Required variables that are absent, null or empty in `data`
+    pub fn missing_fields(&self, data: &serde_json::Value) -> Vec<&'static str> {
+        self.required_fields()
+            .iter()
+            .copied()
+            .filter(|field| match data.get(field) {
+                None | Some(serde_json::Value::Null) => true,
+                Some(serde_json::Value::String(s)) => s.trim().is_empty(),
+                Some(_) => false,
+            })
+            .collect()
+    }
+}
+
 /// Request to send an OTP email (signup)
 #[derive(Debug, Deserialize)]
 pub struct SendOtpRequest {
     pub email: String,
     pub otp: String,
+    #[serde(default)]
+    pub locale: Option<String>,
+    #[serde(default)]
+    pub callback_url: Option<String>,
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
 }
 
 /// Request to send a 2FA OTP email
@@ -49,16 +242,225 @@ pub struct SendOtpRequest {
 pub struct SendOtp2FARequest {
     pub email: String,
     pub otp: String,
+    #[serde(default)]
+    pub locale: Option<String>,
+    #[serde(default)]
+    pub callback_url: Option<String>,
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
 }
 
 /// Request to send a generic email
 #[derive(Debug, Deserialize)]
 pub struct SendEmailRequest {
     pub to: String,
-    pub subject: String,
+    /// Overrides the template's subject (required for raw `custom` HTML)
+    #[serde(default)]
+    pub subject: Option<String>,
     pub template: EmailTemplate,
     #[serde(default)]
     pub data: serde_json::Value,
+    /// Name of a stored template to render (only for `custom`)
+    #[serde(default)]
+    pub template_name: Option<String>,
+    /// Version of the stored template, defaults to the latest version
+    #[serde(default)]
+    pub template_version: Option<u32>,
+    /// Locale for the template and formatting helpers, e.g. `pt-BR`
+    #[serde(default)]
+    pub locale: Option<String>,
+    /// Track opens and clicks when tracking is enabled (default: true)
+    #[serde(default)]
+    pub track: Option<bool>,
+    /// URL to POST status-change events to, instead of the global webhook URL
+    #[serde(default)]
+    pub callback_url: Option<String>,
+    /// Drop the email if it hasn't been sent by this time
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Stored as `EmailJob::metadata`
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Optional per-job settings passed to `EmailQueue::enqueue`
+#[derive(Debug, Clone, Default)]
+pub struct JobOptions {
+    pub stored_template: Option<TemplateRef>,
+    pub locale: Option<String>,
+    pub track: bool,
+    pub callback_url: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub request_id: Option<String>,
+    pub metadata: BTreeMap<String, String>,
+    pub tags: Vec<String>,
+}
+
+/// Filters for `GET /jobs`; all given filters must match
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct JobQuery {
+    /// Recipient address, case-insensitive
+    #[serde(default)]
+    pub recipient: Option<String>,
+    /// Built-in or stored template name
+    #[serde(default)]
+    pub template: Option<String>,
+    #[serde(default)]
+    pub status: Option<EmailStatus>,
+    /// `X-Request-Id` of the request that queued the job
+    #[serde(default)]
+    pub request_id: Option<String>,
+    #[serde(default)]
+    pub tag: Option<String>,
+    /// Metadata entries the job must have, from `metadata.{key}={value}`
+    /// query parameters
+    #[serde(skip)]
+    pub metadata: BTreeMap<String, String>,
+    /// Only jobs created at or after this time
+    #[serde(default)]
+    pub since: Option<DateTime<Utc>>,
+    /// Only jobs created at or before this time
+    #[serde(default)]
+    pub until: Option<DateTime<Utc>>,
+    /// By creation time, newest first by default
+    #[serde(default)]
+    pub order: SortOrder,
+    /// `next_cursor` of the previous page
+    #[serde(default)]
+    pub cursor: Option<String>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+impl JobQuery {
+    /// Whether a job satisfies every filter
+    pub fn matches(&self, job: &EmailJob) -> bool {
+        self.recipient.as_ref().is_none_or(|r| job.to.trim().eq_ignore_ascii_case(r.trim()))
+            && self.template.as_ref().is_none_or(|t| job.template_name() == t)
+            && self.status.as_ref().is_none_or(|s| job.status == *s)
+            && self.request_id.as_ref().is_none_or(|id| job.request_id.as_ref() == Some(id))
+            && self.tag.as_ref().is_none_or(|tag| job.tags.contains(tag))
+            && self.metadata.iter().all(|(key, value)| job.metadata.get(key) == Some(value))
+            && self.since.is_none_or(|since| job.created_at >= since)
+            && self.until.is_none_or(|until| job.created_at <= until)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+/// A page of `GET /jobs` results
+#[derive(Debug, Serialize)]
+pub struct JobPage {
+    pub jobs: Vec<EmailJob>,
+    /// Pass as `cursor` to fetch the next page; `None` on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// Reference to a specific version of a stored template
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateRef {
+    pub name: String,
+    pub version: u32,
+}
+
+/// A single version of a template managed through the API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredTemplate {
+    pub name: String,
+    pub version: u32,
+    pub subject: String,
+    pub html: String,
+    #[serde(default)]
+    pub text: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Summary of a stored template and its version history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateInfo {
+    pub name: String,
+    pub latest_version: u32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Request to create a stored template
+#[derive(Debug, Deserialize)]
+pub struct CreateTemplateRequest {
+    pub name: String,
+    pub subject: String,
+    pub html: String,
+    #[serde(default)]
+    pub text: Option<String>,
+}
+
+/// Request to update a stored template (creates a new version)
+#[derive(Debug, Deserialize)]
+pub struct UpdateTemplateRequest {
+    pub subject: String,
+    pub html: String,
+    #[serde(default)]
+    pub text: Option<String>,
+}
+
+/// Request to roll a stored template back to an earlier version
+#[derive(Debug, Deserialize)]
+pub struct RollbackTemplateRequest {
+    pub version: u32,
+}
+
+/// Request to preview a stored template
+#[derive(Debug, Deserialize)]
+pub struct PreviewTemplateRequest {
+    #[serde(default)]
+    pub data: serde_json::Value,
+    #[serde(default)]
+    pub version: Option<u32>,
+    #[serde(default)]
+    pub locale: Option<String>,
+}
+
+/// Response for `/preview`
+#[derive(Debug, Serialize)]
+pub struct PreviewResponse {
+    #[serde(flatten)]
+    pub rendered: RenderedEmail,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stripped: Vec<String>,
+}
+
+/// Entry in the template gallery (`GET /templates`)
+#[derive(Debug, Serialize)]
+pub struct TemplateSummary {
+    pub name: String,
+    /// `builtin` or `stored`
+    pub source: &'static str,
+    /// Value to pass as `template` in `/send`
+    pub template: EmailTemplate,
+    pub variables: Vec<String>,
+    pub sample_data: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latest_version: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Rendered email content
+#[derive(Debug, Clone, Serialize)]
+pub struct RenderedEmail {
+    pub subject: String,
+    pub html: String,
+    pub text: Option<String>,
 }
 
 /// Response for email operations
@@ -67,13 +469,307 @@ pub struct EmailResponse {
     pub success: bool,
     pub job_id: Option<String>,
     pub message: String,
+    /// Content removed from custom HTML by the sanitizer
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stripped: Vec<String>,
 }
 
 /// Queue stats response
-#[derive(Debug, Serialize)]
+#[derive(Debug, Default, Serialize)]
 pub struct QueueStats {
     pub pending: u64,
     pub processing: u64,
     pub sent: u64,
     pub failed: u64,
 }
+
+impl QueueStats {
+    /// Count `job`, for stats filtered by tag or metadata
+    pub fn add(&mut self, job: &EmailJob) {
+        match job.status {
+            EmailStatus::Pending => self.pending += 1,
+            EmailStatus::Processing => self.processing += 1,
+            EmailStatus::Sent => self.sent += 1,
+            EmailStatus::Failed => self.failed += 1,
+            _ => {}
+        }
+    }
+}
+
+/// Filters for `/stats` and `/stats/templates`
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct StatsQuery {
+    #[serde(default)]
+    pub tag: Option<String>,
+    /// From `metadata.{key}={value}` query parameters
+    #[serde(skip)]
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl StatsQuery {
+    pub fn is_empty(&self) -> bool {
+        self.tag.is_none() && self.metadata.is_empty()
+    }
+}
+
+/// Delivery and engagement counters for one template
+#[derive(Debug, Default, Serialize)]
+pub struct TemplateStats {
+    pub template: String,
+    pub sent: u64,
+    pub opens: u64,
+    pub unique_opens: u64,
+    pub clicks: u64,
+    pub unique_clicks: u64,
+    /// Share of sent emails opened at least once
+    pub open_rate: f64,
+    /// Share of sent emails with at least one click
+    pub click_rate: f64,
+}
+
+impl TemplateStats {
+    /// Count `job` into its template's counters in `stats`, for stats
+    /// filtered by tag or metadata
+    pub fn add(stats: &mut BTreeMap<String, TemplateStats>, job: &EmailJob) {
+        let entry = stats.entry(job.template_name().to_string()).or_insert_with(|| TemplateStats {
+            template: job.template_name().to_string(),
+            ..Default::default()
+        });
+        if job.sent_at.is_some() {
+            entry.sent += 1;
+        }
+        entry.opens += job.opens as u64;
+        entry.clicks += job.clicks as u64;
+        entry.unique_opens += (job.opens > 0) as u64;
+        entry.unique_clicks += (job.clicks > 0) as u64;
+    }
+
+    /// Counters gathered with `add`, with their rates, ordered by template
+    pub fn collect(stats: BTreeMap<String, TemplateStats>) -> Vec<Self> {
+        stats
+            .into_values()
+            .map(|mut s| {
+                s.set_rates();
+                s
+            })
+            .collect()
+    }
+
+    pub fn set_rates(&mut self) {
+        if self.sent > 0 {
+            self.open_rate = self.unique_opens as f64 / self.sent as f64;
+            self.click_rate = self.unique_clicks as f64 / self.sent as f64;
+        }
+    }
+}
+
+/// Status change sent to webhook subscribers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusEvent {
+    /// Unique per event, for de-duplication on the receiving side
+    pub id: String,
+    pub job_id: String,
+    pub to: String,
+    pub template: String,
+    pub status: EmailStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// The job's metadata and tags, as given when it was queued
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered,
+    Failed,
+}
+
+/// A webhook delivery and the outcome of its attempts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub id: String,
+    pub url: String,
+    pub event: StatusEvent,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    /// HTTP status of the last attempt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_status: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BounceKind {
+    /// Permanent failure (5.x.x), the address is suppressed
+    Hard,
+    /// Temporary failure the relay gave up on (4.x.x)
+    Soft,
+    /// Spam complaint (ARF feedback report), the address is suppressed
+    Complaint,
+}
+
+impl BounceKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BounceKind::Hard => "hard bounce",
+            BounceKind::Soft => "soft bounce",
+            BounceKind::Complaint => "complaint",
+        }
+    }
+}
+
+/// Result of ingesting a DSN or ARF report
+#[derive(Debug, Clone, Serialize)]
+pub struct BounceReport {
+    pub kind: BounceKind,
+    /// Job the report refers to, when it could be traced
+    pub job_id: Option<String>,
+    pub recipient: Option<String>,
+    /// Enhanced status code, e.g. `5.1.1`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status_code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diagnostic: Option<String>,
+    /// Whether the recipient was added to the suppression list
+    pub suppressed: bool,
+}
+
+/// Address that no longer receives mail from the mailer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Suppression {
+    pub email: String,
+    pub reason: BounceKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diagnostic: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ComponentStatus {
+    Up,
+    Down,
+    /// The check is turned off in the configuration
+    Disabled,
+}
+
+/// Result of one readiness check
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentHealth {
+    pub status: ComponentStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<f64>,
+    /// Seconds since the worker loop last ran
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_heartbeat_secs: Option<f64>,
+    /// When a cached result was taken
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checked_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ComponentHealth {
+    pub fn status(status: ComponentStatus) -> Self {
+        Self {
+            status,
+            latency_ms: None,
+            last_heartbeat_secs: None,
+            checked_at: None,
+            error: None,
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.status != ComponentStatus::Down
+    }
+}
+
+/// Readiness response with per-component results
+#[derive(Debug, Serialize)]
+pub struct ReadinessResponse {
+    pub status: String,
+    pub service: String,
+    pub shutting_down: bool,
+    pub components: std::collections::BTreeMap<String, ComponentHealth>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(template: &str, stored: Option<&str>, sent: bool, opens: u32, clicks: u32) -> EmailJob {
+        serde_json::from_value(serde_json::json!({
+            "id": "job",
+            "to": "user@example.com",
+            "template": template,
+            "data": {},
+            "status": if sent { "sent" } else { "pending" },
+            "created_at": "2026-03-05T12:00:00Z",
+            "sent_at": sent.then_some("2026-03-05T12:01:00Z"),
+            "retries": 0,
+            "max_retries": 3,
+            "error": null,
+            "stored_template": stored.map(|name| serde_json::json!({"name": name, "version": 1})),
+            "opens": opens,
+            "clicks": clicks,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_template_stats_add() {
+        let jobs = [
+            job("welcome", None, true, 3, 0),
+            job("welcome", None, true, 0, 0),
+            job("welcome", None, false, 0, 0),
+            job("custom", Some("newsletter"), true, 1, 2),
+            job("otp", None, false, 0, 0),
+        ];
+        let mut totals = BTreeMap::new();
+        for job in &jobs {
+            TemplateStats::add(&mut totals, job);
+        }
+        let stats = TemplateStats::collect(totals);
+
+        let names: Vec<&str> = stats.iter().map(|s| s.template.as_str()).collect();
+        assert_eq!(names, ["newsletter", "otp", "welcome"]);
+
+        let welcome = &stats[2];
+        assert_eq!((welcome.sent, welcome.opens, welcome.unique_opens, welcome.clicks), (2, 3, 1, 0));
+        assert_eq!(welcome.open_rate, 0.5);
+        assert_eq!(welcome.click_rate, 0.0);
+
+        let newsletter = &stats[0];
+        assert_eq!((newsletter.sent, newsletter.clicks, newsletter.unique_clicks), (1, 2, 1));
+        assert_eq!((newsletter.open_rate, newsletter.click_rate), (1.0, 1.0));
+
+        // No rates without sent emails
+        assert_eq!((stats[1].sent, stats[1].open_rate), (0, 0.0));
+        assert!(TemplateStats::collect(BTreeMap::new()).is_empty());
+    }
+
+    #[test]
+    fn test_queue_stats_add() {
+        let mut stats = QueueStats::default();
+        for job in [job("otp", None, true, 0, 0), job("otp", None, false, 0, 0), job("otp", None, true, 0, 0)] {
+            stats.add(&job);
+        }
+        assert_eq!((stats.pending, stats.processing, stats.sent, stats.failed), (1, 0, 2, 0));
+    }
+}
@@ -1,14 +1,39 @@
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 use redis::{AsyncCommands, Client as RedisClient};
 use tokio::sync::Mutex;
 use chrono::Utc;
+use tracing::Instrument;
 use uuid::Uuid;
 
-use crate::models::{EmailJob, EmailStatus, EmailTemplate};
+use crate::logging;
+use crate::metrics::METRICS;
+use crate::telemetry;
+use crate::models::{
+    DeliveryAttempt, EmailJob, EmailStatus, EmailTemplate, JobOptions, JobPage, JobQuery, SortOrder, StatsQuery,
+    TemplateStats, TrackingEvent, TrackingEventKind,
+};
 
 const QUEUE_KEY: &str = "mailer:queue";
 const PROCESSING_KEY: &str = "mailer:processing";
 const JOBS_KEY: &str = "mailer:jobs";
+/// Hash of `{template}:{counter}` → count
+const TEMPLATE_STATS_KEY: &str = "mailer:template_stats";
+
+/// Sorted set of every job ID, scored by creation time (ms), for `/jobs`
+const JOBS_BY_CREATED_KEY: &str = "mailer:jobs_by_created";
+/// Sorted sets `{prefix}{field}:{value}` of job IDs, scored like the above
+const JOB_INDEX_KEY_PREFIX: &str = "mailer:jobs_by:";
+
+/// Number of tracking events kept on a job
+const MAX_JOB_EVENTS: usize = 50;
+
+/// Index entries fetched per round trip while filling a page of `/jobs`
+const LIST_BATCH: isize = 200;
+
+/// Index entries read for one `/jobs` request before returning a (possibly
+/// short) page with a cursor, so sparse filters don't scan the whole index
+const MAX_LIST_SCAN: usize = 5000;
 
 pub struct EmailQueue {
     redis: Arc<Mutex<redis::aio::MultiplexedConnection>>,
@@ -25,8 +50,11 @@ impl EmailQueue {
     }
 
     /// Add a new email job to the queue
-    pub async fn enqueue(&self, to: String, subject: String, template: EmailTemplate, data: serde_json::Value) -> Result<String, anyhow::Error> {
+    #[tracing::instrument(name = "enqueue", skip_all, fields(job_id = tracing::field::Empty))]
+    pub async fn enqueue(&self, to: String, subject: Option<String>, template: EmailTemplate, data: serde_json::Value, options: JobOptions) -> Result<String, anyhow::Error> {
+        let _timer = METRICS.enqueue_duration.start_timer();
         let job_id = Uuid::new_v4().to_string();
+        tracing::Span::current().record("job_id", job_id.as_str());
         
         let job = EmailJob {
             id: job_id.clone(),
@@ -34,12 +62,25 @@ impl EmailQueue {
             subject,
             template,
             data,
+            locale: options.locale,
             status: EmailStatus::Pending,
             created_at: Utc::now(),
             sent_at: None,
             retries: 0,
             max_retries: 3,
             error: None,
+            stored_template: options.stored_template,
+            track: options.track,
+            opens: 0,
+            clicks: 0,
+            events: Vec::new(),
+            callback_url: options.callback_url,
+            expires_at: options.expires_at,
+            request_id: options.request_id,
+            trace_context: telemetry::current_context(),
+            attempts: Vec::new(),
+            metadata: options.metadata,
+            tags: options.tags,
         };
 
         let job_json = serde_json::to_string(&job)?;
@@ -48,15 +89,34 @@ impl EmailQueue {
         
         // Store job details
         let _: () = conn.hset(JOBS_KEY, &job_id, &job_json).await?;
+        index_job(&mut conn, &job).await?;
         
         // Add to queue
         let _: () = conn.rpush(QUEUE_KEY, &job_id).await?;
         
-        log::info!("📧 Enqueued email job: {} to {}", job_id, job.to);
+        METRICS.email(job.template_name(), "queued");
+        log::info!("📧 Enqueued email job: {} to {}", job_id, logging::recipient(&job.to));
         
         Ok(job_id)
     }
 
+    /// Round trip to Redis, for readiness checks
+    pub async fn ping(&self) -> Result<(), anyhow::Error> {
+        let mut conn = self.redis.lock().await;
+        let _: String = redis::cmd("PING").query_async(&mut *conn).await?;
+        Ok(())
+    }
+
+    /// Number of jobs per lane: waiting in the queue and being sent
+    pub async fn depth(&self) -> Result<Vec<(&'static str, u64)>, anyhow::Error> {
+        let mut conn = self.redis.lock().await;
+        
+        let pending: u64 = conn.llen(QUEUE_KEY).await?;
+        let processing: u64 = conn.scard(PROCESSING_KEY).await?;
+        
+        Ok(vec![("pending", pending), ("processing", processing)])
+    }
+
     /// Get the next job from the queue
     pub async fn dequeue(&self) -> Result<Option<EmailJob>, anyhow::Error> {
         let mut conn = self.redis.lock().await;
@@ -70,13 +130,22 @@ impl EmailQueue {
             
             if let Some(json) = job_json {
                 let mut job: EmailJob = serde_json::from_str(&json)?;
-                job.status = EmailStatus::Processing;
-                
-                // Update job status
-                let _: () = conn.hset(JOBS_KEY, &id, serde_json::to_string(&job)?).await?;
-                
-                // Add to processing set
-                let _: () = conn.sadd(PROCESSING_KEY, &id).await?;
+                let previous = std::mem::replace(&mut job.status, EmailStatus::Processing);
+
+                // Only polls that pick up a job are traced
+                let span = tracing::info_span!("dequeue", job_id = %id);
+                telemetry::set_parent(&span, &job.trace_context);
+
+                async {
+                    // Update job status
+                    save_job(&mut conn, &job, &previous).await?;
+                    
+                    // Add to processing set
+                    let _: () = conn.sadd(PROCESSING_KEY, &id).await?;
+                    Ok::<_, anyhow::Error>(())
+                }
+                .instrument(span)
+                .await?;
                 
                 return Ok(Some(job));
             }
@@ -85,58 +154,147 @@ impl EmailQueue {
         Ok(None)
     }
 
-    /// Mark a job as completed
-    pub async fn complete(&self, job_id: &str) -> Result<(), anyhow::Error> {
+    /// Mark a job as completed; false when it no longer exists
+    pub async fn complete(&self, job_id: &str, attempt: DeliveryAttempt) -> Result<bool, anyhow::Error> {
         let mut conn = self.redis.lock().await;
         
         let job_json: Option<String> = conn.hget(JOBS_KEY, job_id).await?;
         
         if let Some(json) = job_json {
             let mut job: EmailJob = serde_json::from_str(&json)?;
-            job.status = EmailStatus::Sent;
+            let previous = std::mem::replace(&mut job.status, EmailStatus::Sent);
             job.sent_at = Some(Utc::now());
+            job.attempts.push(attempt);
             
-            let _: () = conn.hset(JOBS_KEY, job_id, serde_json::to_string(&job)?).await?;
+            save_job(&mut conn, &job, &previous).await?;
             let _: () = conn.srem(PROCESSING_KEY, job_id).await?;
+            let _: () = conn.hincr(TEMPLATE_STATS_KEY, format!("{}:sent", job.template_name()), 1).await?;
+            
+            METRICS.email(job.template_name(), "sent");
+            if let Ok(waited) = (Utc::now() - job.created_at).to_std() {
+                METRICS.queue_wait.with_label_values(&[job.template_name()]).observe(waited.as_secs_f64());
+            }
             
             log::info!("✅ Email sent successfully: {}", job_id);
+            return Ok(true);
+        }
+        
+        Ok(false)
+    }
+
+    /// Return an interrupted job to the front of the queue without counting
+    /// it as a failed attempt
+    pub async fn requeue(&self, job_id: &str) -> Result<(), anyhow::Error> {
+        let mut conn = self.redis.lock().await;
+        
+        let removed: u32 = conn.srem(PROCESSING_KEY, job_id).await?;
+        if removed == 0 {
+            return Ok(());
+        }
+        
+        let job_json: Option<String> = conn.hget(JOBS_KEY, job_id).await?;
+        if let Some(json) = job_json {
+            let mut job: EmailJob = serde_json::from_str(&json)?;
+            let previous = std::mem::replace(&mut job.status, EmailStatus::Pending);
+            save_job(&mut conn, &job, &previous).await?;
+            let _: () = conn.lpush(QUEUE_KEY, job_id).await?;
+            
+            log::warn!("↪️ Returned email job {} to the queue", job_id);
         }
         
         Ok(())
     }
 
     /// Mark a job as failed (will retry if retries < max_retries)
-    pub async fn fail(&self, job_id: &str, error: &str) -> Result<bool, anyhow::Error> {
+    ///
+    /// Returns whether the job will be retried, or `None` when it no longer
+    /// exists.
+    pub async fn fail(&self, job_id: &str, error: &str, attempt: DeliveryAttempt) -> Result<Option<bool>, anyhow::Error> {
         let mut conn = self.redis.lock().await;
         
         let job_json: Option<String> = conn.hget(JOBS_KEY, job_id).await?;
         
         if let Some(json) = job_json {
             let mut job: EmailJob = serde_json::from_str(&json)?;
+            let previous = job.status.clone();
             job.retries += 1;
             job.error = Some(error.to_string());
+            job.attempts.push(attempt);
             
             let _: () = conn.srem(PROCESSING_KEY, job_id).await?;
             
             if job.retries < job.max_retries {
                 // Re-queue for retry
                 job.status = EmailStatus::Pending;
-                let _: () = conn.hset(JOBS_KEY, job_id, serde_json::to_string(&job)?).await?;
+                save_job(&mut conn, &job, &previous).await?;
                 let _: () = conn.rpush(QUEUE_KEY, job_id).await?;
                 
-                log::warn!("⚠️ Email failed, retrying ({}/{}): {} - {}", job.retries, job.max_retries, job_id, error);
-                return Ok(true); // Will retry
+                METRICS.retries.with_label_values(&[job.template_name()]).inc();
+                log::warn!("⚠️ Email failed, retrying ({}/{}): {} - {}", job.retries, job.max_retries, job_id, logging::redact(error));
+                return Ok(Some(true)); // Will retry
             } else {
                 // Max retries reached
                 job.status = EmailStatus::Failed;
-                let _: () = conn.hset(JOBS_KEY, job_id, serde_json::to_string(&job)?).await?;
+                save_job(&mut conn, &job, &previous).await?;
                 
-                log::error!("❌ Email permanently failed: {} - {}", job_id, error);
-                return Ok(false); // No more retries
+                METRICS.email(job.template_name(), "failed");
+                log::error!("❌ Email permanently failed: {} - {}", job_id, logging::redact(error));
+                return Ok(Some(false)); // No more retries
             }
         }
         
-        Ok(false)
+        Ok(None)
+    }
+
+    /// Cancel a job that hasn't been picked up by the worker yet
+    ///
+    /// Returns `None` when the job doesn't exist or is no longer pending.
+    pub async fn cancel(&self, job_id: &str) -> Result<Option<EmailJob>, anyhow::Error> {
+        let mut conn = self.redis.lock().await;
+        
+        let job_json: Option<String> = conn.hget(JOBS_KEY, job_id).await?;
+        let Some(json) = job_json else {
+            return Ok(None);
+        };
+        
+        let mut job: EmailJob = serde_json::from_str(&json)?;
+        if job.status != EmailStatus::Pending {
+            return Ok(None);
+        }
+        
+        // Removing it from the queue is what guarantees the worker won't send it
+        let removed: u32 = conn.lrem(QUEUE_KEY, 0, job_id).await?;
+        if removed == 0 {
+            return Ok(None);
+        }
+        
+        job.status = EmailStatus::Cancelled;
+        save_job(&mut conn, &job, &EmailStatus::Pending).await?;
+        METRICS.email(job.template_name(), "cancelled");
+        
+        log::info!("🚫 Cancelled email job: {}", job_id);
+        
+        Ok(Some(job))
+    }
+
+    /// Move a job to a final status such as `Expired` or `Bounced`
+    pub async fn finish(&self, job_id: &str, status: EmailStatus, reason: &str) -> Result<Option<EmailJob>, anyhow::Error> {
+        let mut conn = self.redis.lock().await;
+        
+        let job_json: Option<String> = conn.hget(JOBS_KEY, job_id).await?;
+        let Some(json) = job_json else {
+            return Ok(None);
+        };
+        
+        let mut job: EmailJob = serde_json::from_str(&json)?;
+        let previous = std::mem::replace(&mut job.status, status);
+        job.error = Some(reason.to_string());
+        
+        save_job(&mut conn, &job, &previous).await?;
+        let _: () = conn.srem(PROCESSING_KEY, job_id).await?;
+        
+        METRICS.email(job.template_name(), job.status.as_str());
+        Ok(Some(job))
     }
 
     /// Get queue statistics
@@ -169,6 +327,78 @@ impl EmailQueue {
         })
     }
 
+    /// Record an open or click against a job and its template's counters
+    ///
+    /// Returns `false` when the job no longer exists.
+    pub async fn record_event(&self, job_id: &str, event: TrackingEvent) -> Result<bool, anyhow::Error> {
+        let mut conn = self.redis.lock().await;
+        
+        let job_json: Option<String> = conn.hget(JOBS_KEY, job_id).await?;
+        let Some(json) = job_json else {
+            return Ok(false);
+        };
+        
+        let mut job: EmailJob = serde_json::from_str(&json)?;
+        let (total, unique, first) = match event.kind {
+            TrackingEventKind::Open => {
+                job.opens += 1;
+                ("opens", "unique_opens", job.opens == 1)
+            }
+            TrackingEventKind::Click => {
+                job.clicks += 1;
+                ("clicks", "unique_clicks", job.clicks == 1)
+            }
+        };
+        
+        job.events.push(event);
+        if job.events.len() > MAX_JOB_EVENTS {
+            let excess = job.events.len() - MAX_JOB_EVENTS;
+            job.events.drain(..excess);
+        }
+        
+        let _: () = conn.hset(JOBS_KEY, job_id, serde_json::to_string(&job)?).await?;
+        let _: () = conn.hincr(TEMPLATE_STATS_KEY, format!("{}:{}", job.template_name(), total), 1).await?;
+        if first {
+            let _: () = conn.hincr(TEMPLATE_STATS_KEY, format!("{}:{}", job.template_name(), unique), 1).await?;
+        }
+        
+        Ok(true)
+    }
+
+    /// Sent, open and click counters per template
+    pub async fn template_stats(&self) -> Result<Vec<TemplateStats>, anyhow::Error> {
+        let mut conn = self.redis.lock().await;
+        
+        let counters: HashMap<String, u64> = conn.hgetall(TEMPLATE_STATS_KEY).await?;
+        let mut stats: BTreeMap<String, TemplateStats> = BTreeMap::new();
+        
+        for (field, count) in counters {
+            let Some((template, counter)) = field.rsplit_once(':') else {
+                continue;
+            };
+            let entry = stats.entry(template.to_string()).or_insert_with(|| TemplateStats {
+                template: template.to_string(),
+                ..Default::default()
+            });
+            match counter {
+                "sent" => entry.sent = count,
+                "opens" => entry.opens = count,
+                "unique_opens" => entry.unique_opens = count,
+                "clicks" => entry.clicks = count,
+                "unique_clicks" => entry.unique_clicks = count,
+                _ => {}
+            }
+        }
+        
+        Ok(stats
+            .into_values()
+            .map(|mut s| {
+                s.set_rates();
+                s
+            })
+            .collect())
+    }
+
     /// Get a job by ID
     pub async fn get_job(&self, job_id: &str) -> Result<Option<EmailJob>, anyhow::Error> {
         let mut conn = self.redis.lock().await;
@@ -182,4 +412,353 @@ impl EmailQueue {
         
         Ok(None)
     }
+
+    /// A page of jobs matching `query`, ordered by creation time
+    ///
+    /// Walks the smallest secondary index that applies to the filters and
+    /// checks the remaining filters on each job, so `mailer:jobs` is never
+    /// scanned. At most `MAX_LIST_SCAN` index entries are read per request;
+    /// when that cap is hit the page may hold fewer than `limit` jobs and the
+    /// cursor continues the scan. The Redis connection is released between
+    /// batches.
+    pub async fn list_jobs(&self, query: &JobQuery, limit: usize) -> Result<JobPage, anyhow::Error> {
+        let cursor = query.cursor.as_deref().map(parse_cursor).transpose()?;
+        let index = {
+            let mut conn = self.redis.lock().await;
+            smallest_index(&mut conn, query).await?
+        };
+
+        let mut position = ScanPosition {
+            score: cursor.as_ref().map(|(score, _)| *score),
+            skip: 0,
+        };
+        let mut jobs = Vec::new();
+        let mut scanned = 0;
+
+        let next_cursor = loop {
+            let (entries, batch) = self.scan_batch(&index, query, cursor.as_ref(), &position).await?;
+            scanned += entries.len();
+
+            let room = limit - jobs.len();
+            let more = batch.len() > room || (batch.len() == room && entries.len() as isize == LIST_BATCH);
+            jobs.extend(batch.into_iter().take(room));
+            // A full page with jobs possibly left continues after its last job
+            if jobs.len() == limit && more {
+                break jobs.last().map(|job| format!("{}:{}", job.created_at.timestamp_millis(), job.id));
+            }
+
+            if (entries.len() as isize) < LIST_BATCH {
+                break None;
+            }
+            position.advance(&entries);
+            if scanned >= MAX_LIST_SCAN {
+                break entries.last().map(|(id, score)| format!("{}:{}", *score as i64, id));
+            }
+        };
+
+        Ok(JobPage { jobs, next_cursor })
+    }
+
+    /// One batch of `index` entries from `position` (with `cursor`'s
+    /// already returned entries dropped) and their jobs that match `query`
+    ///
+    /// Holds the Redis connection only for this batch.
+    async fn scan_batch(
+        &self,
+        index: &str,
+        query: &JobQuery,
+        cursor: Option<&(i64, String)>,
+        position: &ScanPosition,
+    ) -> Result<(Vec<(String, f64)>, Vec<EmailJob>), anyhow::Error> {
+        let mut min = query.since.map(|t| t.timestamp_millis());
+        let mut max = query.until.map(|t| t.timestamp_millis());
+        if let Some(score) = position.score {
+            match query.order {
+                SortOrder::Asc => min = Some(min.map_or(score, |min| min.max(score))),
+                SortOrder::Desc => max = Some(max.map_or(score, |max| max.min(score))),
+            }
+        }
+        let min = min.map_or_else(|| "-inf".to_string(), |min| min.to_string());
+        let max = max.map_or_else(|| "+inf".to_string(), |max| max.to_string());
+
+        let mut conn = self.redis.lock().await;
+        let entries: Vec<(String, f64)> = match query.order {
+            SortOrder::Asc => conn.zrangebyscore_limit_withscores(index, &min, &max, position.skip, LIST_BATCH).await?,
+            SortOrder::Desc => conn.zrevrangebyscore_limit_withscores(index, &max, &min, position.skip, LIST_BATCH).await?,
+        };
+
+        let ids: Vec<&String> = entries
+            .iter()
+            .filter(|(id, score)| cursor.is_none_or(|cursor| past_cursor(cursor, query.order, id, *score)))
+            .map(|(id, _)| id)
+            .collect();
+        if ids.is_empty() {
+            return Ok((entries, Vec::new()));
+        }
+
+        let jsons: Vec<Option<String>> = redis::cmd("HMGET")
+            .arg(JOBS_KEY)
+            .arg(&ids)
+            .query_async(&mut *conn)
+            .await?;
+        drop(conn);
+
+        // Entries whose job is gone, or whose status moved on, are skipped
+        let jobs = jsons
+            .into_iter()
+            .flatten()
+            .filter_map(|json| serde_json::from_str::<EmailJob>(&json).ok())
+            .filter(|job| query.matches(job))
+            .collect();
+
+        Ok((entries, jobs))
+    }
+
+    /// Fold every job matching the tag and metadata filters into `acc`, for
+    /// filtered stats
+    ///
+    /// Reads the smallest applicable index in batches, releasing the Redis
+    /// connection between them so sends and workers aren't held up, and only
+    /// keeps one batch of jobs in memory at a time.
+    pub async fn fold_matching_jobs<T>(
+        &self,
+        filter: &StatsQuery,
+        mut acc: T,
+        mut add: impl FnMut(&mut T, &EmailJob),
+    ) -> Result<T, anyhow::Error> {
+        let query = JobQuery {
+            tag: filter.tag.clone(),
+            metadata: filter.metadata.clone(),
+            order: SortOrder::Asc,
+            ..Default::default()
+        };
+        let index = {
+            let mut conn = self.redis.lock().await;
+            smallest_index(&mut conn, &query).await?
+        };
+
+        let mut position = ScanPosition { score: None, skip: 0 };
+        loop {
+            let (entries, batch) = self.scan_batch(&index, &query, None, &position).await?;
+            for job in &batch {
+                add(&mut acc, job);
+            }
+            if (entries.len() as isize) < LIST_BATCH {
+                break;
+            }
+            position.advance(&entries);
+        }
+
+        Ok(acc)
+    }
+
+    /// Index jobs stored before the `/jobs` indexes existed
+    ///
+    /// Returns the number of jobs indexed; does nothing when every job is
+    /// already indexed.
+    pub async fn rebuild_indexes(&self) -> Result<usize, anyhow::Error> {
+        let mut conn = self.redis.lock().await;
+
+        let stored: usize = conn.hlen(JOBS_KEY).await?;
+        let indexed: usize = conn.zcard(JOBS_BY_CREATED_KEY).await?;
+        if indexed >= stored {
+            return Ok(0);
+        }
+
+        let jobs: Vec<String> = conn.hvals(JOBS_KEY).await?;
+        let mut count = 0;
+        for job_json in jobs {
+            if let Ok(job) = serde_json::from_str::<EmailJob>(&job_json) {
+                index_job(&mut conn, &job).await?;
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+}
+
+fn normalize_recipient(email: &str) -> String {
+    email.trim().to_ascii_lowercase()
+}
+
+fn index_key(field: &str, value: &str) -> String {
+    format!("{}{}:{}", JOB_INDEX_KEY_PREFIX, field, value)
+}
+
+/// The smallest index covering one of the query's filters, or the all-jobs
+/// index without filters; the other filters are checked on each job
+async fn smallest_index(conn: &mut redis::aio::MultiplexedConnection, query: &JobQuery) -> Result<String, anyhow::Error> {
+    let mut candidates = Vec::new();
+    if let Some(recipient) = &query.recipient {
+        candidates.push(index_key("to", &normalize_recipient(recipient)));
+    }
+    if let Some(template) = &query.template {
+        candidates.push(index_key("template", template));
+    }
+    if let Some(status) = &query.status {
+        candidates.push(index_key("status", status.as_str()));
+    }
+    if let Some(request_id) = &query.request_id {
+        candidates.push(index_key("request", request_id));
+    }
+    if let Some(tag) = &query.tag {
+        candidates.push(index_key("tag", tag));
+    }
+    for (key, value) in &query.metadata {
+        candidates.push(metadata_index_key(key, value));
+    }
+
+    let mut smallest: Option<(u64, String)> = None;
+    for key in candidates {
+        let size: u64 = conn.zcard(&key).await?;
+        if smallest.as_ref().is_none_or(|(smallest, _)| size < *smallest) {
+            smallest = Some((size, key));
+        }
+    }
+    Ok(smallest.map(|(_, key)| key).unwrap_or_else(|| JOBS_BY_CREATED_KEY.to_string()))
+}
+
+fn metadata_index_key(key: &str, value: &str) -> String {
+    index_key("meta", &format!("{}={}", key, value))
+}
+
+/// Add a job to the all-jobs index and those of its recipient, template,
+/// status, request ID, tags and metadata
+async fn index_job(conn: &mut redis::aio::MultiplexedConnection, job: &EmailJob) -> Result<(), anyhow::Error> {
+    let score = job.created_at.timestamp_millis();
+    let mut keys = vec![
+        JOBS_BY_CREATED_KEY.to_string(),
+        index_key("to", &normalize_recipient(&job.to)),
+        index_key("template", job.template_name()),
+        index_key("status", job.status.as_str()),
+    ];
+    if let Some(request_id) = &job.request_id {
+        keys.push(index_key("request", request_id));
+    }
+    keys.extend(job.tags.iter().map(|tag| index_key("tag", tag)));
+    keys.extend(job.metadata.iter().map(|(key, value)| metadata_index_key(key, value)));
+
+    for key in keys {
+        let _: () = conn.zadd(key, &job.id, score).await?;
+    }
+    Ok(())
+}
+
+/// Store a job, moving it between status indexes when its status changed
+async fn save_job(
+    conn: &mut redis::aio::MultiplexedConnection,
+    job: &EmailJob,
+    previous: &EmailStatus,
+) -> Result<(), anyhow::Error> {
+    let _: () = conn.hset(JOBS_KEY, &job.id, serde_json::to_string(job)?).await?;
+    if job.status != *previous {
+        let _: () = conn.zrem(index_key("status", previous.as_str()), &job.id).await?;
+        let _: () = conn.zadd(index_key("status", job.status.as_str()), &job.id, job.created_at.timestamp_millis()).await?;
+    }
+    Ok(())
+}
+
+/// Where a scan of an index resumes: the score reached and how many entries
+/// with that score were already read
+#[derive(Debug, PartialEq)]
+struct ScanPosition {
+    score: Option<i64>,
+    skip: isize,
+}
+
+impl ScanPosition {
+    /// Move past a batch read from this position
+    fn advance(&mut self, entries: &[(String, f64)]) {
+        let Some((_, last)) = entries.last() else {
+            return;
+        };
+        let last = *last as i64;
+        let at_last = entries.iter().rev().take_while(|(_, score)| *score as i64 == last).count() as isize;
+
+        // A batch entirely at the score it started from adds to the skip
+        self.skip = if self.score == Some(last) && at_last == entries.len() as isize {
+            self.skip + at_last
+        } else {
+            at_last
+        };
+        self.score = Some(last);
+    }
+}
+
+/// Whether an index entry comes after `cursor` in `order`
+///
+/// Entries with equal scores are ordered by member, so the cursor's job ID
+/// tells which of them were already returned.
+fn past_cursor(cursor: &(i64, String), order: SortOrder, id: &str, score: f64) -> bool {
+    let (cursor_score, cursor_id) = cursor;
+    let score = score as i64;
+    match order {
+        SortOrder::Asc => score > *cursor_score || (score == *cursor_score && id > cursor_id.as_str()),
+        SortOrder::Desc => score < *cursor_score || (score == *cursor_score && id < cursor_id.as_str()),
+    }
+}
+
+/// `{created_at ms}:{job_id}` of the last job on the previous page
+pub fn parse_cursor(cursor: &str) -> Result<(i64, String), anyhow::Error> {
+    cursor
+        .split_once(':')
+        .and_then(|(score, id)| Some((score.parse().ok()?, id.to_string())))
+        .filter(|(_, id)| !id.is_empty())
+        .ok_or_else(|| anyhow::anyhow!("Invalid cursor: {}", cursor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(list: &[(&str, i64)]) -> Vec<(String, f64)> {
+        list.iter().map(|(id, score)| (id.to_string(), *score as f64)).collect()
+    }
+
+    #[test]
+    fn test_parse_cursor() {
+        assert_eq!(parse_cursor("1700000000000:job-1").unwrap(), (1700000000000, "job-1".to_string()));
+        // Job IDs may contain colons; only the first one separates the score
+        assert_eq!(parse_cursor("5:a:b").unwrap(), (5, "a:b".to_string()));
+        assert_eq!(parse_cursor("-1:x").unwrap(), (-1, "x".to_string()));
+        assert!(parse_cursor("1700000000000").is_err());
+        assert!(parse_cursor("1700000000000:").is_err());
+        assert!(parse_cursor("abc:job-1").is_err());
+        assert!(parse_cursor("").is_err());
+    }
+
+    #[test]
+    fn test_past_cursor_breaks_ties_by_id() {
+        let cursor = (100, "job-m".to_string());
+
+        assert!(past_cursor(&cursor, SortOrder::Asc, "job-a", 101.0));
+        assert!(past_cursor(&cursor, SortOrder::Asc, "job-z", 100.0));
+        assert!(!past_cursor(&cursor, SortOrder::Asc, "job-m", 100.0));
+        assert!(!past_cursor(&cursor, SortOrder::Asc, "job-a", 100.0));
+        assert!(!past_cursor(&cursor, SortOrder::Asc, "job-z", 99.0));
+
+        assert!(past_cursor(&cursor, SortOrder::Desc, "job-z", 99.0));
+        assert!(past_cursor(&cursor, SortOrder::Desc, "job-a", 100.0));
+        assert!(!past_cursor(&cursor, SortOrder::Desc, "job-m", 100.0));
+        assert!(!past_cursor(&cursor, SortOrder::Desc, "job-z", 100.0));
+        assert!(!past_cursor(&cursor, SortOrder::Desc, "job-a", 101.0));
+    }
+
+    #[test]
+    fn test_scan_position_advance() {
+        let mut position = ScanPosition { score: None, skip: 0 };
+        position.advance(&entries(&[("a", 1), ("b", 2), ("c", 2)]));
+        assert_eq!(position, ScanPosition { score: Some(2), skip: 2 });
+
+        // A batch entirely at the same score skips past the previous ones too
+        position.advance(&entries(&[("d", 2), ("e", 2)]));
+        assert_eq!(position, ScanPosition { score: Some(2), skip: 4 });
+
+        position.advance(&entries(&[("f", 2), ("g", 3)]));
+        assert_eq!(position, ScanPosition { score: Some(3), skip: 1 });
+
+        position.advance(&[]);
+        assert_eq!(position, ScanPosition { score: Some(3), skip: 1 });
+    }
 }
@@ -0,0 +1,473 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+
+use lol_html::html_content::{Comment, ContentType, Element, TextChunk};
+use lol_html::{doc_comments, element, rewrite_str, text, RewriteStrSettings};
+use serde::{Deserialize, Serialize};
+
+use crate::tracking;
+
+/// Elements removed together with their content
+const DANGEROUS_TAGS: &[&str] = &[
+    "script", "iframe", "frame", "frameset", "object", "embed", "applet", "form", "input",
+    "button", "textarea", "select", "option", "noscript", "template", "svg", "math", "link",
+    "base", "audio", "video", "source", "track", "canvas",
+];
+
+const DEFAULT_ALLOWED_TAGS: &[&str] = &[
+    "html", "head", "body", "title", "meta", "style", "a", "abbr", "b", "blockquote", "br",
+    "caption", "center", "code", "col", "colgroup", "dd", "div", "dl", "dt", "em", "font", "h1",
+    "h2", "h3", "h4", "h5", "h6", "hr", "i", "img", "li", "ol", "p", "pre", "s", "small", "span",
+    "strong", "sub", "sup", "table", "tbody", "td", "tfoot", "th", "thead", "tr", "u", "ul",
+];
+
+const DEFAULT_ALLOWED_ATTRIBUTES: &[&str] = &[
+    "align", "alt", "bgcolor", "border", "cellpadding", "cellspacing", "charset", "class",
+    "color", "colspan", "content", "dir", "height", "href", "http-equiv", "id", "lang", "name",
+    "role", "rowspan", "src", "style", "target", "title", "valign", "width", "xmlns",
+];
+
+/// Attributes holding URLs that are checked against schemes and domains
+const URL_ATTRIBUTES: &[&str] = &["href", "src", "background"];
+
+const ALLOWED_URL_SCHEMES: &[&str] = &["http", "https", "mailto", "cid"];
+
+/// Default maximum size of custom HTML bodies (256 KiB)
+pub const DEFAULT_MAX_BODY_BYTES: usize = 256 * 1024;
+
+/// What to do when custom HTML contains disallowed content
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SanitizeMode {
+    /// Strip the offending content and send the rest
+    Clean,
+    /// Refuse to send the email
+    Reject,
+}
+
+impl std::str::FromStr for SanitizeMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "clean" => Ok(SanitizeMode::Clean),
+            "reject" => Ok(SanitizeMode::Reject),
+            other => Err(anyhow::anyhow!("Unknown sanitize mode: {}", other)),
+        }
+    }
+}
+
+/// Result of sanitizing a body
+#[derive(Debug)]
+pub struct Sanitized {
+    pub html: String,
+    /// Human-readable description of everything that was removed
+    pub stripped: Vec<String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SanitizeError {
+    #[error("HTML body is {size} bytes, exceeding the {max} byte limit")]
+    TooLarge { size: usize, max: usize },
+    #[error("HTML body contains disallowed content: {}", .0.join("; "))]
+    Rejected(Vec<String>),
+    #[error("Failed to parse HTML: {0}")]
+    Parse(String),
+}
+
+/// Allow-list based cleaner for caller-supplied (`custom`) HTML
+///
+/// Removes scripts, forms and other active content, attributes outside the
+/// allow-list (including all `on*` handlers), URLs with unexpected schemes or
+/// domains, tracking pixels and CSS that loads external resources.
+pub struct HtmlSanitizer {
+    allowed_tags: HashSet<String>,
+    allowed_attributes: HashSet<String>,
+    /// Domains links and images may point to (subdomains included); any when `None`
+    allowed_domains: Option<Vec<String>>,
+    max_body_bytes: usize,
+    mode: SanitizeMode,
+}
+
+impl Default for HtmlSanitizer {
+    fn default() -> Self {
+        Self {
+            allowed_tags: DEFAULT_ALLOWED_TAGS.iter().map(|t| t.to_string()).collect(),
+            allowed_attributes: DEFAULT_ALLOWED_ATTRIBUTES.iter().map(|a| a.to_string()).collect(),
+            allowed_domains: None,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            mode: SanitizeMode::Clean,
+        }
+    }
+}
+
+impl HtmlSanitizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the allowed tags
+    pub fn with_allowed_tags<I: IntoIterator<Item = S>, S: AsRef<str>>(mut self, tags: I) -> Self {
+        self.allowed_tags = tags.into_iter().map(|t| t.as_ref().trim().to_ascii_lowercase()).collect();
+        self
+    }
+
+    /// Replace the allowed attributes
+    pub fn with_allowed_attributes<I: IntoIterator<Item = S>, S: AsRef<str>>(mut self, attributes: I) -> Self {
+        self.allowed_attributes = attributes.into_iter().map(|a| a.as_ref().trim().to_ascii_lowercase()).collect();
+        self
+    }
+
+    /// Restrict links and images to these domains and their subdomains
+    pub fn with_allowed_domains<I: IntoIterator<Item = S>, S: AsRef<str>>(mut self, domains: I) -> Self {
+        self.allowed_domains = Some(domains.into_iter().map(|d| d.as_ref().trim().to_ascii_lowercase()).collect());
+        self
+    }
+
+    pub fn with_max_body_bytes(mut self, max_body_bytes: usize) -> Self {
+        self.max_body_bytes = max_body_bytes;
+        self
+    }
+
+    pub fn with_mode(mut self, mode: SanitizeMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Clean `html`, or reject it when the mode is `Reject` and anything had to be removed
+    pub fn sanitize(&self, html: &str) -> Result<Sanitized, SanitizeError> {
+        if html.len() > self.max_body_bytes {
+            return Err(SanitizeError::TooLarge { size: html.len(), max: self.max_body_bytes });
+        }
+
+        let sanitized = self.clean(html)?;
+
+        if self.mode == SanitizeMode::Reject && !sanitized.stripped.is_empty() {
+            return Err(SanitizeError::Rejected(sanitized.stripped));
+        }
+
+        Ok(sanitized)
+    }
+
+    fn clean(&self, html: &str) -> Result<Sanitized, SanitizeError> {
+        let stripped = RefCell::new(Vec::<String>::new());
+        let report = |message: String| {
+            let mut stripped = stripped.borrow_mut();
+            if !stripped.contains(&message) {
+                stripped.push(message);
+            }
+        };
+        let style_buffer = RefCell::new(String::new());
+
+        let html = rewrite_str(html, RewriteStrSettings {
+            element_content_handlers: vec![
+                element!("*", |el: &mut Element| {
+                    self.clean_element(el, &report);
+                    Ok(())
+                }),
+                // CSS is buffered and only written back once the whole block
+                // is known not to load external resources
+                text!("style", |chunk: &mut TextChunk| {
+                    style_buffer.borrow_mut().push_str(chunk.as_str());
+                    chunk.remove();
+                    if chunk.last_in_text_node() {
+                        let css = std::mem::take(&mut *style_buffer.borrow_mut());
+                        match unsafe_css(&css) {
+                            Some(reason) => report(format!("removed <style> block ({})", reason)),
+                            None => chunk.after(&css, ContentType::Html),
+                        }
+                    }
+                    Ok(())
+                }),
+            ],
+            document_content_handlers: vec![doc_comments!(|comment: &mut Comment| {
+                comment.remove();
+                Ok(())
+            })],
+            ..RewriteStrSettings::new()
+        })
+        .map_err(|e| SanitizeError::Parse(e.to_string()))?;
+
+        Ok(Sanitized {
+            html,
+            stripped: stripped.into_inner(),
+        })
+    }
+
+    fn clean_element(&self, el: &mut Element, report: &impl Fn(String)) {
+        let tag = el.tag_name().to_ascii_lowercase();
+
+        if DANGEROUS_TAGS.contains(&tag.as_str()) {
+            report(format!("removed <{}> element", tag));
+            el.remove();
+            return;
+        }
+        if !self.allowed_tags.contains(&tag) {
+            report(format!("removed disallowed <{}> tag (content kept)", tag));
+            el.remove_and_keep_content();
+            return;
+        }
+        if tag == "meta"
+            && el.get_attribute("http-equiv").is_some_and(|v| v.eq_ignore_ascii_case("refresh"))
+        {
+            report("removed <meta http-equiv=\"refresh\">".to_string());
+            el.remove();
+            return;
+        }
+        if tag == "img" && is_tracking_pixel(el) {
+            report("removed tracking pixel image".to_string());
+            el.remove();
+            return;
+        }
+
+        let attributes: Vec<(String, String)> = el
+            .attributes()
+            .iter()
+            .map(|a| (a.name().to_ascii_lowercase(), a.value()))
+            .collect();
+
+        for (name, value) in attributes {
+            if !self.allowed_attributes.contains(&name) {
+                report(format!("removed attribute {} from <{}>", name, tag));
+                el.remove_attribute(&name);
+            } else if URL_ATTRIBUTES.contains(&name.as_str()) {
+                if let Err(reason) = self.check_url(&value) {
+                    report(format!("removed {} from <{}> ({})", name, tag, reason));
+                    el.remove_attribute(&name);
+                }
+            } else if name == "style"
+                && let Some(reason) = unsafe_style_attribute(&value)
+            {
+                report(format!("removed style from <{}> ({})", tag, reason));
+                el.remove_attribute(&name);
+            }
+        }
+    }
+
+    fn check_url(&self, value: &str) -> Result<(), String> {
+        let value = value.trim();
+        // `#` anchors are harmless and common in email markup
+        if value.is_empty() || value.starts_with('#') {
+            return Ok(());
+        }
+
+        let url = url::Url::parse(value).map_err(|_| format!("invalid or relative URL '{}'", value))?;
+
+        if !ALLOWED_URL_SCHEMES.contains(&url.scheme()) {
+            return Err(format!("{}: URLs are not allowed", url.scheme()));
+        }
+
+        if let (Some(domains), Some(host)) = (&self.allowed_domains, url.host_str()) {
+            let host = host.to_ascii_lowercase();
+            let allowed = domains
+                .iter()
+                .any(|d| host == *d || host.ends_with(&format!(".{}", d)));
+            if !allowed {
+                return Err(format!("domain {} is not allowed", host));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A 0/1 pixel image is almost always an open-tracking beacon
+fn is_tracking_pixel(el: &Element) -> bool {
+    let tiny = |attr: &str| {
+        el.get_attribute(attr)
+            .and_then(|v| v.trim().trim_end_matches("px").parse::<u32>().ok())
+            .is_some_and(|v| v <= 1)
+    };
+    tiny("width") && tiny("height")
+}
+
+/// Reason a piece of CSS is unsafe: it loads external resources or runs script
+///
+/// Checked on the normalized CSS so escapes (`\75 rl(`), comments
+/// (`u/**/rl(`) and whitespace can't hide a keyword.
+fn unsafe_css(css: &str) -> Option<&'static str> {
+    let css = normalize_css(css);
+    if css.contains("url(") || css.contains("image-set(") {
+        Some("external resource via url()")
+    } else if css.contains("@import") {
+        Some("@import")
+    } else if css.contains("expression(") || css.contains("javascript:") {
+        Some("script in CSS")
+    } else {
+        None
+    }
+}
+
+/// Reason a `style` attribute is unsafe
+///
+/// Attribute values reach us with their character references still encoded
+/// and the mail client decodes them before parsing the CSS, so they are
+/// decoded first; any reference left over (named ones we don't know, numeric
+/// ones without `;`) could still hide a keyword and is refused.
+fn unsafe_style_attribute(value: &str) -> Option<&'static str> {
+    let css = tracking::decode_entities(value);
+    if css.contains('&') {
+        return Some("character reference in CSS");
+    }
+    unsafe_css(&css)
+}
+
+/// Lowercase `css` with comments and whitespace removed and escapes decoded
+fn normalize_css(css: &str) -> String {
+    let mut out = String::with_capacity(css.len());
+    let mut chars = css.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = '\0';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+            }
+            '\\' => {
+                let mut hex = String::new();
+                while hex.len() < 6
+                    && let Some(&h) = chars.peek()
+                    && h.is_ascii_hexdigit()
+                {
+                    hex.push(h);
+                    chars.next();
+                }
+                if hex.is_empty() {
+                    // `\r`, `\(`: the escaped character itself; an escaped newline is dropped
+                    if let Some(escaped) = chars.next() {
+                        out.push(escaped);
+                    }
+                } else {
+                    // A single whitespace character ends a hex escape
+                    if chars.peek().is_some_and(|c| c.is_whitespace()) {
+                        chars.next();
+                    }
+                    let decoded = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32).unwrap_or('\u{fffd}');
+                    out.push(decoded);
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+
+    out.chars().filter(|c| !c.is_whitespace() && *c != '\0').flat_map(char::to_lowercase).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clean(html: &str) -> Sanitized {
+        HtmlSanitizer::new().sanitize(html).unwrap()
+    }
+
+    #[test]
+    fn test_removes_scripts_and_active_content() {
+        let sanitized = clean("<p>Hi</p><script>alert(1)</script><iframe src=\"https://x.test\"></iframe><!-- note -->");
+        assert_eq!(sanitized.html, "<p>Hi</p>");
+        assert_eq!(sanitized.stripped, vec!["removed <script> element", "removed <iframe> element"]);
+    }
+
+    #[test]
+    fn test_removes_event_handlers_and_disallowed_attributes() {
+        let sanitized = clean(r#"<img src="https://x.test/a.png" onerror="alert(1)" data-x="1"><p OnClick="go()">x</p>"#);
+        assert_eq!(sanitized.html, r#"<img src="https://x.test/a.png"><p>x</p>"#);
+        assert!(sanitized.stripped.contains(&"removed attribute onerror from <img>".to_string()));
+        assert!(sanitized.stripped.contains(&"removed attribute data-x from <img>".to_string()));
+        assert!(sanitized.stripped.contains(&"removed attribute onclick from <p>".to_string()));
+    }
+
+    #[test]
+    fn test_removes_unsafe_urls() {
+        let sanitized = clean(concat!(
+            r#"<a href="javascript:alert(1)">a</a><a href=" JavaScript:alert(1)">b</a>"#,
+            r#"<img src="data:image/png;base64,AAAA"><a href="/relative">c</a>"#,
+            r##"<a href="#top">d</a><a href="mailto:a@b.test">e</a>"##,
+        ));
+        assert_eq!(
+            sanitized.html,
+            r##"<a>a</a><a>b</a><img><a>c</a><a href="#top">d</a><a href="mailto:a@b.test">e</a>"##
+        );
+        assert!(sanitized.stripped.contains(&"removed href from <a> (javascript: URLs are not allowed)".to_string()));
+        assert!(sanitized.stripped.contains(&"removed src from <img> (data: URLs are not allowed)".to_string()));
+    }
+
+    #[test]
+    fn test_disallowed_tags_keep_content() {
+        let sanitized = HtmlSanitizer::new()
+            .with_allowed_tags(["p", "b"])
+            .sanitize("<p><b>bold</b> <i>italic</i></p>")
+            .unwrap();
+        assert_eq!(sanitized.html, "<p><b>bold</b> italic</p>");
+        assert_eq!(sanitized.stripped, vec!["removed disallowed <i> tag (content kept)"]);
+    }
+
+    #[test]
+    fn test_link_domain_allow_list() {
+        let sanitizer = HtmlSanitizer::new().with_allowed_domains(["example.com"]);
+        let sanitized = sanitizer
+            .sanitize(concat!(
+                r#"<a href="https://example.com/a">1</a><a href="https://www.Example.com/b">2</a>"#,
+                r#"<a href="https://example.com.evil.test/c">3</a><a href="https://notexample.com">4</a>"#,
+            ))
+            .unwrap();
+        assert_eq!(
+            sanitized.html,
+            r#"<a href="https://example.com/a">1</a><a href="https://www.Example.com/b">2</a><a>3</a><a>4</a>"#
+        );
+        assert!(sanitized.stripped.contains(&"removed href from <a> (domain example.com.evil.test is not allowed)".to_string()));
+    }
+
+    #[test]
+    fn test_unsafe_css() {
+        assert_eq!(unsafe_css("color: red; background: #fff"), None);
+        assert_eq!(unsafe_css("background: URL(https://x.test/p.png)"), Some("external resource via url()"));
+        assert_eq!(unsafe_css(r"background: \75 rl(https://x.test/p.png)"), Some("external resource via url()"));
+        assert_eq!(unsafe_css(r"background: \000055RL(https://x.test)"), Some("external resource via url()"));
+        assert_eq!(unsafe_css(r"background: u\rl(https://x.test)"), Some("external resource via url()"));
+        assert_eq!(unsafe_css("background: u/* x */rl(https://x.test)"), Some("external resource via url()"));
+        assert_eq!(unsafe_css("background-image: image-set(\"https://x.test/a.png\" 1x)"), Some("external resource via url()"));
+        assert_eq!(unsafe_css(r"@\69mport 'https://x.test/a.css';"), Some("@import"));
+        assert_eq!(unsafe_css("width: expression (alert(1))"), Some("script in CSS"));
+    }
+
+    #[test]
+    fn test_unsafe_style_attribute_decodes_entities() {
+        assert_eq!(unsafe_style_attribute("color: red; font-family: &quot;Arial&quot;"), None);
+        assert_eq!(unsafe_style_attribute("background:u&#114;l(https://t.example/p.gif)"), Some("external resource via url()"));
+        assert_eq!(unsafe_style_attribute("background:&#x55;RL(https://t.example/p.gif)"), Some("external resource via url()"));
+        assert_eq!(unsafe_style_attribute("x:expr&#101;ssion(alert(1))"), Some("script in CSS"));
+        assert_eq!(unsafe_style_attribute("x:expr&#101ssion(alert(1))"), Some("character reference in CSS"));
+        assert_eq!(unsafe_style_attribute("background:url&lpar;https://t.example/p.gif)"), Some("character reference in CSS"));
+
+        let sanitized = clean(r#"<p style="background:u&#114;l(https://t.example/p.gif)">x</p><p style="x:expr&#101;ssion(alert(1))">y</p>"#);
+        assert_eq!(sanitized.html, "<p>x</p><p>y</p>");
+    }
+
+    #[test]
+    fn test_unsafe_styles_are_removed() {
+        let sanitized = clean(concat!(
+            r"<style>p { background: \75 rl(https://x.test/p.png) }</style>",
+            r#"<style>p { color: red }</style><p style="background: u\72l(https://x.test)">x</p>"#,
+        ));
+        assert_eq!(sanitized.html, "<style></style><style>p { color: red }</style><p>x</p>");
+        assert_eq!(
+            sanitized.stripped,
+            vec!["removed <style> block (external resource via url())", "removed style from <p> (external resource via url())"]
+        );
+    }
+
+    #[test]
+    fn test_reject_mode_and_size_limit() {
+        let sanitizer = HtmlSanitizer::new().with_mode(SanitizeMode::Reject).with_max_body_bytes(64);
+        assert!(matches!(sanitizer.sanitize("<p onclick=\"x()\">x</p>"), Err(SanitizeError::Rejected(_))));
+        assert!(matches!(sanitizer.sanitize(&"a".repeat(65)), Err(SanitizeError::TooLarge { size: 65, max: 64 })));
+        assert_eq!(sanitizer.sanitize("<p>fine</p>").unwrap().html, "<p>fine</p>");
+    }
+}
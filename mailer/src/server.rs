@@ -0,0 +1,115 @@
+use std::path::{Path, PathBuf};
+
+use openssl::ssl::{SslAcceptor, SslAcceptorBuilder, SslFiletype, SslMethod, SslVerifyMode};
+
+/// Address the HTTP server listens on
+#[derive(Debug, Clone, PartialEq)]
+pub enum Listener {
+    /// `host:port`, e.g. `0.0.0.0:8000` or `[::1]:8000`
+    Tcp(String),
+    /// Unix domain socket, written as `unix:/path/to/mailer.sock`
+    Unix(PathBuf),
+}
+
+impl std::fmt::Display for Listener {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Listener::Tcp(addr) => write!(f, "{}", addr),
+            Listener::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// Parse a comma-separated list of listeners
+pub fn parse_listeners(input: &str) -> Result<Vec<Listener>, anyhow::Error> {
+    let listeners = input
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| match entry.strip_prefix("unix:") {
+            Some(path) if !path.is_empty() => Ok(Listener::Unix(PathBuf::from(path))),
+            Some(_) => Err(anyhow::anyhow!("Missing socket path in '{}'", entry)),
+            None if entry.rsplit_once(':').is_some_and(|(_, port)| port.parse::<u16>().is_ok()) => {
+                Ok(Listener::Tcp(entry.to_string()))
+            }
+            None => Err(anyhow::anyhow!("Invalid listen address '{}', expected host:port or unix:/path", entry)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if listeners.is_empty() {
+        anyhow::bail!("No listen address configured");
+    }
+
+    Ok(listeners)
+}
+
+/// Remove a socket left behind by a previous run, so binding doesn't fail
+pub fn remove_stale_socket(path: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        if let Ok(metadata) = std::fs::symlink_metadata(path)
+            && metadata.file_type().is_socket()
+        {
+            std::fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// TLS settings for TCP listeners
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    pub cert_file: PathBuf,
+    pub key_file: PathBuf,
+    /// CA bundle client certificates must chain to; enables mutual TLS
+    pub client_ca_file: Option<PathBuf>,
+}
+
+impl TlsConfig {
+    /// Build an acceptor; called once per listener since builders are consumed
+    pub fn acceptor(&self) -> Result<SslAcceptorBuilder, anyhow::Error> {
+        let mut builder = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls_server())?;
+        builder.set_certificate_chain_file(&self.cert_file)?;
+        builder.set_private_key_file(&self.key_file, SslFiletype::PEM)?;
+        builder.check_private_key()?;
+
+        if let Some(ca_file) = &self.client_ca_file {
+            builder.set_ca_file(ca_file)?;
+            builder.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+        }
+
+        Ok(builder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_listeners() {
+        assert_eq!(
+            parse_listeners("0.0.0.0:8000, [::1]:8001,unix:/run/mailer/mailer.sock").unwrap(),
+            vec![
+                Listener::Tcp("0.0.0.0:8000".to_string()),
+                Listener::Tcp("[::1]:8001".to_string()),
+                Listener::Unix(PathBuf::from("/run/mailer/mailer.sock")),
+            ]
+        );
+        assert_eq!(parse_listeners("localhost:8000,,").unwrap(), vec![Listener::Tcp("localhost:8000".to_string())]);
+        assert_eq!(parse_listeners("unix:/tmp/a.sock").unwrap()[0].to_string(), "unix:/tmp/a.sock");
+    }
+
+    #[test]
+    fn test_parse_listeners_rejects_invalid_entries() {
+        assert_eq!(parse_listeners("").unwrap_err().to_string(), "No listen address configured");
+        assert_eq!(parse_listeners(" , ").unwrap_err().to_string(), "No listen address configured");
+        assert_eq!(parse_listeners("unix:").unwrap_err().to_string(), "Missing socket path in 'unix:'");
+        for entry in ["8000", "localhost", "localhost:http", "0.0.0.0:70000", "[::1]"] {
+            assert!(parse_listeners(entry).is_err(), "{}", entry);
+        }
+        // One bad entry fails the whole list
+        assert!(parse_listeners("0.0.0.0:8000,nope").is_err());
+    }
+}
@@ -0,0 +1,135 @@
+use std::time::Duration;
+
+use tokio::sync::watch;
+use tokio::time::{Instant, Interval};
+
+/// Coordinates a graceful shutdown between the HTTP server and the workers
+///
+/// Once triggered, `/send` endpoints refuse new jobs and workers stop picking
+/// up work. A job that is still being sent when the grace period runs out is
+/// abandoned and returned to the queue.
+#[derive(Clone)]
+pub struct Shutdown {
+    started: watch::Sender<Option<Instant>>,
+    grace: Duration,
+}
+
+impl Shutdown {
+    pub fn new(grace: Duration) -> Self {
+        Self {
+            started: watch::Sender::new(None),
+            grace,
+        }
+    }
+
+    /// Start shutting down; later calls keep the original deadline
+    pub fn trigger(&self) {
+        self.started.send_if_modified(|started| {
+            if started.is_some() {
+                return false;
+            }
+            *started = Some(Instant::now());
+            true
+        });
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.started.borrow().is_some()
+    }
+
+    pub fn grace(&self) -> Duration {
+        self.grace
+    }
+
+    /// Resolves once shutdown has been triggered
+    pub async fn triggered(&self) {
+        self.started_at().await;
+    }
+
+    /// Waits for the next tick of a worker's `ticker`; false once shutdown has
+    /// been triggered, even when the tick is ready too, so a worker never
+    /// starts new work after the trigger
+    pub async fn tick(&self, ticker: &mut Interval) -> bool {
+        tokio::select! {
+            biased;
+            _ = self.triggered() => false,
+            _ = ticker.tick() => !self.is_triggered(),
+        }
+    }
+
+    /// Resolves when the grace period after the trigger has run out
+    pub async fn deadline(&self) {
+        let started = self.started_at().await;
+        tokio::time::sleep_until(started + self.grace).await;
+    }
+
+    async fn started_at(&self) -> Instant {
+        let mut rx = self.started.subscribe();
+        let started = rx.wait_for(Option::is_some).await.map(|started| *started);
+        match started {
+            Ok(started) => started.unwrap_or_else(Instant::now),
+            // The sender lives in `self`, so it can't have been dropped
+            Err(_) => std::future::pending().await,
+        }
+    }
+}
+
+/// Resolves on SIGTERM or Ctrl-C
+pub async fn signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                tokio::select! {
+                    _ = sigterm.recv() => {}
+                    _ = tokio::signal::ctrl_c() => {}
+                }
+            }
+            Err(e) => {
+                log::warn!("Failed to listen for SIGTERM: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_trigger_stops_ticks() {
+        let shutdown = Shutdown::new(Duration::from_secs(30));
+        let mut ticker = tokio::time::interval(Duration::from_millis(1));
+        assert!(shutdown.tick(&mut ticker).await);
+        assert!(shutdown.tick(&mut ticker).await);
+
+        shutdown.trigger();
+        // The ticker is due as well, but a worker must not start another job
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        for _ in 0..10 {
+            assert!(!shutdown.tick(&mut ticker).await);
+        }
+        assert!(shutdown.is_triggered());
+    }
+
+    #[tokio::test]
+    async fn test_deadline_keeps_first_trigger() {
+        let shutdown = Shutdown::new(Duration::from_millis(50));
+        shutdown.trigger();
+        let started = Instant::now();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        shutdown.trigger();
+
+        shutdown.triggered().await;
+        shutdown.deadline().await;
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(50), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(70), "{:?}", elapsed);
+    }
+}
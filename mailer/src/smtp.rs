@@ -1,15 +1,40 @@
 use lettre::{
+    message::dkim::{DkimConfig, DkimSigningAlgorithm, DkimSigningKey},
     message::header::ContentType,
+    message::{Mailbox, MultiPart},
+    address::{Address, Envelope},
     transport::smtp::authentication::Credentials,
     transport::smtp::client::{Tls, TlsParameters},
     AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
 };
+use std::collections::HashMap;
 use std::time::Duration;
 
+use crate::logging;
+use crate::telemetry;
+use crate::metrics::METRICS;
+
 pub struct SmtpClient {
     mailer: AsyncSmtpTransport<Tokio1Executor>,
-    from_email: String,
-    from_name: String,
+    /// Sender set with `with_from`, or the SMTP username when that is an
+    /// address; `None` until one of them is
+    from: Option<Mailbox>,
+    /// DKIM signing configs keyed by sender domain
+    dkim: HashMap<String, DkimConfig>,
+    /// Envelope sender bounces are returned to, VERP-encoded per job as
+    /// `local+{job_id}@domain`; the From address is used when `None`
+    bounce_address: Option<Address>,
+    /// Relay URL recorded on delivery attempts, e.g. `smtps://smtp.example.com:465`
+    transport: String,
+}
+
+/// The relay's final reply to a message, accepting or rejecting it
+#[derive(Debug, Clone)]
+pub struct SmtpReply {
+    pub code: u16,
+    pub message: String,
+    /// ID the relay queued the message under, when its reply includes one
+    pub queue_id: Option<String>,
 }
 
 impl SmtpClient {
@@ -35,6 +60,12 @@ impl SmtpClient {
         let creds = Credentials::new(username.to_string(), password.to_string());
         let timeout = Duration::from_secs(30);
 
+        let scheme = match (secure, implicit_tls) {
+            (true, true) => "smtps",
+            (true, false) => "smtp+starttls",
+            (false, _) => "smtp",
+        };
+
         let mailer = if secure {
             let tls_params = TlsParameters::builder(host.to_string())
                 .dangerous_accept_invalid_certs(accept_invalid_certs)
@@ -72,27 +103,303 @@ impl SmtpClient {
 
         Ok(Self {
             mailer,
-            from_email: username.to_string(),
-            from_name: "KillCode".to_string(),
+            // Relays such as SendGrid use a username like `apikey`, which
+            // only works together with an explicit From
+            from: username.parse().ok().map(|address| Mailbox::new(Some("KillCode".to_string()), address)),
+            dkim: HashMap::new(),
+            bounce_address: None,
+            transport: format!("{}://{}:{}", scheme, host, port),
         })
     }
 
-    pub async fn send(&self, to: &str, subject: &str, html_body: &str) -> Result<(), anyhow::Error> {
-        let from = format!("{} <{}>", self.from_name, self.from_email);
-        
-        log::debug!("Building email: from={}, to={}, subject={}", from, to, subject);
+    /// Override the sender address (e.g. `KillCode <noreply@killcode.app>`)
+    ///
+    /// Defaults to the SMTP username, which is not necessarily an address on
+    /// our own domain when sending through a third-party relay.
+    pub fn with_from(mut self, from: &str) -> Result<Self, anyhow::Error> {
+        self.from = Some(from.parse()?);
+        Ok(self)
+    }
+
+    /// Return bounces to a VERP address derived from `address`
+    /// (e.g. `bounces@killcode.app` → `bounces+{job_id}@killcode.app`)
+    pub fn with_bounce_address(mut self, address: &str) -> Result<Self, anyhow::Error> {
+        self.bounce_address = Some(address.parse()?);
+        Ok(self)
+    }
+
+    /// Relay URL, e.g. `smtp+starttls://smtp.example.com:587`
+    pub fn transport(&self) -> &str {
+        &self.transport
+    }
+
+    /// Connect, authenticate and send NOOP to verify the relay and credentials
+    pub async fn check_connection(&self) -> Result<(), anyhow::Error> {
+        if !self.mailer.test_connection().await? {
+            anyhow::bail!("SMTP relay did not answer NOOP");
+        }
+        Ok(())
+    }
+
+    fn from(&self) -> Result<&Mailbox, anyhow::Error> {
+        self.from
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("No sender address: set SMTP_FROM, or use an email address as SMTP_USER"))
+    }
+
+    /// Register a DKIM key used to sign mail sent from `domain`
+    ///
+    /// # Arguments
+    /// * `domain` - Sender domain the key signs for (the `d=` tag)
+    /// * `selector` - DNS selector the public key is published under (the `s=` tag)
+    /// * `key_path` - Path to the private key file: PKCS#1 PEM for RSA, base64 of
+    ///   the raw 32-byte secret for Ed25519
+    /// * `algorithm` - Signing algorithm matching the key
+    pub fn add_dkim_key(
+        &mut self,
+        domain: &str,
+        selector: &str,
+        key_path: &str,
+        algorithm: DkimSigningAlgorithm,
+    ) -> Result<(), anyhow::Error> {
+        let key = read_dkim_key(key_path, algorithm)?;
+
+        let domain = domain.to_ascii_lowercase();
+        log::info!("🔏 DKIM signing enabled for {} (selector: {}, algorithm: {})", domain, selector, algorithm);
+
+        self.dkim.insert(
+            domain.clone(),
+            DkimConfig::default_config(selector.to_string(), domain, key),
+        );
+
+        Ok(())
+    }
+
+    /// Find the DKIM config for a sender domain, falling back to parent domains
+    /// so that a key for `killcode.app` also signs mail from `mail.killcode.app`
+    fn dkim_for(&self, domain: &str) -> Option<&DkimConfig> {
+        let mut domain = domain.to_ascii_lowercase();
+        loop {
+            if let Some(config) = self.dkim.get(&domain) {
+                return Some(config);
+            }
+            match domain.split_once('.') {
+                Some((_, parent)) if parent.contains('.') => domain = parent.to_string(),
+                _ => return None,
+            }
+        }
+    }
+
+    /// Send an HTML email, as multipart/alternative when a plain-text body is given
+    ///
+    /// The job ID is embedded in the `Message-ID` (and the VERP envelope
+    /// sender, when configured) so bounces can be traced back to the job.
+    #[tracing::instrument(
+        name = "smtp.send",
+        skip_all,
+        fields(otel.status_code = tracing::field::Empty, error = tracing::field::Empty)
+    )]
+    pub async fn send(&self, job_id: &str, to: &str, subject: &str, html_body: &str, text_body: Option<&str>) -> Result<SmtpReply, anyhow::Error> {
+        let from = self.from()?;
+        log::debug!("Building email: from={}, to={}, subject={}", from, logging::recipient(to), subject);
         
-        let email = Message::builder()
-            .from(from.parse()?)
-            .to(to.parse()?)
+        let to: Mailbox = to.parse()?;
+        let mut builder = Message::builder()
+            .from(from.clone())
+            .to(to.clone())
             .subject(subject)
-            .header(ContentType::TEXT_HTML)
-            .body(html_body.to_string())?;
+            .message_id(Some(format!("<{}@{}>", job_id, from.email.domain())));
+
+        if let Some(bounce) = &self.bounce_address {
+            let sender = Address::new(format!("{}+{}", bounce.user(), job_id), bounce.domain())?;
+            builder = builder.envelope(Envelope::new(Some(sender), vec![to.email])?);
+        }
+
+        let mut email = match text_body {
+            Some(text) => builder.multipart(MultiPart::alternative_plain_html(
+                text.to_string(),
+                html_body.to_string(),
+            ))?,
+            None => builder
+                .header(ContentType::TEXT_HTML)
+                .body(html_body.to_string())?,
+        };
+
+        if let Some(dkim) = self.dkim_for(from.email.domain()) {
+            log::debug!("Signing email with DKIM for {}", from.email.domain());
+            email.sign(dkim);
+        }
 
         log::debug!("Sending email via SMTP...");
-        self.mailer.send(email).await?;
+        let timer = METRICS.smtp_duration.start_timer();
+        let result = self.mailer.send(email).await;
+        timer.observe_duration();
+        if let Err(e) = &result {
+            METRICS.smtp_errors.with_label_values(&[&error_code(e)]).inc();
+            telemetry::record_error(&logging::redact(&e.to_string()));
+        }
+        let response = result?;
         log::debug!("Email sent successfully!");
         
-        Ok(())
+        let message = response.message().collect::<Vec<_>>().join(" ");
+        Ok(SmtpReply {
+            code: response.code().into(),
+            queue_id: queue_id(&message),
+            message,
+        })
+    }
+}
+
+/// The relay's reply to a send it rejected; `None` for failures without
+/// one, such as timeouts
+pub fn rejection(error: &anyhow::Error) -> Option<SmtpReply> {
+    let error = error.downcast_ref::<lettre::transport::smtp::Error>()?;
+    let code = error.status()?;
+    Some(SmtpReply {
+        code: code.into(),
+        // lettre keeps the reply text as the error's source
+        message: std::error::Error::source(error).map(|e| e.to_string()).unwrap_or_default(),
+        queue_id: None,
+    })
+}
+
+/// Queue ID in a relay's reply to DATA
+///
+/// Postfix and Exim say `queued as <id>` (or `id=<id>`); other relays such as
+/// SES and Gmail just include the ID, taken as the longest token with a digit
+/// that isn't the enhanced status code.
+fn queue_id(message: &str) -> Option<String> {
+    let tokens: Vec<&str> = message
+        .split_whitespace()
+        .map(|t| t.trim_matches(|c: char| matches!(c, '<' | '>' | '(' | ')' | '[' | ']' | ',' | ';')))
+        .collect();
+
+    if let Some(pos) = tokens.windows(2).position(|w| w[0].eq_ignore_ascii_case("queued") && w[1].eq_ignore_ascii_case("as")) {
+        return tokens.get(pos + 2).map(|id| id.to_string());
+    }
+    if let Some(id) = tokens.iter().find_map(|t| t.strip_prefix("id=")) {
+        return Some(id.to_string());
+    }
+
+    tokens
+        .iter()
+        .skip_while(|t| is_enhanced_status(t))
+        .filter(|t| t.len() >= 8 && t.chars().any(|c| c.is_ascii_digit()))
+        .max_by_key(|t| t.len())
+        .map(|id| id.to_string())
+}
+
+/// `2.0.0`-style enhanced status codes
+fn is_enhanced_status(token: &str) -> bool {
+    let parts: Vec<&str> = token.split('.').collect();
+    parts.len() == 3 && parts.iter().all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Reply code of a rejected send (`550`), or the kind of failure without one
+fn error_code(error: &lettre::transport::smtp::Error) -> String {
+    if let Some(code) = error.status() {
+        return code.to_string();
+    }
+    let class = if error.is_timeout() {
+        "timeout"
+    } else if error.is_tls() {
+        "tls"
+    } else if error.is_client() {
+        "client"
+    } else {
+        "connection"
+    };
+    class.to_string()
+}
+
+/// Read and parse a PEM-encoded DKIM private key
+pub fn read_dkim_key(key_path: &str, algorithm: DkimSigningAlgorithm) -> Result<DkimSigningKey, anyhow::Error> {
+    let pem = std::fs::read_to_string(key_path)
+        .map_err(|e| anyhow::anyhow!("Failed to read DKIM key {}: {}", key_path, e))?;
+    DkimSigningKey::new(pem.trim(), algorithm)
+        .map_err(|e| anyhow::anyhow!("Invalid DKIM key {}: {}", key_path, e))
+}
+
+/// Parse a DKIM algorithm name (`rsa` or `ed25519`)
+pub fn parse_dkim_algorithm(name: &str) -> Result<DkimSigningAlgorithm, anyhow::Error> {
+    match name.to_ascii_lowercase().as_str() {
+        "rsa" | "rsa-sha256" => Ok(DkimSigningAlgorithm::Rsa),
+        "ed25519" | "ed25519-sha256" => Ok(DkimSigningAlgorithm::Ed25519),
+        other => Err(anyhow::anyhow!("Unsupported DKIM algorithm: {}", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Base64 of a 32-byte Ed25519 secret, as DKIM key files hold it
+    const ED25519_KEY: &str = "AQIDBAUGBwgJCgsMDQ4PEBESExQVFhcYGRobHB0eHyA=";
+
+    /// The transport's connection pool needs a runtime
+    fn client(username: &str) -> SmtpClient {
+        SmtpClient::new("localhost", 25, username, "secret", false, false, false).unwrap()
+    }
+
+    fn add_key(client: &mut SmtpClient, domain: &str, selector: &str) {
+        let path = std::env::temp_dir().join(format!("mailer-dkim-{}-{}.key", std::process::id(), selector));
+        std::fs::write(&path, ED25519_KEY).unwrap();
+        client.add_dkim_key(domain, selector, path.to_str().unwrap(), DkimSigningAlgorithm::Ed25519).unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_from_defaults_to_username_address() {
+        assert_eq!(client("noreply@killcode.app").from().unwrap().email.to_string(), "noreply@killcode.app");
+
+        // API-key style usernames need an explicit From
+        let client = client("apikey");
+        assert!(client.from().is_err());
+        let client = client.with_from("KillCode <noreply@killcode.app>").unwrap();
+        assert_eq!(client.from().unwrap().email.to_string(), "noreply@killcode.app");
+    }
+
+    #[tokio::test]
+    async fn test_dkim_for() {
+        let mut client = client("noreply@killcode.app");
+        add_key(&mut client, "KillCode.app", "root");
+        add_key(&mut client, "eu.mail.killcode.app", "eu");
+
+        assert!(client.dkim_for("killcode.app").is_some());
+        assert!(client.dkim_for("KILLCODE.APP").is_some());
+        // Subdomains fall back to the closest parent with a key
+        assert!(client.dkim_for("mail.killcode.app").is_some());
+        assert!(client.dkim_for("us.mail.killcode.app").is_some());
+        assert!(client.dkim_for("eu.mail.killcode.app").is_some());
+        // but never to a bare TLD or an unrelated domain
+        assert!(client.dkim_for("app").is_none());
+        assert!(client.dkim_for("example.com").is_none());
+        assert!(client.dkim_for("notkillcode.app").is_none());
+    }
+
+    #[test]
+    fn test_parse_dkim_algorithm() {
+        assert!(matches!(parse_dkim_algorithm("rsa"), Ok(DkimSigningAlgorithm::Rsa)));
+        assert!(matches!(parse_dkim_algorithm("RSA-SHA256"), Ok(DkimSigningAlgorithm::Rsa)));
+        assert!(matches!(parse_dkim_algorithm("ed25519"), Ok(DkimSigningAlgorithm::Ed25519)));
+        assert!(matches!(parse_dkim_algorithm("Ed25519-SHA256"), Ok(DkimSigningAlgorithm::Ed25519)));
+        assert!(parse_dkim_algorithm("dsa").is_err());
+        assert!(parse_dkim_algorithm("").is_err());
+    }
+
+    #[test]
+    fn test_queue_id() {
+        assert_eq!(queue_id("2.0.0 Ok: queued as 4F3K2Q1zX9z1"), Some("4F3K2Q1zX9z1".to_string()));
+        assert_eq!(queue_id("OK id=1qAbCd-0003xY-Zz"), Some("1qAbCd-0003xY-Zz".to_string()));
+        assert_eq!(
+            queue_id("Ok 0100018b2c3d4e5f-1a2b3c4d-5e6f-7a8b-9c0d-1e2f3a4b5c6d-000000"),
+            Some("0100018b2c3d4e5f-1a2b3c4d-5e6f-7a8b-9c0d-1e2f3a4b5c6d-000000".to_string())
+        );
+        assert_eq!(
+            queue_id("2.0.0 OK  1697040000 d9443c01a7336-1c9b1c3b1d8si1234567pla.123 - gsmtp"),
+            Some("d9443c01a7336-1c9b1c3b1d8si1234567pla.123".to_string())
+        );
+        assert_eq!(queue_id("2.0.0 Ok"), None);
+        assert_eq!(queue_id("Great success"), None);
     }
 }
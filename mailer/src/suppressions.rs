@@ -0,0 +1,73 @@
+use std::sync::Arc;
+use redis::{AsyncCommands, Client as RedisClient};
+use tokio::sync::Mutex;
+
+use crate::logging;
+use crate::models::Suppression;
+
+const SUPPRESSIONS_KEY: &str = "mailer:suppressions";
+
+/// Recipients that hard-bounced or complained, keyed by lowercase address
+pub struct SuppressionList {
+    redis: Arc<Mutex<redis::aio::MultiplexedConnection>>,
+}
+
+fn normalize(email: &str) -> String {
+    email.trim().to_ascii_lowercase()
+}
+
+impl SuppressionList {
+    pub async fn new(redis_url: &str) -> Result<Self, anyhow::Error> {
+        let client = RedisClient::open(redis_url)?;
+        let conn = client.get_multiplexed_async_connection().await?;
+
+        Ok(Self {
+            redis: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Add (or replace) a suppression
+    pub async fn add(&self, mut suppression: Suppression) -> Result<(), anyhow::Error> {
+        let mut conn = self.redis.lock().await;
+
+        suppression.email = normalize(&suppression.email);
+        let _: () = conn.hset(SUPPRESSIONS_KEY, &suppression.email, serde_json::to_string(&suppression)?).await?;
+
+        log::warn!("🚫 Suppressed {} ({})", logging::recipient(&suppression.email), suppression.reason.as_str());
+
+        Ok(())
+    }
+
+    pub async fn get(&self, email: &str) -> Result<Option<Suppression>, anyhow::Error> {
+        let mut conn = self.redis.lock().await;
+
+        let json: Option<String> = conn.hget(SUPPRESSIONS_KEY, normalize(email)).await?;
+        Ok(json.map(|json| serde_json::from_str(&json)).transpose()?)
+    }
+
+    /// Remove a suppression; returns `false` if the address wasn't suppressed
+    pub async fn remove(&self, email: &str) -> Result<bool, anyhow::Error> {
+        let mut conn = self.redis.lock().await;
+
+        let removed: u32 = conn.hdel(SUPPRESSIONS_KEY, normalize(email)).await?;
+        if removed > 0 {
+            log::info!("✅ Removed suppression for {}", logging::recipient(&normalize(email)));
+        }
+
+        Ok(removed > 0)
+    }
+
+    /// All suppressions, newest first
+    pub async fn list(&self) -> Result<Vec<Suppression>, anyhow::Error> {
+        let mut conn = self.redis.lock().await;
+
+        let values: Vec<String> = conn.hvals(SUPPRESSIONS_KEY).await?;
+        let mut suppressions = values
+            .iter()
+            .filter_map(|json| serde_json::from_str::<Suppression>(json).ok())
+            .collect::<Vec<_>>();
+        suppressions.sort_by_key(|s| std::cmp::Reverse(s.created_at));
+
+        Ok(suppressions)
+    }
+}
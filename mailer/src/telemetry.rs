@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+
+use actix_web::http::header::HeaderMap;
+use opentelemetry::global;
+use opentelemetry::propagation::Extractor;
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{SdkTracerProvider, Tracer};
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Exports `tracing` spans over OTLP/HTTP
+///
+/// Requests carrying a W3C `traceparent` continue the caller's trace; the
+/// context is stored on the job (`EmailJob::trace_context`) so the worker's
+/// render and SMTP spans join the same trace.
+pub struct Telemetry {
+    provider: SdkTracerProvider,
+}
+
+impl Telemetry {
+    /// `endpoint` is the collector's base URL, e.g. `http://otel-collector:4318`
+    pub fn otlp(endpoint: &str, service_name: &str) -> Result<Self, anyhow::Error> {
+        let exporter = SpanExporter::builder()
+            .with_http()
+            .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+            .build()?;
+
+        let provider = SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(Resource::builder().with_service_name(service_name.to_string()).build())
+            .build();
+
+        Ok(Self::with_provider(provider))
+    }
+
+    /// Use an already configured provider, e.g. one with an in-memory exporter
+    pub fn with_provider(provider: SdkTracerProvider) -> Self {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        Self { provider }
+    }
+
+    /// Tracer for the `tracing-opentelemetry` layer
+    pub fn tracer(&self) -> Tracer {
+        self.provider.tracer("mailer")
+    }
+
+    /// Flush pending spans; called on shutdown
+    pub fn shutdown(&self) {
+        if let Err(e) = self.provider.shutdown() {
+            log::warn!("Failed to flush traces: {}", e);
+        }
+    }
+}
+
+/// Continue the trace of an incoming request's `traceparent`/`tracestate`
+pub fn set_parent_from_headers(span: &Span, headers: &HeaderMap) {
+    let cx = global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers)));
+    let _ = span.set_parent(cx);
+}
+
+/// Continue a trace stored on a job
+pub fn set_parent(span: &Span, carrier: &HashMap<String, String>) {
+    if carrier.is_empty() {
+        return;
+    }
+    let cx = global::get_text_map_propagator(|propagator| propagator.extract(carrier));
+    let _ = span.set_parent(cx);
+}
+
+/// Trace context of the current span, to persist on a job
+pub fn current_context() -> HashMap<String, String> {
+    let cx = Span::current().context();
+    let mut carrier = HashMap::new();
+    global::get_text_map_propagator(|propagator| propagator.inject_context(&cx, &mut carrier));
+    carrier.retain(|_, value| !value.is_empty());
+    carrier
+}
+
+/// Mark the current span as failed in the exported trace
+pub fn record_error(error: &dyn std::fmt::Display) {
+    let span = Span::current();
+    span.record("otel.status_code", "ERROR");
+    span.record("error", tracing::field::display(error));
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|k| k.as_str()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::{HeaderName, HeaderValue};
+    use opentelemetry::trace::{SpanId, TraceId};
+    use opentelemetry_sdk::trace::InMemorySpanExporter;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn test_job_spans_join_request_trace() {
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder().with_simple_exporter(exporter.clone()).build();
+        let telemetry = Telemetry::with_provider(provider.clone());
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(telemetry.tracer()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let mut headers = HeaderMap::new();
+            headers.insert(HeaderName::from_static("traceparent"), HeaderValue::from_static(TRACEPARENT));
+
+            // Request → enqueue, persisting the context as the queue does
+            let request = tracing::info_span!("request");
+            set_parent_from_headers(&request, &headers);
+            let carrier = request.in_scope(|| tracing::info_span!("enqueue").in_scope(current_context));
+            drop(request);
+            assert!(carrier.contains_key("traceparent"));
+
+            // Worker, later, with only the stored context
+            let job = tracing::info_span!("job");
+            set_parent(&job, &carrier);
+            job.in_scope(|| {
+                tracing::info_span!("smtp.send", otel.status_code = tracing::field::Empty, error = tracing::field::Empty)
+                    .in_scope(|| record_error(&"550 rejected"));
+            });
+        });
+        provider.force_flush().unwrap();
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let span = |name: &str| spans.iter().find(|s| s.name == name).unwrap_or_else(|| panic!("no {} span", name));
+        let trace_id = TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap();
+
+        assert_eq!(spans.len(), 4);
+        assert!(spans.iter().all(|s| s.span_context.trace_id() == trace_id));
+        assert_eq!(span("request").parent_span_id, SpanId::from_hex("00f067aa0ba902b7").unwrap());
+        assert_eq!(span("enqueue").parent_span_id, span("request").span_context.span_id());
+        assert_eq!(span("job").parent_span_id, span("enqueue").span_context.span_id());
+        assert_eq!(span("smtp.send").parent_span_id, span("job").span_context.span_id());
+        assert!(matches!(span("smtp.send").status, opentelemetry::trace::Status::Error { .. }));
+    }
+}
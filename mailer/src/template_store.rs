@@ -0,0 +1,189 @@
+use std::sync::Arc;
+use redis::{AsyncCommands, Client as RedisClient};
+use tokio::sync::Mutex;
+use chrono::Utc;
+
+use crate::models::{StoredTemplate, TemplateInfo};
+
+const TEMPLATES_KEY: &str = "mailer:templates";
+const VERSIONS_KEY_PREFIX: &str = "mailer:template_versions:";
+
+#[derive(Debug, thiserror::Error)]
+pub enum TemplateStoreError {
+    #[error("Template '{0}' not found")]
+    NotFound(String),
+    #[error("Template '{0}' already exists")]
+    AlreadyExists(String),
+    #[error("Template '{name}' has no version {version}")]
+    VersionNotFound { name: String, version: u32 },
+    #[error(transparent)]
+    Redis(#[from] redis::RedisError),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+}
+
+/// Named, versioned email templates stored in Redis
+///
+/// Every create/update/rollback appends a new immutable version, so jobs that
+/// were enqueued against an older version keep rendering the same content.
+pub struct TemplateStore {
+    redis: Arc<Mutex<redis::aio::MultiplexedConnection>>,
+}
+
+fn versions_key(name: &str) -> String {
+    format!("{}{}", VERSIONS_KEY_PREFIX, name)
+}
+
+impl TemplateStore {
+    pub async fn new(redis_url: &str) -> Result<Self, anyhow::Error> {
+        let client = RedisClient::open(redis_url)?;
+        let conn = client.get_multiplexed_async_connection().await?;
+
+        Ok(Self {
+            redis: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Create a new template as version 1
+    pub async fn create(&self, name: &str, subject: String, html: String, text: Option<String>) -> Result<StoredTemplate, TemplateStoreError> {
+        let mut conn = self.redis.lock().await;
+
+        let now = Utc::now();
+        let info = TemplateInfo {
+            name: name.to_string(),
+            latest_version: 1,
+            created_at: now,
+            updated_at: now,
+        };
+
+        let created: bool = conn.hset_nx(TEMPLATES_KEY, name, serde_json::to_string(&info)?).await?;
+        if !created {
+            return Err(TemplateStoreError::AlreadyExists(name.to_string()));
+        }
+
+        let template = StoredTemplate {
+            name: name.to_string(),
+            version: 1,
+            subject,
+            html,
+            text,
+            created_at: now,
+        };
+        let _: () = conn.hset(versions_key(name), 1, serde_json::to_string(&template)?).await?;
+
+        log::info!("📝 Created template {} (v1)", name);
+
+        Ok(template)
+    }
+
+    /// Store a new version of an existing template
+    pub async fn update(&self, name: &str, subject: String, html: String, text: Option<String>) -> Result<StoredTemplate, TemplateStoreError> {
+        let mut conn = self.redis.lock().await;
+
+        let info_json: Option<String> = conn.hget(TEMPLATES_KEY, name).await?;
+        let mut info: TemplateInfo = match info_json {
+            Some(json) => serde_json::from_str(&json)?,
+            None => return Err(TemplateStoreError::NotFound(name.to_string())),
+        };
+
+        let now = Utc::now();
+        info.latest_version += 1;
+        info.updated_at = now;
+
+        let template = StoredTemplate {
+            name: name.to_string(),
+            version: info.latest_version,
+            subject,
+            html,
+            text,
+            created_at: now,
+        };
+
+        let _: () = conn.hset(versions_key(name), template.version, serde_json::to_string(&template)?).await?;
+        let _: () = conn.hset(TEMPLATES_KEY, name, serde_json::to_string(&info)?).await?;
+
+        log::info!("📝 Updated template {} (v{})", name, template.version);
+
+        Ok(template)
+    }
+
+    /// Roll back by republishing the content of `version` as a new latest version
+    pub async fn rollback(&self, name: &str, version: u32) -> Result<StoredTemplate, TemplateStoreError> {
+        let target = self.get(name, Some(version)).await?;
+        let template = self.update(name, target.subject, target.html, target.text).await?;
+
+        log::info!("⏪ Rolled back template {} to v{} (now v{})", name, version, template.version);
+
+        Ok(template)
+    }
+
+    /// Get a template version, or the latest one when `version` is `None`
+    pub async fn get(&self, name: &str, version: Option<u32>) -> Result<StoredTemplate, TemplateStoreError> {
+        let mut conn = self.redis.lock().await;
+
+        let version = match version {
+            Some(v) => v,
+            None => {
+                let info_json: Option<String> = conn.hget(TEMPLATES_KEY, name).await?;
+                match info_json {
+                    Some(json) => serde_json::from_str::<TemplateInfo>(&json)?.latest_version,
+                    None => return Err(TemplateStoreError::NotFound(name.to_string())),
+                }
+            }
+        };
+
+        let template_json: Option<String> = conn.hget(versions_key(name), version).await?;
+        match template_json {
+            Some(json) => Ok(serde_json::from_str(&json)?),
+            None => Err(TemplateStoreError::VersionNotFound { name: name.to_string(), version }),
+        }
+    }
+
+    /// List all stored templates
+    pub async fn list(&self) -> Result<Vec<TemplateInfo>, TemplateStoreError> {
+        let mut conn = self.redis.lock().await;
+
+        let infos: Vec<String> = conn.hvals(TEMPLATES_KEY).await?;
+        let mut templates = infos
+            .iter()
+            .filter_map(|json| serde_json::from_str::<TemplateInfo>(json).ok())
+            .collect::<Vec<_>>();
+        templates.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(templates)
+    }
+
+    /// List every version of a template, oldest first
+    pub async fn versions(&self, name: &str) -> Result<Vec<StoredTemplate>, TemplateStoreError> {
+        let mut conn = self.redis.lock().await;
+
+        let exists: bool = conn.hexists(TEMPLATES_KEY, name).await?;
+        if !exists {
+            return Err(TemplateStoreError::NotFound(name.to_string()));
+        }
+
+        let versions: Vec<String> = conn.hvals(versions_key(name)).await?;
+        let mut versions = versions
+            .iter()
+            .filter_map(|json| serde_json::from_str::<StoredTemplate>(json).ok())
+            .collect::<Vec<_>>();
+        versions.sort_by_key(|t| t.version);
+
+        Ok(versions)
+    }
+
+    /// Delete a template and all of its versions
+    pub async fn delete(&self, name: &str) -> Result<(), TemplateStoreError> {
+        let mut conn = self.redis.lock().await;
+
+        let removed: u32 = conn.hdel(TEMPLATES_KEY, name).await?;
+        if removed == 0 {
+            return Err(TemplateStoreError::NotFound(name.to_string()));
+        }
+        let _: () = conn.del(versions_key(name)).await?;
+
+        log::info!("🗑️ Deleted template {}", name);
+
+        Ok(())
+    }
+}
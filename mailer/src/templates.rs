@@ -1,64 +1,241 @@
-use handlebars::Handlebars;
+use handlebars::template::{HelperTemplate, Parameter, TemplateElement};
+use handlebars::{Handlebars, Path as HbsPath, Template};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
 use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, RwLock};
+use std::time::Duration;
 
-use crate::models::EmailTemplate;
+use crate::helpers::{self, DEFAULT_LOCALE};
+use crate::inliner::CssInliner;
+use crate::metrics::METRICS;
+use crate::models::{EmailTemplate, RenderedEmail, StoredTemplate};
+
+/// Templates compiled into the binary, used when no template directory is
+/// configured or a file is missing/broken in it
+const EMBEDDED_TEMPLATES: &[(&str, &str)] = &[
+    ("otp", include_str!("../templates/otp.html")),
+    ("otp_2fa", include_str!("../templates/otp_2fa.html")),
+    ("welcome", include_str!("../templates/welcome.html")),
+    ("password_reset", include_str!("../templates/password_reset.html")),
+    ("license_created", include_str!("../templates/license_created.html")),
+];
+
+/// Subject templates compiled into the binary, registered as `<name>.subject`
+const EMBEDDED_SUBJECTS: &[(&str, &str)] = &[
+    ("otp", include_str!("../templates/otp.subject")),
+    ("otp_2fa", include_str!("../templates/otp_2fa.subject")),
+    ("welcome", include_str!("../templates/welcome.subject")),
+    ("password_reset", include_str!("../templates/password_reset.subject")),
+    ("license_created", include_str!("../templates/license_created.subject")),
+];
+
+/// Layout and partials compiled into the binary, shared by all HTML templates
+///
+/// Content templates extend the layout with `{{#> layout title="..."}}...{{/layout}}`.
+const EMBEDDED_PARTIALS: &[(&str, &str)] = &[
+    ("layout", include_str!("../templates/partials/layout.html")),
+    ("header", include_str!("../templates/partials/header.html")),
+    ("footer", include_str!("../templates/partials/footer.html")),
+];
+
+/// Subdirectory of the template directory holding partials
+const PARTIALS_DIR: &str = "partials";
+
+/// How long to wait for a burst of filesystem events to settle before reloading
+const RELOAD_DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// Registered templates, swapped as a whole on reload
+struct Registry {
+    /// HTML bodies, with HTML escaping
+    html: Handlebars<'static>,
+    /// Subjects and plain-text bodies, without HTML escaping
+    plain: Handlebars<'static>,
+}
 
 pub struct TemplateEngine {
-    hbs: Handlebars<'static>,
+    registry: Arc<RwLock<Registry>>,
+    inliner: CssInliner,
+    dir: Option<PathBuf>,
+    watcher: Option<RecommendedWatcher>,
 }
 
 impl TemplateEngine {
+    /// Create an engine using only the embedded templates
     pub fn new() -> Self {
-        let mut hbs = Handlebars::new();
-        
-        // Register OTP template (signup)
-        hbs.register_template_string("otp", include_str!("../templates/otp.html"))
-            .expect("Failed to register OTP template");
-        
-        // Register 2FA OTP template
-        hbs.register_template_string("otp_2fa", include_str!("../templates/otp_2fa.html"))
-            .expect("Failed to register 2FA OTP template");
-        
-        // Register Welcome template
-        hbs.register_template_string("welcome", include_str!("../templates/welcome.html"))
-            .expect("Failed to register Welcome template");
-        
-        // Register Password Reset template
-        hbs.register_template_string("password_reset", include_str!("../templates/password_reset.html"))
-            .expect("Failed to register Password Reset template");
-        
-        // Register License Created template
-        hbs.register_template_string("license_created", include_str!("../templates/license_created.html"))
-            .expect("Failed to register License Created template");
-        
-        Self { hbs }
-    }
-
-    pub fn render(&self, template: &EmailTemplate, data: &serde_json::Value) -> Result<String, anyhow::Error> {
+        Self {
+            registry: Arc::new(RwLock::new(build_registry(None, None))),
+            inliner: CssInliner::new(),
+            dir: None,
+            watcher: None,
+        }
+    }
+
+    /// Create an engine loading `*.html` templates from `dir`
+    ///
+    /// Files are registered under their file stem (`welcome.html` -> `welcome`)
+    /// on top of the embedded set, so a missing or broken file falls back to
+    /// the compiled-in version. Subject templates live next to them as
+    /// `<name>.subject`, and the shared layout and partials in `partials/`.
+    pub fn from_dir(dir: impl AsRef<Path>) -> Self {
+        let dir = dir.as_ref().to_path_buf();
+        let registry = build_registry(Some(&dir), None);
+        log::info!("📝 Loaded {} templates from {}", registry.html.get_templates().len(), dir.display());
+
+        Self {
+            registry: Arc::new(RwLock::new(registry)),
+            inliner: CssInliner::new(),
+            dir: Some(dir),
+            watcher: None,
+        }
+    }
+
+    /// Watch the template directory and re-register templates when files change
+    pub fn watch(&mut self) -> Result<(), anyhow::Error> {
+        let Some(dir) = self.dir.clone() else {
+            return Err(anyhow::anyhow!("No template directory configured"));
+        };
+
+        let (tx, rx) = mpsc::channel::<notify::Result<notify::Event>>();
+        let mut watcher = notify::recommended_watcher(tx)?;
+        watcher.watch(&dir, RecursiveMode::Recursive)?;
+
+        log::info!("👀 Watching {} for template changes", dir.display());
+
+        let registry = self.registry.clone();
+        std::thread::spawn(move || {
+            while let Ok(event) = rx.recv() {
+                if let Err(e) = event {
+                    log::warn!("Template watcher error: {}", e);
+                    continue;
+                }
+                // Editors tend to emit several events per save
+                while rx.recv_timeout(RELOAD_DEBOUNCE).is_ok() {}
+
+                let mut current = registry.write().unwrap_or_else(|e| e.into_inner());
+                let reloaded = build_registry(Some(&dir), Some(&current));
+                *current = reloaded;
+                log::info!("🔄 Reloaded templates from {}", dir.display());
+            }
+            log::warn!("Template watcher stopped");
+        });
+
+        self.watcher = Some(watcher);
+
+        Ok(())
+    }
+
+    /// Render the subject and HTML body of a built-in template
+    ///
+    /// The subject comes from the template's own subject template unless
+    /// `subject` overrides it. Locale variants (`welcome.pt-BR.html`) are
+    /// picked along the fallback chain `pt-BR` -> `pt` -> `en`.
+    pub fn render(&self, template: &EmailTemplate, data: &serde_json::Value, subject: Option<&str>, locale: Option<&str>) -> Result<RenderedEmail, anyhow::Error> {
+        let result = self.render_builtin(template, data, subject, locale);
+        if result.is_err() {
+            METRICS.render_failures.with_label_values(&[template.name()]).inc();
+        }
+        result
+    }
+
+    fn render_builtin(&self, template: &EmailTemplate, data: &serde_json::Value, subject: Option<&str>, locale: Option<&str>) -> Result<RenderedEmail, anyhow::Error> {
         let template_name = match template {
-            EmailTemplate::Otp => "otp",
-            EmailTemplate::Otp2FA => "otp_2fa",
-            EmailTemplate::Welcome => "welcome",
-            EmailTemplate::PasswordReset => "password_reset",
-            EmailTemplate::LicenseCreated => "license_created",
             EmailTemplate::Custom => {
                 // For custom, the data should contain an "html" field
-                if let Some(html) = data.get("html").and_then(|v| v.as_str()) {
-                    return Ok(html.to_string());
-                }
-                return Err(anyhow::anyhow!("Custom template requires 'html' field in data"));
+                let Some(html) = data.get("html").and_then(|v| v.as_str()) else {
+                    return Err(anyhow::anyhow!("Custom template requires 'html' field in data"));
+                };
+                let Some(subject) = subject else {
+                    return Err(anyhow::anyhow!("Custom template requires a subject"));
+                };
+                return Ok(RenderedEmail {
+                    subject: subject.to_string(),
+                    html: self.inliner.inline(None, html)?,
+                    text: None,
+                });
+            }
+            builtin => builtin.name(),
+        };
+
+        // Helpers format for the requested locale even when the template
+        // itself falls back to a less specific variant
+        let locale = locale.and_then(helpers::normalize_locale).unwrap_or_else(|| DEFAULT_LOCALE.to_string());
+        let render_data = render_data(data, &locale)?;
+
+        let registry = self.registry.read().unwrap_or_else(|e| e.into_inner());
+        let chain = helpers::locale_chain(&locale);
+
+        let html_name = localized_name(&registry.html, template_name, "", &chain);
+
+        let html = registry.html.render(&html_name, &render_data)?;
+        let html = self.inliner.inline(Some(&html_name), &html)?;
+        let subject = match subject {
+            Some(subject) => subject.to_string(),
+            None => {
+                let subject_name = localized_name(&registry.plain, template_name, ".subject", &chain);
+                registry.plain.render(&subject_name, &render_data)?
             }
         };
 
-        // Merge year into the data object
-        let mut render_data = data.clone();
-        if let Some(obj) = render_data.as_object_mut() {
-            obj.insert("year".to_string(), json!(chrono::Utc::now().format("%Y").to_string()));
+        Ok(RenderedEmail {
+            subject: subject.trim().to_string(),
+            html,
+            text: None,
+        })
+    }
+
+    /// Render the subject, HTML and text body of a stored template
+    pub fn render_stored(&self, template: &StoredTemplate, data: &serde_json::Value, subject: Option<&str>, locale: Option<&str>) -> Result<RenderedEmail, anyhow::Error> {
+        let result = self.render_stored_version(template, data, subject, locale);
+        if result.is_err() {
+            METRICS.render_failures.with_label_values(&[&template.name]).inc();
         }
+        result
+    }
 
-        let html = self.hbs.render(template_name, &render_data)?;
-        
-        Ok(html)
+    fn render_stored_version(&self, template: &StoredTemplate, data: &serde_json::Value, subject: Option<&str>, locale: Option<&str>) -> Result<RenderedEmail, anyhow::Error> {
+        let locale = locale.and_then(helpers::normalize_locale).unwrap_or_else(|| DEFAULT_LOCALE.to_string());
+        let render_data = render_data(data, &locale)?;
+
+        let registry = self.registry.read().unwrap_or_else(|e| e.into_inner());
+        let html = registry.html.render_template(&template.html, &render_data)?;
+        let html = self.inliner.inline(Some(&format!("stored:{}:v{}", template.name, template.version)), &html)?;
+        let subject = match subject {
+            Some(subject) => subject.to_string(),
+            None => registry.plain.render_template(&template.subject, &render_data)?,
+        };
+        let text = template
+            .text
+            .as_ref()
+            .map(|text| registry.plain.render_template(text, &render_data))
+            .transpose()?;
+
+        Ok(RenderedEmail {
+            subject: subject.trim().to_string(),
+            html,
+            text,
+        })
+    }
+
+    /// Variables referenced by a template source, for the template gallery
+    ///
+    /// Walks the parsed template: plain expressions, helper, subexpression and
+    /// partial arguments count, as do `../` references back to the top level
+    /// from inside `each`/`with` blocks. Paths relative to a block's item,
+    /// `@` variables and `this` are skipped. Sources that don't compile have
+    /// no variables.
+    pub fn variables(source: &str) -> Vec<String> {
+        let mut variables = Vec::new();
+        if let Ok(template) = Template::compile(source) {
+            collect_template(&template, 0, &mut variables);
+        }
+        variables
+    }
+
+    /// Check that a template source compiles
+    pub fn validate(source: &str) -> Result<(), anyhow::Error> {
+        handlebars::Template::compile(source)?;
+        Ok(())
     }
 }
 
@@ -67,3 +244,335 @@ impl Default for TemplateEngine {
         Self::new()
     }
 }
+
+/// `depth` counts the `each`/`with` blocks entered, whose bodies resolve
+/// paths against the current item rather than the template data
+fn collect_template(template: &Template, depth: usize, variables: &mut Vec<String>) {
+    for element in &template.elements {
+        match element {
+            TemplateElement::Expression(helper)
+            | TemplateElement::HtmlExpression(helper)
+            | TemplateElement::HelperBlock(helper) => collect_helper(helper, depth, variables),
+            TemplateElement::PartialExpression(decorator)
+            | TemplateElement::PartialBlock(decorator)
+            | TemplateElement::DecoratorExpression(decorator)
+            | TemplateElement::DecoratorBlock(decorator) => {
+                // Dynamic partials (`{{> (name_var) }}`) are named by a subexpression
+                if let Parameter::Subexpression(_) = &decorator.name {
+                    collect_parameter(&decorator.name, depth, variables);
+                }
+                for param in decorator.params.iter().chain(decorator.hash.values()) {
+                    collect_parameter(param, depth, variables);
+                }
+                if let Some(template) = &decorator.template {
+                    collect_template(template, depth, variables);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn collect_helper(helper: &HelperTemplate, depth: usize, variables: &mut Vec<String>) {
+    // `{{var}}` is named by a path; helpers (`{{format_date d}}`) by a name
+    if let Parameter::Path(_) = &helper.name {
+        collect_parameter(&helper.name, depth, variables);
+    }
+    for param in helper.params.iter().chain(helper.hash.values()) {
+        collect_parameter(param, depth, variables);
+    }
+
+    if let Some(template) = &helper.template {
+        let scoped = matches!(&helper.name, Parameter::Name(name) if name == "each" || name == "with");
+        collect_template(template, depth + usize::from(scoped), variables);
+    }
+    // `{{else}}` runs in the block's outer context
+    if let Some(inverse) = &helper.inverse {
+        collect_template(inverse, depth, variables);
+    }
+}
+
+fn collect_parameter(param: &Parameter, depth: usize, variables: &mut Vec<String>) {
+    match param {
+        Parameter::Path(HbsPath::Relative((_, raw))) => {
+            if let Some(variable) = top_level_variable(raw, depth)
+                && !variables.contains(&variable)
+            {
+                variables.push(variable);
+            }
+        }
+        Parameter::Subexpression(subexpression) => {
+            if let TemplateElement::Expression(helper) = subexpression.element.as_ref() {
+                collect_helper(helper, depth, variables);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// `user.name` for a path that refers to the template data from `depth`
+/// blocks deep, e.g. `../user/name` one level into an `each`
+fn top_level_variable(raw: &str, depth: usize) -> Option<String> {
+    let (mut path, mut up) = match raw.strip_prefix("@root.").or_else(|| raw.strip_prefix("@root/")) {
+        Some(rest) => (rest, depth),
+        None => (raw, 0),
+    };
+    while let Some(rest) = path.strip_prefix("../") {
+        path = rest;
+        up += 1;
+    }
+    if up != depth {
+        return None;
+    }
+
+    let path = path
+        .strip_prefix("this.")
+        .or_else(|| path.strip_prefix("this/"))
+        .or_else(|| path.strip_prefix("./"))
+        .unwrap_or(path)
+        .replace('/', ".");
+    let root = path.split('.').next().unwrap_or_default();
+    if root.is_empty() || root == "this" || root.starts_with('@') || root == helpers::MAILER_KEY {
+        return None;
+    }
+    Some(path)
+}
+
+/// Add the values the engine provides under `_mailer`: `year` (for the
+/// footer) and `locale` (for the formatting helpers)
+///
+/// They live in their own namespace so a caller's own `year` or `locale`
+/// keys reach the template untouched.
+fn render_data(data: &serde_json::Value, locale: &str) -> Result<serde_json::Value, anyhow::Error> {
+    let mut render_data = data.clone();
+    if let Some(obj) = render_data.as_object_mut() {
+        if obj.contains_key(helpers::MAILER_KEY) {
+            return Err(anyhow::anyhow!("'{}' is reserved and can't be set in template data", helpers::MAILER_KEY));
+        }
+        obj.insert(
+            helpers::MAILER_KEY.to_string(),
+            json!({ "year": chrono::Utc::now().format("%Y").to_string(), "locale": locale }),
+        );
+    }
+    Ok(render_data)
+}
+
+/// Find the first registered `<name>.<locale><suffix>` along the locale chain
+///
+/// The default locale maps to the unsuffixed `<name><suffix>`.
+fn localized_name(hbs: &Handlebars<'static>, name: &str, suffix: &str, chain: &[String]) -> String {
+    for locale in chain {
+        let candidate = if locale == DEFAULT_LOCALE {
+            format!("{}{}", name, suffix)
+        } else {
+            format!("{}.{}{}", name, locale, suffix)
+        };
+        if hbs.has_template(&candidate) {
+            return candidate;
+        }
+    }
+    format!("{}{}", name, suffix)
+}
+
+/// Build a fresh registry from the embedded templates overlaid with the files in `dir`
+///
+/// When a file fails to parse, the version from `previous` is kept (or the
+/// embedded one if there is none) so a typo never takes a template offline.
+fn build_registry(dir: Option<&Path>, previous: Option<&Registry>) -> Registry {
+    // Strict mode turns a missing variable into a render error instead of a blank
+    let mut html = Handlebars::new();
+    html.set_strict_mode(true);
+    let mut plain = Handlebars::new();
+    plain.set_strict_mode(true);
+    plain.register_escape_fn(handlebars::no_escape);
+    helpers::register_helpers(&mut html);
+    helpers::register_helpers(&mut plain);
+
+    for (name, source) in EMBEDDED_PARTIALS {
+        html.register_partial(name, *source)
+            .unwrap_or_else(|e| panic!("Failed to register embedded {} partial: {}", name, e));
+    }
+    for (name, source) in EMBEDDED_TEMPLATES {
+        html.register_template_string(name, *source)
+            .unwrap_or_else(|e| panic!("Failed to register embedded {} template: {}", name, e));
+    }
+    for (name, source) in EMBEDDED_SUBJECTS {
+        plain.register_template_string(&format!("{}.subject", name), *source)
+            .unwrap_or_else(|e| panic!("Failed to register embedded {} subject: {}", name, e));
+    }
+
+    let Some(dir) = dir else {
+        return Registry { html, plain };
+    };
+
+    load_dir(dir, false, &mut html, &mut plain, previous);
+    load_dir(&dir.join(PARTIALS_DIR), true, &mut html, &mut plain, previous);
+
+    Registry { html, plain }
+}
+
+/// Register the templates found in `dir`, or its partials when `partials` is set
+fn load_dir(dir: &Path, partials: bool, html: &mut Handlebars<'static>, plain: &mut Handlebars<'static>, previous: Option<&Registry>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if partials && e.kind() == std::io::ErrorKind::NotFound => return,
+        Err(e) => {
+            log::warn!("Failed to read template directory {}: {}, using embedded templates", dir.display(), e);
+            return;
+        }
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+
+        // `welcome.html` registers `welcome`, `welcome.subject` registers `welcome.subject`,
+        // `welcome.pt-BR.html` registers the `pt-BR` variant `welcome.pt-BR` and
+        // `partials/footer.html` registers the `footer` partial
+        let (hbs, name, old) = match path.extension().and_then(|e| e.to_str()) {
+            Some("html") => (&mut *html, stem.to_string(), previous.map(|p| &p.html)),
+            Some("subject") if !partials => (&mut *plain, format!("{}.subject", stem), previous.map(|p| &p.plain)),
+            _ => continue,
+        };
+
+        let result = std::fs::read_to_string(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|source| {
+                let registered = if partials {
+                    hbs.register_partial(&name, source)
+                } else {
+                    hbs.register_template_string(&name, source)
+                };
+                registered.map_err(anyhow::Error::from)
+            });
+
+        if let Err(e) = result {
+            log::error!("Failed to load template {}: {}", path.display(), e);
+            if let Some(template) = old.and_then(|p| p.get_template(&name)) {
+                hbs.register_template(&name, template.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_variables_plain_and_helper_arguments() {
+        assert_eq!(
+            TemplateEngine::variables("Hi {{name}}, {{{html}}} {{format_date expires_at \"long\" tz=timezone}} {{_mailer.year}}"),
+            vec!["name", "html", "expires_at", "timezone"]
+        );
+        assert_eq!(TemplateEngine::variables("{{user.name}} {{user/email}} {{this.otp}}"), vec!["user.name", "user.email", "otp"]);
+        // Repeated references are reported once
+        assert_eq!(TemplateEngine::variables("{{otp}} {{otp}}"), vec!["otp"]);
+    }
+
+    #[test]
+    fn test_variables_blocks_and_else() {
+        assert_eq!(
+            TemplateEngine::variables("{{#if trial}}{{trial_days}} days{{else if paid}}{{plan}}{{else}}{{fallback}}{{/if}}"),
+            vec!["trial", "trial_days", "paid", "plan", "fallback"]
+        );
+        assert_eq!(TemplateEngine::variables("{{#unless verified}}{{link}}{{/unless}}"), vec!["verified", "link"]);
+    }
+
+    #[test]
+    fn test_variables_each_and_with_scopes() {
+        // Inside `each`, paths are relative to the item; `../` reaches back out
+        assert_eq!(
+            TemplateEngine::variables("{{#each licenses}}{{key}} {{@index}} {{this}} {{../owner}}{{else}}{{empty_text}}{{/each}}"),
+            vec!["licenses", "owner", "empty_text"]
+        );
+        assert_eq!(
+            TemplateEngine::variables("{{#each items as |item|}}{{item.name}} {{@root.currency}}{{/each}}"),
+            vec!["items", "currency"]
+        );
+        assert_eq!(
+            TemplateEngine::variables("{{#with user}}{{name}}{{#each roles}}{{../../company}}{{/each}}{{/with}}"),
+            vec!["user", "company"]
+        );
+    }
+
+    #[test]
+    fn test_variables_subexpressions_partials_and_comments() {
+        assert_eq!(
+            TemplateEngine::variables("{{truncate (url link source=campaign) max_len}}"),
+            vec!["link", "campaign", "max_len"]
+        );
+        assert_eq!(TemplateEngine::variables("{{> footer company=company_name}} {{> (partial_name) ctx}}"), vec!["company_name", "partial_name", "ctx"]);
+        assert_eq!(TemplateEngine::variables("{{!-- {{hidden}} --}}{{! note }}{{shown}}"), vec!["shown"]);
+        assert_eq!(TemplateEngine::variables("{{ \"literal\" }} {{pluralize 3 \"day\"}}"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_helpers_use_requested_locale_without_a_variant() {
+        let dir = std::env::temp_dir().join(format!("mailer-templates-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("welcome.html"), "{{format_date d}}").unwrap();
+        let engine = TemplateEngine::from_dir(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // There is no welcome.pt-BR.html, but dates still follow pt-BR
+        let rendered = engine
+            .render(&EmailTemplate::Welcome, &json!({ "d": "2026-03-05" }), Some("Olá"), Some("pt_br"))
+            .unwrap();
+        assert!(rendered.html.contains("5 março 2026"), "{}", rendered.html);
+    }
+
+    #[test]
+    fn test_layout_lang_follows_locale() {
+        let engine = TemplateEngine::new();
+        let data = EmailTemplate::Welcome.sample_data();
+        let rendered = engine.render(&EmailTemplate::Welcome, &data, None, Some("pt_br")).unwrap();
+        assert!(rendered.html.contains(r#"lang="pt-BR""#), "{}", rendered.html);
+        let rendered = engine.render(&EmailTemplate::Welcome, &data, None, None).unwrap();
+        assert!(rendered.html.contains(r#"lang="en""#), "{}", rendered.html);
+    }
+
+    #[test]
+    fn test_render_data_keeps_caller_keys() {
+        let data = render_data(&json!({ "locale": "caller", "year": 1999 }), "de").unwrap();
+        assert_eq!(data["locale"], "caller");
+        assert_eq!(data["year"], 1999);
+        assert_eq!(data[helpers::MAILER_KEY]["locale"], "de");
+        assert_eq!(data[helpers::MAILER_KEY]["year"], chrono::Utc::now().format("%Y").to_string());
+
+        assert!(render_data(&json!({ "_mailer": { "locale": "fr" } }), "de").is_err());
+    }
+
+    #[test]
+    fn test_builtin_templates_render_sample_data() {
+        let engine = TemplateEngine::new();
+        for template in EmailTemplate::BUILTIN {
+            let data = template.sample_data();
+            assert!(template.missing_fields(&data).is_empty(), "{}", template.name());
+            let rendered = engine
+                .render(&template, &data, None, None)
+                .unwrap_or_else(|e| panic!("{}: {}", template.name(), e));
+            assert!(!rendered.subject.is_empty(), "{}", template.name());
+
+            // Wrapped in the layout, with the header and footer partials
+            let year = format!("&copy; {} KillCode", chrono::Utc::now().format("%Y"));
+            for part in ["<!DOCTYPE html", "<title>", "Binary Protection Platform", &year, "</html>"] {
+                assert!(rendered.html.contains(part), "{} lacks {}", template.name(), part);
+            }
+
+            // Strict mode: every required field is actually used
+            for field in template.required_fields() {
+                let mut data = data.clone();
+                data.as_object_mut().unwrap().remove(*field);
+                assert!(engine.render(&template, &data, None, None).is_err(), "{} without {}", template.name(), field);
+            }
+        }
+    }
+
+    #[test]
+    fn test_variables_of_invalid_source() {
+        assert!(TemplateEngine::variables("{{#if open}}never closed").is_empty());
+    }
+}
@@ -0,0 +1,292 @@
+use std::cell::Cell;
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use hmac::{Hmac, Mac};
+use lol_html::html_content::{ContentType, Element};
+use lol_html::{element, rewrite_str, RewriteStrSettings};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Shortest accepted tracking secret, the same minimum as API key secrets
+pub const MIN_SECRET_LEN: usize = 16;
+
+/// Transparent 1x1 GIF served for open tracking
+pub const PIXEL_GIF: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+/// Adds an open-tracking pixel and rewrites links to signed redirect URLs
+///
+/// Tokens carry the job ID (and the target URL for clicks) together with an
+/// HMAC, so the redirect endpoint can't be used as an open redirect and events
+/// can't be forged for other jobs.
+pub struct Tracker {
+    base_url: String,
+    secret: Vec<u8>,
+    track_opens: bool,
+    track_clicks: bool,
+}
+
+impl Tracker {
+    /// `base_url` is the public URL the mailer is reachable at
+    pub fn new(base_url: &str, secret: &str) -> Result<Self, anyhow::Error> {
+        let parsed = url::Url::parse(base_url)?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            anyhow::bail!("Tracking base URL must be http(s): {}", base_url);
+        }
+        if secret.len() < MIN_SECRET_LEN {
+            anyhow::bail!("Tracking secret must be at least {} characters", MIN_SECRET_LEN);
+        }
+
+        Ok(Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            secret: secret.as_bytes().to_vec(),
+            track_opens: true,
+            track_clicks: true,
+        })
+    }
+
+    pub fn with_opens(mut self, enabled: bool) -> Self {
+        self.track_opens = enabled;
+        self
+    }
+
+    pub fn with_clicks(mut self, enabled: bool) -> Self {
+        self.track_clicks = enabled;
+        self
+    }
+
+    /// Rewrite `http(s)` links and append the tracking pixel to rendered HTML
+    pub fn instrument(&self, job_id: &str, html: &str) -> Result<String, anyhow::Error> {
+        if !self.track_opens && !self.track_clicks {
+            return Ok(html.to_string());
+        }
+
+        let pixel = format!(
+            r#"<img src="{}/track/open/{}" width="1" height="1" alt="" style="display: block; border: 0; width: 1px; height: 1px;">"#,
+            self.base_url,
+            self.open_token(job_id),
+        );
+        let pixel_written = Cell::new(false);
+
+        let mut html = rewrite_str(html, RewriteStrSettings {
+            element_content_handlers: vec![
+                element!("a[href]", |el: &mut Element| {
+                    if !self.track_clicks {
+                        return Ok(());
+                    }
+                    if let Some(href) = el.get_attribute("href")
+                        && self.should_track(&href)
+                    {
+                        let token = self.click_token(job_id, &decode_entities(href.trim()));
+                        el.set_attribute("href", &format!("{}/track/click/{}", self.base_url, token))?;
+                    }
+                    Ok(())
+                }),
+                element!("body", |el: &mut Element| {
+                    if self.track_opens {
+                        el.append(&pixel, ContentType::Html);
+                        pixel_written.set(true);
+                    }
+                    Ok(())
+                }),
+            ],
+            ..RewriteStrSettings::new()
+        })?;
+
+        // Fragments without a <body> get the pixel at the end
+        if self.track_opens && !pixel_written.get() {
+            html.push_str(&pixel);
+        }
+
+        Ok(html)
+    }
+
+    /// Job ID of a valid open token
+    pub fn verify_open(&self, token: &str) -> Option<String> {
+        let (job_id, signature) = token.split_once('.')?;
+        self.verify(&format!("open:{}", job_id), signature)
+            .then(|| job_id.to_string())
+    }
+
+    /// Job ID and target URL of a valid click token
+    pub fn verify_click(&self, token: &str) -> Option<(String, String)> {
+        let mut parts = token.splitn(3, '.');
+        let (job_id, encoded_url, signature) = (parts.next()?, parts.next()?, parts.next()?);
+        let url = String::from_utf8(URL_SAFE_NO_PAD.decode(encoded_url).ok()?).ok()?;
+
+        self.verify(&format!("click:{}:{}", job_id, url), signature)
+            .then(|| (job_id.to_string(), url))
+    }
+
+    fn open_token(&self, job_id: &str) -> String {
+        format!("{}.{}", job_id, self.sign(&format!("open:{}", job_id)))
+    }
+
+    fn click_token(&self, job_id: &str, url: &str) -> String {
+        format!(
+            "{}.{}.{}",
+            job_id,
+            URL_SAFE_NO_PAD.encode(url),
+            self.sign(&format!("click:{}:{}", job_id, url)),
+        )
+    }
+
+    /// Only absolute web links are tracked; `mailto:`, anchors and links that
+    /// already point at the mailer are left alone
+    fn should_track(&self, href: &str) -> bool {
+        let href = href.trim();
+        let lower = href.to_ascii_lowercase();
+        (lower.starts_with("http://") || lower.starts_with("https://"))
+            && !href.starts_with(&self.base_url)
+    }
+
+    fn mac(&self) -> HmacSha256 {
+        HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts keys of any length")
+    }
+
+    fn sign(&self, message: &str) -> String {
+        let mut mac = self.mac();
+        mac.update(message.as_bytes());
+        URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+    }
+
+    fn verify(&self, message: &str, signature: &str) -> bool {
+        let Ok(signature) = URL_SAFE_NO_PAD.decode(signature) else {
+            return false;
+        };
+        let mut mac = self.mac();
+        mac.update(message.as_bytes());
+        mac.verify_slice(&signature).is_ok()
+    }
+}
+
+/// Decode the character references handlebars and editors put in attribute
+/// values (`&amp;`, `&#x3D;`, ...) so redirects go to the real URL
+pub(crate) fn decode_entities(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let decoded = rest.find(';').and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                _ => entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                    .map(|hex| u32::from_str_radix(hex, 16))
+                    .or_else(|| entity.strip_prefix('#').map(str::parse::<u32>))
+                    .and_then(|code| code.ok())
+                    .and_then(char::from_u32),
+            };
+            c.map(|c| (c, end))
+        });
+
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker() -> Tracker {
+        Tracker::new("https://mail.example.com/", "tracking-secret-0123").unwrap()
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let tracker = tracker();
+        // HMAC-SHA256("tracking-secret-0123", "open:job-1"), base64url without padding
+        assert_eq!(tracker.sign("open:job-1"), "6jyM9ig-VcXRMN0C26KrTLFRYieSq5l9xYU3MqoQp7s");
+        assert!(tracker.verify("open:job-1", "6jyM9ig-VcXRMN0C26KrTLFRYieSq5l9xYU3MqoQp7s"));
+        assert!(!tracker.verify("open:job-2", "6jyM9ig-VcXRMN0C26KrTLFRYieSq5l9xYU3MqoQp7s"));
+        assert!(!tracker.verify("open:job-1", "not base64!"));
+        assert!(!Tracker::new("https://mail.example.com", "other-secret-0123").unwrap().verify("open:job-1", &tracker.sign("open:job-1")));
+    }
+
+    #[test]
+    fn test_new_requires_long_secret() {
+        assert!(Tracker::new("https://mail.example.com", "").is_err());
+        assert!(Tracker::new("https://mail.example.com", "fifteen-chars!!").is_err());
+        assert!(Tracker::new("https://mail.example.com", "sixteen-chars!!!").is_ok());
+        assert!(Tracker::new("ftp://mail.example.com", "sixteen-chars!!!").is_err());
+    }
+
+    #[test]
+    fn test_verify_open() {
+        let tracker = tracker();
+        let token = tracker.open_token("job-1");
+        assert_eq!(tracker.verify_open(&token), Some("job-1".to_string()));
+
+        let signature = token.split_once('.').unwrap().1;
+        assert_eq!(tracker.verify_open(&format!("job-2.{}", signature)), None);
+        assert_eq!(tracker.verify_open("job-1"), None);
+        // A click signature doesn't open-track the same job
+        let click = tracker.click_token("job-1", "https://example.com");
+        assert_eq!(tracker.verify_open(&format!("job-1.{}", click.rsplit('.').next().unwrap())), None);
+    }
+
+    #[test]
+    fn test_verify_click_rejects_tampering() {
+        let tracker = tracker();
+        let token = tracker.click_token("job-1", "https://example.com/a?b=1");
+        assert_eq!(tracker.verify_click(&token), Some(("job-1".to_string(), "https://example.com/a?b=1".to_string())));
+
+        let parts: Vec<&str> = token.split('.').collect();
+        let other_url = URL_SAFE_NO_PAD.encode("https://evil.test/");
+        assert_eq!(tracker.verify_click(&format!("{}.{}.{}", parts[0], other_url, parts[2])), None);
+        assert_eq!(tracker.verify_click(&format!("job-2.{}.{}", parts[1], parts[2])), None);
+        assert_eq!(tracker.verify_click(&format!("{}.{}", parts[0], parts[1])), None);
+        assert_eq!(tracker.verify_click(&format!("{}.%%%.{}", parts[0], parts[2])), None);
+    }
+
+    #[test]
+    fn test_decode_entities() {
+        assert_eq!(decode_entities("https://a.test/?x=1&amp;y=2"), "https://a.test/?x=1&y=2");
+        assert_eq!(decode_entities("a&#x3D;b&#X3d;c&#61;d"), "a=b=c=d");
+        assert_eq!(decode_entities("&lt;&gt;&quot;&apos;"), "<>\"'");
+        // Unknown or unterminated references are kept as-is
+        assert_eq!(decode_entities("a&b=1&nbsp;&#xZZ;&"), "a&b=1&nbsp;&#xZZ;&");
+    }
+
+    #[test]
+    fn test_instrument() {
+        let tracker = tracker();
+        let html = tracker
+            .instrument("job-1", r##"<body><a href="https://example.com/?a=1&amp;b=2">x</a><a href="mailto:a@b.test">m</a><a href="#top">t</a></body>"##)
+            .unwrap();
+
+        let token = tracker.click_token("job-1", "https://example.com/?a=1&b=2");
+        assert!(html.contains(&format!(r#"<a href="https://mail.example.com/track/click/{}">x</a>"#, token)), "{}", html);
+        assert!(html.contains(r##"<a href="mailto:a@b.test">m</a><a href="#top">t</a>"##), "{}", html);
+        assert!(html.contains(&format!("/track/open/{}\"", tracker.open_token("job-1"))), "{}", html);
+        assert!(html.ends_with("></body>"), "{}", html);
+
+        let untracked = tracker.with_opens(false).with_clicks(false);
+        assert_eq!(untracked.instrument("job-1", "<p>x</p>").unwrap(), "<p>x</p>");
+    }
+}
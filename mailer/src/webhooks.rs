@@ -0,0 +1,549 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use redis::{AsyncCommands, Client as RedisClient};
+use tokio::sync::Mutex;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::models::{DeliveryStatus, EmailJob, EmailStatus, StatusEvent, WebhookDelivery};
+
+type HmacSha256 = Hmac<Sha256>;
+
+const DELIVERIES_KEY: &str = "mailer:webhook_deliveries";
+/// Sorted set of pending delivery IDs scored by next attempt (unix seconds)
+const SCHEDULE_KEY: &str = "mailer:webhook_schedule";
+/// Most recent delivery IDs, newest first
+const LOG_KEY: &str = "mailer:webhook_log";
+const JOB_DELIVERIES_KEY_PREFIX: &str = "mailer:webhook_job:";
+/// Sorted set of delivered and failed delivery IDs scored by when they
+/// finished (unix seconds), pruned after the retention period
+const FINISHED_KEY: &str = "mailer:webhook_finished";
+
+const LOG_SIZE: isize = 1000;
+const BATCH_SIZE: isize = 10;
+const DEFAULT_MAX_ATTEMPTS: u32 = 8;
+const BASE_BACKOFF_SECS: i64 = 10;
+const MAX_BACKOFF_SECS: i64 = 3600;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a claimed delivery stays out of the schedule; a worker that dies
+/// mid-attempt leaves it to be picked up again once the lease runs out
+const LEASE_SECS: i64 = 60;
+const DEFAULT_RETENTION: Duration = Duration::from_secs(7 * 24 * 3600);
+const PRUNE_BATCH: isize = 100;
+
+/// Move a due delivery to the end of its lease, so only one worker claims it
+///
+/// KEYS[1] = schedule, ARGV = [delivery ID, now, lease end]; returns 1 when claimed
+const CLAIM_SCRIPT: &str = r"
+local score = redis.call('ZSCORE', KEYS[1], ARGV[1])
+if score and tonumber(score) <= tonumber(ARGV[2]) then
+    redis.call('ZADD', KEYS[1], ARGV[3], ARGV[1])
+    return 1
+end
+return 0
+";
+
+fn job_deliveries_key(job_id: &str) -> String {
+    format!("{}{}", JOB_DELIVERIES_KEY_PREFIX, job_id)
+}
+
+/// Posts signed status-change events to callback URLs
+///
+/// Deliveries are persisted in Redis and retried with exponential backoff
+/// until the receiver answers with a 2xx status. Each request carries
+/// `X-Mailer-Signature: sha256=<hex>`, an HMAC-SHA256 of
+/// `"{X-Mailer-Timestamp}.{body}"` keyed with the shared secret.
+///
+/// Delivery is at-least-once: a worker that dies mid-attempt leaves the
+/// delivery to be retried, so receivers should de-duplicate on
+/// `X-Mailer-Delivery`. Callbacks can't reach loopback, private or link-local
+/// addresses unless their host is allow-listed.
+pub struct WebhookDispatcher {
+    redis: Arc<Mutex<redis::aio::MultiplexedConnection>>,
+    client: reqwest::Client,
+    claim: redis::Script,
+    secret: Vec<u8>,
+    /// Used for jobs without their own callback URL
+    default_url: Option<String>,
+    /// Hosts callbacks may reach even when they resolve to internal addresses
+    allowed_hosts: Arc<Vec<String>>,
+    max_attempts: u32,
+    /// How long delivered and failed deliveries are kept
+    retention: Duration,
+}
+
+impl WebhookDispatcher {
+    /// `allowed_hosts` are exempt from the internal-address check, for
+    /// receivers on the same network (e.g. `server` in docker-compose)
+    pub async fn new(redis_url: &str, secret: &str, default_url: Option<String>, allowed_hosts: Vec<String>) -> Result<Self, anyhow::Error> {
+        if secret.is_empty() {
+            anyhow::bail!("Webhook secret must not be empty");
+        }
+        if let Some(url) = &default_url {
+            validate_callback_url(url, &allowed_hosts)?;
+        }
+
+        let client = RedisClient::open(redis_url)?;
+        let conn = client.get_multiplexed_async_connection().await?;
+        let allowed_hosts = Arc::new(allowed_hosts);
+
+        Ok(Self {
+            redis: Arc::new(Mutex::new(conn)),
+            // Redirects could point anywhere, including internal addresses
+            client: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .redirect(reqwest::redirect::Policy::none())
+                .dns_resolver(Arc::new(PublicResolver { allowed_hosts: allowed_hosts.clone() }))
+                .build()?,
+            claim: redis::Script::new(CLAIM_SCRIPT),
+            secret: secret.as_bytes().to_vec(),
+            default_url,
+            allowed_hosts,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            retention: DEFAULT_RETENTION,
+        })
+    }
+
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    pub fn with_retention(mut self, retention: Duration) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// Check a per-request callback URL against this dispatcher's allow-list
+    pub fn validate_url(&self, url: &str) -> Result<(), anyhow::Error> {
+        validate_callback_url(url, &self.allowed_hosts)
+    }
+
+    /// Queue a status-change event for the job's callback URL, if any
+    pub async fn notify(&self, job: &EmailJob, status: EmailStatus, reason: Option<&str>) -> Result<(), anyhow::Error> {
+        let Some(url) = job.callback_url.clone().or_else(|| self.default_url.clone()) else {
+            return Ok(());
+        };
+
+        let now = Utc::now();
+        let delivery = WebhookDelivery {
+            id: Uuid::new_v4().to_string(),
+            url,
+            event: StatusEvent {
+                id: Uuid::new_v4().to_string(),
+                job_id: job.id.clone(),
+                to: job.to.clone(),
+                template: job.template_name().to_string(),
+                status,
+                reason: reason.map(str::to_string),
+                metadata: job.metadata.clone(),
+                tags: job.tags.clone(),
+                occurred_at: now,
+            },
+            status: DeliveryStatus::Pending,
+            attempts: 0,
+            response_status: None,
+            error: None,
+            created_at: now,
+            next_attempt_at: Some(now),
+            delivered_at: None,
+        };
+
+        let mut conn = self.redis.lock().await;
+
+        let _: () = conn.hset(DELIVERIES_KEY, &delivery.id, serde_json::to_string(&delivery)?).await?;
+        let _: () = conn.zadd(SCHEDULE_KEY, &delivery.id, now.timestamp()).await?;
+        let _: () = conn.lpush(LOG_KEY, &delivery.id).await?;
+        let _: () = conn.ltrim(LOG_KEY, 0, LOG_SIZE - 1).await?;
+        let _: () = conn.sadd(job_deliveries_key(&job.id), &delivery.id).await?;
+
+        Ok(())
+    }
+
+    /// Attempt every delivery that is due; returns how many were attempted
+    pub async fn process_due(&self) -> Result<usize, anyhow::Error> {
+        self.prune().await?;
+
+        let now = Utc::now().timestamp();
+        let due: Vec<String> = {
+            let mut conn = self.redis.lock().await;
+            conn.zrangebyscore_limit(SCHEDULE_KEY, "-inf", now, 0, BATCH_SIZE).await?
+        };
+
+        let mut attempted = 0;
+        for id in due {
+            // Lease the delivery rather than removing it, so concurrent workers
+            // don't send it twice and a crash doesn't lose it
+            // Taken per claim: earlier sends in the batch can outlast a lease
+            let now = Utc::now().timestamp();
+            let delivery = {
+                let mut conn = self.redis.lock().await;
+                let claimed: u32 = self.claim
+                    .key(SCHEDULE_KEY)
+                    .arg(&id)
+                    .arg(now)
+                    .arg(now + LEASE_SECS)
+                    .invoke_async(&mut *conn)
+                    .await?;
+                if claimed == 0 {
+                    continue;
+                }
+                let json: Option<String> = conn.hget(DELIVERIES_KEY, &id).await?;
+                match json {
+                    Some(json) => serde_json::from_str::<WebhookDelivery>(&json)?,
+                    None => {
+                        let _: () = conn.zrem(SCHEDULE_KEY, &id).await?;
+                        continue;
+                    }
+                }
+            };
+
+            self.attempt(delivery).await?;
+            attempted += 1;
+        }
+
+        Ok(attempted)
+    }
+
+    async fn attempt(&self, mut delivery: WebhookDelivery) -> Result<(), anyhow::Error> {
+        // IP literals never reach the resolver, so check the URL itself too
+        // (the allow-list may also have changed since the delivery was queued)
+        if let Err(e) = self.validate_url(&delivery.url) {
+            delivery.attempts += 1;
+            delivery.status = DeliveryStatus::Failed;
+            delivery.next_attempt_at = None;
+            log::error!("❌ Webhook {} to {} refused: {}", delivery.id, delivery.url, e);
+            delivery.error = Some(e.to_string());
+            return self.finish(&delivery).await;
+        }
+
+        let body = serde_json::to_string(&delivery.event)?;
+        let timestamp = Utc::now().timestamp().to_string();
+
+        let result = self.client
+            .post(&delivery.url)
+            .header("Content-Type", "application/json")
+            .header("X-Mailer-Event", delivery.event.status.as_str())
+            .header("X-Mailer-Delivery", &delivery.id)
+            .header("X-Mailer-Timestamp", &timestamp)
+            .header("X-Mailer-Signature", format!("sha256={}", sign(&self.secret, &timestamp, &body)))
+            .body(body)
+            .send()
+            .await;
+
+        delivery.attempts += 1;
+        let now = Utc::now();
+
+        let error = match result {
+            Ok(response) => {
+                delivery.response_status = Some(response.status().as_u16());
+                if response.status().is_success() {
+                    None
+                } else {
+                    Some(format!("Receiver responded with {}", response.status()))
+                }
+            }
+            // Includes addresses refused by `PublicResolver`
+            Err(e) => Some(format!("{:#}", anyhow::Error::from(e))),
+        };
+
+        match error {
+            None => {
+                delivery.status = DeliveryStatus::Delivered;
+                delivery.delivered_at = Some(now);
+                delivery.next_attempt_at = None;
+                delivery.error = None;
+                log::info!("🪝 Delivered {} webhook for job {}", delivery.event.status.as_str(), delivery.event.job_id);
+                self.finish(&delivery).await
+            }
+            Some(error) if delivery.attempts >= self.max_attempts => {
+                delivery.status = DeliveryStatus::Failed;
+                delivery.next_attempt_at = None;
+                log::error!("❌ Webhook {} to {} permanently failed: {}", delivery.id, delivery.url, error);
+                delivery.error = Some(error);
+                self.finish(&delivery).await
+            }
+            Some(error) => {
+                let next = now + chrono::Duration::seconds(backoff_secs(delivery.attempts));
+                delivery.next_attempt_at = Some(next);
+                log::warn!("⚠️ Webhook {} failed, retrying ({}/{}): {}", delivery.id, delivery.attempts, self.max_attempts, error);
+                delivery.error = Some(error);
+
+                // Replaces the lease with the next attempt time
+                let mut conn = self.redis.lock().await;
+                let _: () = conn.hset(DELIVERIES_KEY, &delivery.id, serde_json::to_string(&delivery)?).await?;
+                let _: () = conn.zadd(SCHEDULE_KEY, &delivery.id, next.timestamp()).await?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Save a delivered or failed delivery and release its lease
+    async fn finish(&self, delivery: &WebhookDelivery) -> Result<(), anyhow::Error> {
+        let mut conn = self.redis.lock().await;
+        let _: () = conn.hset(DELIVERIES_KEY, &delivery.id, serde_json::to_string(delivery)?).await?;
+        let _: () = conn.zadd(FINISHED_KEY, &delivery.id, Utc::now().timestamp()).await?;
+        let _: () = conn.zrem(SCHEDULE_KEY, &delivery.id).await?;
+        Ok(())
+    }
+
+    /// Delete deliveries that finished longer than the retention period ago
+    async fn prune(&self) -> Result<(), anyhow::Error> {
+        let cutoff = Utc::now().timestamp() - self.retention.as_secs() as i64;
+        let mut conn = self.redis.lock().await;
+
+        let ids: Vec<String> = conn.zrangebyscore_limit(FINISHED_KEY, "-inf", cutoff, 0, PRUNE_BATCH).await?;
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        for delivery in load_deliveries(&mut conn, &ids).await? {
+            let _: () = conn.srem(job_deliveries_key(&delivery.event.job_id), &delivery.id).await?;
+        }
+        let _: () = conn.hdel(DELIVERIES_KEY, &ids).await?;
+        let _: () = conn.zrem(FINISHED_KEY, &ids).await?;
+        // Pruned IDs still in the log are skipped by `load_deliveries`
+
+        log::debug!("🧹 Pruned {} finished webhook deliveries", ids.len());
+        Ok(())
+    }
+
+    /// Most recent deliveries, newest first
+    pub async fn recent(&self, limit: usize, status: Option<DeliveryStatus>) -> Result<Vec<WebhookDelivery>, anyhow::Error> {
+        let mut conn = self.redis.lock().await;
+
+        let ids: Vec<String> = conn.lrange(LOG_KEY, 0, LOG_SIZE - 1).await?;
+        let deliveries = load_deliveries(&mut conn, &ids).await?;
+
+        Ok(deliveries
+            .into_iter()
+            .filter(|d| status.as_ref().is_none_or(|s| d.status == *s))
+            .take(limit)
+            .collect())
+    }
+
+    /// All deliveries for a job, oldest first
+    pub async fn for_job(&self, job_id: &str) -> Result<Vec<WebhookDelivery>, anyhow::Error> {
+        let mut conn = self.redis.lock().await;
+
+        let ids: Vec<String> = conn.smembers(job_deliveries_key(job_id)).await?;
+        let mut deliveries = load_deliveries(&mut conn, &ids).await?;
+        deliveries.sort_by_key(|d| d.created_at);
+
+        Ok(deliveries)
+    }
+
+}
+
+/// Hex HMAC-SHA256 of `"{timestamp}.{body}"`, sent as `X-Mailer-Signature`
+fn sign(secret: &[u8], timestamp: &str, body: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+async fn load_deliveries(
+    conn: &mut redis::aio::MultiplexedConnection,
+    ids: &[String],
+) -> Result<Vec<WebhookDelivery>, anyhow::Error> {
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // Explicit HMGET: `hget` sends HGET for a single ID, which doesn't decode as a list
+    let jsons: Vec<Option<String>> = redis::cmd("HMGET")
+        .arg(DELIVERIES_KEY)
+        .arg(ids)
+        .query_async(conn)
+        .await?;
+    Ok(jsons
+        .into_iter()
+        .flatten()
+        .filter_map(|json| serde_json::from_str(&json).ok())
+        .collect())
+}
+
+/// Callback URLs must be absolute http(s) URLs that don't point at internal
+/// addresses (loopback, private, link-local, ...), unless the host is in
+/// `allowed_hosts`
+///
+/// Host names are checked again when they are resolved for delivery, see
+/// `PublicResolver`.
+pub fn validate_callback_url(url: &str, allowed_hosts: &[String]) -> Result<(), anyhow::Error> {
+    let parsed = url::Url::parse(url).map_err(|e| anyhow::anyhow!("Invalid callback URL '{}': {}", url, e))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        anyhow::bail!("Callback URL must be http(s): {}", url);
+    }
+    let Some(host) = parsed.host() else {
+        anyhow::bail!("Callback URL has no host: {}", url);
+    };
+    if is_allowed_host(allowed_hosts, &host.to_string()) {
+        return Ok(());
+    }
+
+    let internal = match host {
+        url::Host::Ipv4(ip) => !is_public(IpAddr::V4(ip)),
+        url::Host::Ipv6(ip) => !is_public(IpAddr::V6(ip)),
+        url::Host::Domain(domain) => {
+            let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+            domain == "localhost" || domain.ends_with(".localhost")
+        }
+    };
+    if internal {
+        anyhow::bail!("Callback URL must not point at a loopback, private or link-local address: {}", url);
+    }
+    Ok(())
+}
+
+fn is_allowed_host(allowed_hosts: &[String], host: &str) -> bool {
+    let host = host.trim_end_matches('.');
+    allowed_hosts.iter().any(|allowed| allowed.eq_ignore_ascii_case(host))
+}
+
+/// Whether an address is reachable on the public internet
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || a == 0
+                // Carrier-grade NAT, 100.64.0.0/10
+                || (a == 100 && (b & 0xc0) == 64))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(v4) => is_public(IpAddr::V4(v4)),
+            None => !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_multicast()
+                || ip.is_unique_local()
+                || ip.is_unicast_link_local()),
+        },
+    }
+}
+
+/// Resolves callback hosts, dropping internal addresses unless the host is
+/// allow-listed
+///
+/// Checking at connection time also covers names that only start resolving
+/// to internal addresses after the callback URL was accepted.
+struct PublicResolver {
+    allowed_hosts: Arc<Vec<String>>,
+}
+
+impl reqwest::dns::Resolve for PublicResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let host = name.as_str().to_string();
+        let allowed = is_allowed_host(&self.allowed_hosts, &host);
+
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|addr| allowed || is_public(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{} does not resolve to a public address", host).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+/// 10s, 20s, 40s, ... capped at an hour
+fn backoff_secs(attempts: u32) -> i64 {
+    BASE_BACKOFF_SECS
+        .saturating_mul(1i64 << attempts.saturating_sub(1).min(20))
+        .min(MAX_BACKOFF_SECS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign() {
+        // HMAC-SHA256("secret", "1700000000.{\"a\":1}")
+        assert_eq!(
+            sign(b"secret", "1700000000", r#"{"a":1}"#),
+            "49f24e537407743fa4a0242bb63b94b9a47ee99cbbe071ccd8a22550ae411686"
+        );
+        assert_ne!(sign(b"secret", "1700000001", r#"{"a":1}"#), sign(b"secret", "1700000000", r#"{"a":1}"#));
+        assert_ne!(sign(b"other", "1700000000", r#"{"a":1}"#), sign(b"secret", "1700000000", r#"{"a":1}"#));
+    }
+
+    #[test]
+    fn test_backoff_secs() {
+        assert_eq!(backoff_secs(0), 10);
+        assert_eq!(backoff_secs(1), 10);
+        assert_eq!(backoff_secs(2), 20);
+        assert_eq!(backoff_secs(3), 40);
+        assert_eq!(backoff_secs(9), 2560);
+        assert_eq!(backoff_secs(10), MAX_BACKOFF_SECS);
+        assert_eq!(backoff_secs(u32::MAX), MAX_BACKOFF_SECS);
+    }
+
+    #[test]
+    fn test_is_public() {
+        for ip in ["93.184.216.34", "2606:4700::1111", "::ffff:8.8.8.8"] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "0.0.0.0",
+            "100.64.0.1", "255.255.255.255", "::1", "::", "fd00::1", "fe80::1", "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn test_validate_callback_url() {
+        assert!(validate_callback_url("https://hooks.example.com/mailer", &[]).is_ok());
+        assert!(validate_callback_url("ftp://hooks.example.com", &[]).is_err());
+        assert!(validate_callback_url("not a url", &[]).is_err());
+
+        for url in [
+            "http://127.0.0.1:8080/",
+            "http://169.254.169.254/latest/meta-data",
+            "http://[::1]/",
+            "http://10.0.0.5/",
+            "http://localhost:3000/",
+            "http://api.localhost./",
+            "http://2130706433/",
+        ] {
+            assert!(validate_callback_url(url, &[]).is_err(), "{}", url);
+        }
+
+        let allowed = vec!["server".to_string(), "10.0.0.5".to_string()];
+        assert!(validate_callback_url("http://server:8080/webhooks", &allowed).is_ok());
+        assert!(validate_callback_url("http://SERVER./webhooks", &allowed).is_ok());
+        assert!(validate_callback_url("http://10.0.0.5/", &allowed).is_ok());
+        assert!(validate_callback_url("http://10.0.0.6/", &allowed).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resolver_drops_internal_addresses() {
+        use reqwest::dns::Resolve;
+        use std::str::FromStr;
+
+        let resolver = PublicResolver { allowed_hosts: Arc::new(vec!["localhost".to_string()]) };
+        let addrs: Vec<SocketAddr> = resolver.resolve(reqwest::dns::Name::from_str("localhost").unwrap()).await.unwrap().collect();
+        assert!(addrs.iter().all(|a| a.ip().is_loopback()) && !addrs.is_empty());
+
+        let resolver = PublicResolver { allowed_hosts: Arc::new(Vec::new()) };
+        assert!(resolver.resolve(reqwest::dns::Name::from_str("localhost").unwrap()).await.is_err());
+    }
+}